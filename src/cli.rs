@@ -0,0 +1,299 @@
+use clap::{Args, Parser, Subcommand};
+use domain_hunter::util::db::export::{ExportCompression, ExportFormat};
+use domain_hunter::util::db::import::json::JsonFormat;
+use domain_hunter::util::db::import::object_store::ObjectFormat;
+use domain_hunter::util::db::search::SearchMode;
+use domain_hunter::util::db::store::StoreKind;
+use domain_hunter::web_driver::sink::SinkSpec;
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about = "Find, filter and store expired domains")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Crawl a source and store the results (the default)
+    Crawl {
+        /// Source to crawl, e.g. `expired-domains.co` (defaults to CRAWL_SOURCE)
+        #[arg(long)]
+        source: Option<String>,
+        /// Where to write domains: db, lake, stdout or file:<path>; repeatable (defaults to CRAWL_SINKS, then DB_TYPE)
+        #[arg(long = "sink")]
+        sinks: Vec<SinkSpec>,
+        /// Write everything once the crawl finishes instead of page by page
+        #[arg(long)]
+        at_end: bool,
+        /// Stop after this many seconds, keeping the pages read so far (as Ctrl-C does)
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Continue an earlier run from the page after its last checkpoint
+        #[arg(long, value_name = "RUN_ID")]
+        resume: Option<String>,
+        /// Save every page's HTML in this directory, for --replay
+        #[arg(long, value_name = "DIR")]
+        record: Option<PathBuf>,
+        /// Crawl the pages recorded in this directory, served locally, instead of the live site
+        #[arg(long, value_name = "DIR", conflicts_with = "record")]
+        replay: Option<PathBuf>,
+    },
+    /// Copy every domain from one store into another and verify the copy
+    MigrateStore {
+        #[arg(long, value_enum)]
+        from: StoreKind,
+        #[arg(long, value_enum)]
+        to: StoreKind,
+        /// Source path or URL (defaults to DUCKDB_PATH / SQLITE_PATH / POSTGRES_URL)
+        #[arg(long)]
+        from_path: Option<String>,
+        /// Target path or URL (defaults to DUCKDB_PATH / SQLITE_PATH / POSTGRES_URL)
+        #[arg(long)]
+        to_path: Option<String>,
+    },
+    /// Export domains from the DuckDB store, with a manifest of what was written
+    Export {
+        /// Local directory or object store prefix, e.g. `./exports` or `s3://bucket/exports`; Azure (`az://`) is read-only to DuckDB and rejected
+        target: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Parquet)]
+        format: ExportFormat,
+        /// Defaults to zstd for Parquet and none otherwise
+        #[arg(long, value_enum)]
+        compression: Option<ExportCompression>,
+        /// File name template with {table}, {date}, {timestamp} and {ext}
+        #[arg(long, default_value = "{table}-{timestamp}.{ext}")]
+        name: String,
+        /// Only write domains changed or deleted since the last incremental export to this target with the same filter
+        #[arg(long)]
+        incremental: bool,
+        /// With --incremental, leave changes from the last SECS seconds for the next export, so writes still in progress are not missed
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        skew: u64,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Create, verify or upgrade the DuckDB store
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+        /// Database file (defaults to DUCKDB_PATH)
+        #[arg(long, global = true)]
+        path: Option<PathBuf>,
+    },
+    /// Snapshot the DuckDB store into the backup directory, keeping the newest generations
+    Backup {
+        /// Database to back up (defaults to DUCKDB_PATH)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Backup directory (defaults to DUCKDB_BACKUP_DIRECTORY)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Generations to keep (defaults to DUCKDB_BACKUP_GENERATIONS)
+        #[arg(long)]
+        keep: Option<usize>,
+    },
+    /// Verify a backup and restore it over the DuckDB store
+    Restore {
+        /// Backup file (defaults to the newest in the backup directory)
+        backup: Option<PathBuf>,
+        /// Database to replace (defaults to DUCKDB_PATH)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Backup directory (defaults to DUCKDB_BACKUP_DIRECTORY)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Overwrite the database even if it has newer changes than the backup
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run SQL over the crawl lake, available as the table `lake`, and print the result as CSV
+    QueryLake {
+        /// e.g. `SELECT source, date, count(*) FROM lake GROUP BY ALL`
+        sql: String,
+        /// Lake root (defaults to LAKE_PATH)
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Search domain names in the DuckDB store by keyword, similarity or regex, best match first
+    Search {
+        query: String,
+        #[arg(long, value_enum, default_value_t = SearchMode::Keyword)]
+        mode: SearchMode,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Drop hits scoring lower (scores run from 0 to 1; fuzzy defaults to 0.5)
+        #[arg(long)]
+        min_score: Option<f64>,
+        /// Rebuild the full-text index keyword searches rank with first (needs the fts extension)
+        #[arg(long)]
+        reindex: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Import domains into the DuckDB store (DUCKDB_PATH)
+    Import {
+        #[command(subcommand)]
+        source: ImportCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Create a new database at the latest schema
+    Create,
+    /// Check the schema version, tables and constraints without changing anything
+    Verify,
+    /// Apply pending migrations in place
+    Upgrade,
+}
+
+#[derive(Subcommand)]
+pub enum ImportCommand {
+    /// A CSV file with a header row
+    Csv {
+        path: PathBuf,
+        /// Map a domain field to a source column, e.g. `--map name=Domain`
+        #[arg(long = "map", value_name = "FIELD=COLUMN")]
+        mappings: Vec<String>,
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+        /// Where rejected rows are written (defaults to `<PATH>.rejects.csv`)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
+    /// A JSON array or newline-delimited JSON file of records
+    Json {
+        path: PathBuf,
+        /// Map a domain field to a dotted path, e.g. `--map backlinks=metrics.backlinks`
+        #[arg(long = "map", value_name = "FIELD=PATH")]
+        mappings: Vec<String>,
+        #[arg(long, value_enum, default_value_t = JsonFormat::Auto)]
+        format: JsonFormat,
+        /// Where rejected records are written (defaults to `<PATH>.rejects.csv`)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
+    /// Parquet files: a file, a glob, or a directory of Hive partitions
+    Parquet {
+        /// e.g. `dump.parquet`, `'dumps/*.parquet'` or `lake/`
+        source: String,
+        /// Map a domain field to a source column, e.g. `--map name=domain`
+        #[arg(long = "map", value_name = "FIELD=COLUMN")]
+        mappings: Vec<String>,
+        /// Read `key=value` directory names as columns (always on for directories)
+        #[arg(long)]
+        hive: bool,
+        /// Where rejected rows are written (defaults to `<SOURCE>.rejects.csv`)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
+    /// The crawl lake, keeping the newest row for each domain
+    Lake {
+        /// Lake root (defaults to LAKE_PATH)
+        root: Option<PathBuf>,
+        /// Only this source partition
+        #[arg(long)]
+        source: Option<String>,
+        /// Only partitions dated on or after this day, e.g. `2024-05-01`
+        #[arg(long)]
+        since: Option<NaiveDate>,
+        /// Where rejected rows are written (defaults to `<ROOT>/lake.rejects.csv`)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
+    /// An Iceberg table, at its latest or a given snapshot
+    Iceberg {
+        /// Table root, holding `metadata/`
+        path: PathBuf,
+        /// Snapshot id to read instead of the latest
+        #[arg(long)]
+        snapshot: Option<u64>,
+        #[command(flatten)]
+        lakehouse: LakehouseArgs,
+    },
+    /// A Delta Lake table, at its latest or a given version
+    Delta {
+        /// Table root, holding `_delta_log/`
+        path: PathBuf,
+        /// Version to read instead of the latest
+        #[arg(long)]
+        version: Option<u64>,
+        #[command(flatten)]
+        lakehouse: LakehouseArgs,
+    },
+    /// Objects in S3, R2 or Azure Blob Storage (credentials from the environment)
+    ObjectStore {
+        /// `s3://`, `r2://`, `az://` or `azure://` URL, optionally a glob
+        url: String,
+        /// Object format (guessed from the URL's extension by default)
+        #[arg(long, value_enum)]
+        format: Option<ObjectFormat>,
+        /// Map a domain field to a source column, e.g. `--map name=domain`
+        #[arg(long = "map", value_name = "FIELD=COLUMN")]
+        mappings: Vec<String>,
+        /// Where rejected rows are written (defaults to `s3.rejects.csv`, `r2.rejects.csv` or `azure.rejects.csv`)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
+    /// A table or query in a PostgreSQL database (POSTGRES_URL)
+    Postgres(AttachArgs),
+    /// A table or query in a MySQL database (MYSQL_URL)
+    Mysql(AttachArgs),
+}
+
+#[derive(Args)]
+pub struct AttachArgs {
+    /// Connection string or URI (defaults to POSTGRES_URL / MYSQL_URL)
+    #[arg(long)]
+    pub connection: Option<String>,
+    /// Table to import, optionally schema-qualified
+    #[arg(long, required_unless_present = "query", conflicts_with = "query")]
+    pub table: Option<String>,
+    /// Query to run on the source server instead of reading a table
+    #[arg(long)]
+    pub query: Option<String>,
+    /// Map a domain field to a source column, e.g. `--map name=domain`
+    #[arg(long = "map", value_name = "FIELD=COLUMN")]
+    pub mappings: Vec<String>,
+    /// Where rejected rows are written (defaults to `postgres.rejects.csv` / `mysql.rejects.csv`)
+    #[arg(long)]
+    pub rejects: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct LakehouseArgs {
+    /// Read the whole snapshot, not just what changed since the last import
+    #[arg(long)]
+    pub full: bool,
+    /// Map a domain field to a source column, e.g. `--map name=domain`
+    #[arg(long = "map", value_name = "FIELD=COLUMN")]
+    pub mappings: Vec<String>,
+    /// Where rejected rows are written (defaults to `<PATH>.rejects.csv`)
+    #[arg(long)]
+    pub rejects: Option<PathBuf>,
+}
+
+/// Which domains to select; every option given narrows the selection.
+#[derive(Args)]
+pub struct FilterArgs {
+    #[arg(long)]
+    pub available: Option<bool>,
+    #[arg(long)]
+    pub valid: Option<bool>,
+    #[arg(long)]
+    pub censored: Option<bool>,
+    /// Keep only these TLDs, e.g. `--tld com --tld net`
+    #[arg(long = "tld")]
+    pub tlds: Vec<String>,
+    #[arg(long)]
+    pub min_page_rank: Option<f64>,
+    /// SQL LIKE pattern for the name, e.g. `%shop%`
+    #[arg(long)]
+    pub name_like: Option<String>,
+    /// Any further SQL predicate on the domains table
+    #[arg(long = "where")]
+    pub sql: Option<String>,
+}
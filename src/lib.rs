@@ -0,0 +1,2 @@
+pub mod util;
+pub mod web_driver;
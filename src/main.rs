@@ -1,12 +1,340 @@
-mod web_driver;
-mod util;
+mod cli;
 
-use crate::web_driver::expired_domains::*;
+use clap::Parser;
+use cli::{AttachArgs, Cli, Command, DbCommand, FilterArgs, ImportCommand, LakehouseArgs};
+use domain_hunter::util::db::actor::DbHandle;
+use domain_hunter::util::db::backup::{self, latest_backup, BackupConfig};
+use domain_hunter::util::db::checkpoint::{self, Checkpoints, RunStatus};
+use domain_hunter::util::db::duck::{db_import, DuckDbImportSource, DuckStore};
+use domain_hunter::util::db::import::attach::{AttachImport, AttachKind, AttachSelect};
+use domain_hunter::util::db::import::csv::CsvImport;
+use domain_hunter::util::db::import::json::JsonImport;
+use domain_hunter::util::db::import::lake::LakeImport;
+use domain_hunter::util::db::import::lakehouse::{LakeFormat, LakehouseImport};
+use domain_hunter::util::db::import::object_store::ObjectStoreImport;
+use domain_hunter::util::db::import::parquet::ParquetImport;
+use domain_hunter::util::db::import::ColumnMapping;
+use domain_hunter::util::db::export::{export_domains, DomainExport, DomainFilter};
+use domain_hunter::util::db::lake::{self, register_lake};
+use domain_hunter::util::db::manager::DatabaseManager;
+use domain_hunter::util::db::object_store::ObjectStore;
+use domain_hunter::util::db::search::{build_search_index, search_domains, DomainSearch};
+use domain_hunter::util::db::store::{self, open_store, StoreKind};
+use domain_hunter::web_driver::crawl::{cancel_on_interrupt, crawl, replay as replay_crawl, CancellationToken};
+use domain_hunter::web_driver::sink::{drain, fallback_dir, SinkError, SinkReport, SinkSpec};
+use domain_hunter::web_driver::source::{default_source_name, source_by_name};
+use futures_util::StreamExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
 // use util::bad_words::*;
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // let bad_words = get_bad_words(BadWordSource::File).unwrap();
-    println!("{:?}", basically_selenium(CrawlTarget::ExpiredDomainsDotCom).await.unwrap());
+    match Cli::parse().command.unwrap_or(Command::Crawl { source: None, sinks: Vec::new(), at_end: false, timeout: None, resume: None, record: None, replay: None }) {
+        Command::Crawl { source, sinks, at_end, timeout, resume, record, replay } => {
+            let source = source_by_name(&source.unwrap_or_else(default_source_name))?;
+            let name = source.metadata().name;
+            let specs = match sinks.is_empty() {
+                true => SinkSpec::from_env()?,
+                false => sinks,
+            };
+            // One worker owns the database for the whole crawl: it records
+            // the run's checkpoints and serves the db sink, if there is one.
+            // Only the db sink and resuming need it, so a crawl to other
+            // sinks goes on without checkpoints if it cannot be opened, e.g.
+            // while another process holds it.
+            let db_path = StoreKind::DuckDb.default_path();
+            let db = match DuckStore::open(&db_path).map_err(Box::<dyn std::error::Error>::from).and_then(|store| Ok(DbHandle::spawn(store, 4)?)) {
+                Ok(db) => Some(db),
+                Err(e) if resume.is_none() && !specs.contains(&SinkSpec::Db) => {
+                    eprintln!("Crawling without checkpoints, so the run cannot be resumed: cannot open {db_path}: {e}");
+                    None
+                },
+                Err(e) => return Err(e),
+            };
+            let resuming = resume.is_some();
+            let run = match &db {
+                Some(db) => {
+                    db.call(move |store| match resume {
+                        Some(id) => checkpoint::resume_run(store.connection(), &id, name),
+                        None => checkpoint::start_run(store.connection(), name),
+                    })
+                    .await??
+                },
+                None => checkpoint::new_run(name),
+            };
+            match (resuming, db.is_some()) {
+                (true, _) => eprintln!("Resuming run {} of {name} at page {}, after {} domains", run.id, run.next_page, run.domains),
+                (false, true) => eprintln!("Crawl run {} of {name}; continue it with --resume {}", run.id, run.id),
+                (false, false) => eprintln!("Crawl run {} of {name}", run.id),
+            }
+            let sinks = specs.iter().map(|spec| Ok((spec.to_string(), spec.open(name, db.as_ref())?))).collect::<Result<Vec<_>, SinkError>>()?;
+            let checkpoints = db.map(|db| Checkpoints::new(db, &run.id));
+            let cancel = CancellationToken::new();
+            cancel_on_interrupt(cancel.clone(), timeout.map(Duration::from_secs));
 
+            // Scrape and store side by side: the sinks get each page as it
+            // is read, and a sink failing does not stop the crawl.
+            let (pages, received) = mpsc::channel(16);
+            let fallback = fallback_dir().join(format!("{name}-{}.ndjson", run.id));
+            let storing = tokio::spawn(drain(received, sinks, at_end, checkpoints.clone(), Some(fallback)));
+            let mut crawled = match replay {
+                Some(dir) => replay_crawl(source, dir, run.next_page, cancel.clone()).await?,
+                None => crawl(source, run.next_page, record, cancel.clone()),
+            };
+            let (mut count, mut failure) = (0, None);
+            while let Some(page) = crawled.next().await {
+                match page {
+                    Ok(page) => {
+                        count += page.domains.len();
+                        pages.send(page).await.ok();
+                    },
+                    Err(e) => failure = failure.or(Some(e)),
+                }
+            }
+            drop(pages);
+
+            let drained = storing.await?;
+            let mut failed = 0;
+            for report in &drained.sinks {
+                match &report.error {
+                    None => eprintln!("Wrote {} domains to {}", report.written, report.sink),
+                    Some(e) => {
+                        failed += 1;
+                        eprintln!("Wrote {} domains to {} before it failed: {e}", report.written, report.sink);
+                    },
+                }
+            }
+            match &drained.fallback {
+                Some(SinkReport { sink, written, error: None }) => eprintln!("Wrote the {written} domains the failed sinks missed to {sink}"),
+                Some(SinkReport { sink, written, error: Some(e) }) => eprintln!("Wrote {written} domains the failed sinks missed to {sink} before it failed too: {e}"),
+                None => {},
+            }
+            match cancel.is_cancelled() {
+                true => eprintln!("Stopped early after {count} domains from {name}"),
+                false => eprintln!("Crawled {count} domains from {name}"),
+            }
+            if let Some(e) = &drained.checkpoint_error {
+                eprintln!("Stopped checkpointing run {} after it failed: {e}", run.id);
+            }
+            let status = match (&failure, failed, cancel.is_cancelled()) {
+                (None, 0, false) => RunStatus::Complete,
+                (None, 0, true) => RunStatus::Stopped,
+                _ => RunStatus::Failed,
+            };
+            if let Some(checkpoints) = checkpoints {
+                checkpoints.finish(status).await?;
+                if status != RunStatus::Complete {
+                    match drained.checkpointed {
+                        Some(page) => eprintln!("Run {} is checkpointed up to page {page}; continue it with --resume {}", run.id, run.id),
+                        None => eprintln!("Run {} has no new checkpoints; --resume {} starts again at page {}", run.id, run.id, run.next_page),
+                    }
+                }
+            }
+            if let Some(e) = failure {
+                return Err(e.into());
+            }
+            if failed > 0 {
+                return Err(format!("{failed} of {} sinks failed", drained.sinks.len()).into());
+            }
+        },
+        Command::MigrateStore { from, to, from_path, to_path } => {
+            let from_path = from_path.unwrap_or_else(|| from.default_path());
+            let to_path = to_path.unwrap_or_else(|| to.default_path());
+            if from_path == to_path {
+                return Err("refusing to migrate a store onto itself".into());
+            }
+            // The store clients block, so keep them off the async runtime.
+            let (source, target) = (from_path.clone(), to_path.clone());
+            let copied = tokio::task::spawn_blocking(move || {
+                let source = open_store(from, &source)?;
+                let mut target = open_store(to, &target)?;
+                store::migrate_store(source.as_ref(), target.as_mut())
+            }).await??;
+            println!("Copied {copied} domains from {from_path} to {to_path}");
+        },
+        Command::Export { target, format, compression, name, incremental, skew, filter } => {
+            let export = DomainExport {
+                compression,
+                name_template: name,
+                filter: domain_filter(filter),
+                incremental,
+                skew: Duration::from_secs(skew),
+                ..DomainExport::new(target, format)
+            };
+            let mut store = DuckStore::open(&StoreKind::DuckDb.default_path())?;
+            let manifest = export_domains(store.connection(), &export)?;
+            println!("{}", serde_json::to_string_pretty(&manifest.to_json())?);
+        },
+        Command::Db { action, path } => {
+            let manager = path.map_or_else(DatabaseManager::from_env, DatabaseManager::new);
+            match action {
+                DbCommand::Create => {
+                    manager.create()?;
+                    println!("Created {}", manager.path().display());
+                },
+                DbCommand::Verify => {
+                    let report = manager.verify()?;
+                    println!("{}: schema v{} of v{}", manager.path().display(), report.version, report.latest);
+                    for problem in &report.problems {
+                        println!("  {problem}");
+                    }
+                    if report.version < report.latest || !report.problems.is_empty() {
+                        return Err("database does not match the latest schema".into());
+                    }
+                },
+                DbCommand::Upgrade => {
+                    let (from, to) = manager.upgrade()?;
+                    println!("Upgraded {} from schema v{from} to v{to}", manager.path().display());
+                },
+            }
+        },
+        Command::Backup { db, dir, keep } => {
+            let db = db.unwrap_or_else(|| StoreKind::DuckDb.default_path().into());
+            let mut config = BackupConfig::from_env();
+            config.directory = dir.unwrap_or(config.directory);
+            config.generations = keep.unwrap_or(config.generations);
+            println!("Backed up {} to {}", db.display(), backup::backup(&db, &config)?.display());
+        },
+        Command::Restore { backup, db, dir, force } => {
+            let db = db.unwrap_or_else(|| StoreKind::DuckDb.default_path().into());
+            let backup = match backup {
+                Some(backup) => backup,
+                None => latest_backup(&db, &dir.unwrap_or(BackupConfig::from_env().directory))?,
+            };
+            let check = backup::restore(&backup, &db, force)?;
+            println!(
+                "Restored {} ({} tables, {} rows, schema v{}) to {}",
+                backup.display(),
+                check.tables,
+                check.rows,
+                check.schema_version,
+                db.display()
+            );
+        },
+        Command::QueryLake { sql, root } => {
+            let conn = duckdb::Connection::open_in_memory()?;
+            register_lake(&conn, &root.unwrap_or_else(lake::default_root))?;
+            // Cast every column to text so any result prints the same way.
+            let mut stmt = conn.prepare(&format!("SELECT CAST(COLUMNS(*) AS VARCHAR) FROM ({sql})"))?;
+            let mut rows = stmt.query([])?;
+            let mut out = csv::Writer::from_writer(std::io::stdout());
+            out.write_record(rows.as_ref().map(|stmt| stmt.column_names()).unwrap_or_default())?;
+            while let Some(row) = rows.next()? {
+                let count = row.as_ref().column_count();
+                let record = (0..count).map(|i| row.get::<_, Option<String>>(i)).collect::<Result<Vec<_>, _>>()?;
+                out.write_record(record.iter().map(|value| value.as_deref().unwrap_or_default()))?;
+            }
+            out.flush()?;
+        },
+        Command::Search { query, mode, limit, min_score, reindex, filter } => {
+            let search = DomainSearch {
+                limit,
+                min_score,
+                filter: domain_filter(filter),
+                ..DomainSearch::new(query, mode)
+            };
+            let mut store = DuckStore::open(&StoreKind::DuckDb.default_path())?;
+            if reindex {
+                println!("Indexed {} domains", build_search_index(store.connection())?);
+            }
+            for hit in search_domains(store.connection(), &search)? {
+                println!("{:.3}\t{}", hit.score, hit.domain.name);
+            }
+        },
+        Command::Import { source } => {
+            let source = match source {
+                ImportCommand::Csv { path, mappings, delimiter, rejects } => DuckDbImportSource::Csv(CsvImport {
+                    mapping: ColumnMapping::parse(&mappings)?,
+                    delimiter: delimiter as u8,
+                    reject_path: rejects,
+                    ..CsvImport::new(path)
+                }),
+                ImportCommand::Json { path, mappings, format, rejects } => DuckDbImportSource::Json(JsonImport {
+                    mapping: ColumnMapping::parse(&mappings)?,
+                    format,
+                    reject_path: rejects,
+                    ..JsonImport::new(path)
+                }),
+                ImportCommand::Parquet { source, mappings, hive, rejects } => DuckDbImportSource::Parquet(ParquetImport {
+                    mapping: ColumnMapping::parse(&mappings)?,
+                    hive_partitioning: hive,
+                    reject_path: rejects,
+                    ..ParquetImport::new(source)
+                }),
+                ImportCommand::Lake { root, source, since, rejects } => DuckDbImportSource::Lake(LakeImport {
+                    source,
+                    since,
+                    reject_path: rejects,
+                    ..LakeImport::new(root.unwrap_or_else(lake::default_root))
+                }),
+                ImportCommand::Iceberg { path, snapshot, lakehouse } => {
+                    DuckDbImportSource::Iceberg(lakehouse_import(LakeFormat::Iceberg, path, snapshot, lakehouse)?)
+                },
+                ImportCommand::Delta { path, version, lakehouse } => {
+                    DuckDbImportSource::DeltaLake(lakehouse_import(LakeFormat::Delta, path, version, lakehouse)?)
+                },
+                ImportCommand::ObjectStore { url, format, mappings, rejects } => {
+                    let store = ObjectStore::from_url(&url).ok_or("expected an s3://, r2://, az:// or azure:// URL")?;
+                    let mut import = ObjectStoreImport {
+                        mapping: ColumnMapping::parse(&mappings)?,
+                        reject_path: rejects,
+                        ..ObjectStoreImport::new(store, url)
+                    };
+                    if let Some(format) = format {
+                        import.format = format;
+                    }
+                    match store {
+                        ObjectStore::S3 => DuckDbImportSource::S3(import),
+                        ObjectStore::R2 => DuckDbImportSource::CloudflareR2(import),
+                        ObjectStore::Azure => DuckDbImportSource::AzureBlob(import),
+                    }
+                },
+                ImportCommand::Postgres(args) => DuckDbImportSource::PostgreSQL(attach_import(AttachKind::Postgres, args)?),
+                ImportCommand::Mysql(args) => DuckDbImportSource::MySQL(attach_import(AttachKind::Mysql, args)?),
+            };
+            let mut store = DuckStore::open(&StoreKind::DuckDb.default_path())?;
+            let summary = db_import(store.connection(), Some(source))?;
+            println!("Imported: {summary}");
+        },
+    }
+    Ok(())
+}
+
+fn attach_import(kind: AttachKind, args: AttachArgs) -> Result<AttachImport, Box<dyn std::error::Error>> {
+    let select = match (args.table, args.query) {
+        (Some(table), _) => AttachSelect::Table(table),
+        (None, Some(query)) => AttachSelect::Query(query),
+        (None, None) => return Err("one of --table or --query is required".into()),
+    };
+    let connection = args.connection.unwrap_or_else(|| kind.default_connection());
+    Ok(AttachImport {
+        mapping: ColumnMapping::parse(&args.mappings)?,
+        reject_path: args.rejects,
+        ..AttachImport::new(kind, connection, select)
+    })
+}
+
+fn lakehouse_import(format: LakeFormat, path: PathBuf, snapshot: Option<u64>, args: LakehouseArgs) -> Result<LakehouseImport, Box<dyn std::error::Error>> {
+    Ok(LakehouseImport {
+        snapshot,
+        full: args.full,
+        mapping: ColumnMapping::parse(&args.mappings)?,
+        reject_path: args.rejects,
+        ..LakehouseImport::new(format, path)
+    })
+}
+
+fn domain_filter(args: FilterArgs) -> DomainFilter {
+    DomainFilter {
+        available: args.available,
+        valid: args.valid,
+        censored: args.censored,
+        tlds: args.tlds,
+        min_page_rank: args.min_page_rank,
+        name_like: args.name_like,
+        sql: args.sql,
+    }
 }
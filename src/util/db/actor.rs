@@ -0,0 +1,119 @@
+use crate::util::db::duck::{Domain, DuckStore};
+use crate::util::db::store::{DomainStore, StoreError};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use tokio::sync::{mpsc, oneshot};
+
+/// Work sent to the worker thread, run against the store it owns.
+type Job = Box<dyn FnOnce(&mut DuckStore) + Send>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ActorError {
+    #[error(transparent)]
+    Store(#[from] StoreError),
+    #[error("the database worker has stopped")]
+    Stopped,
+    #[error("a database job panicked: {0}")]
+    Panicked(String),
+}
+
+/// A cheap, cloneable handle to a DuckDB store owned by a dedicated worker
+/// thread. DuckDB calls block and the file allows a single writer, so async
+/// code sends its work here instead of touching a connection itself: jobs
+/// run one at a time, in the order they arrive, and each caller awaits its
+/// own reply without blocking the runtime.
+///
+/// The worker stops, closing the store, once every handle is dropped.
+#[derive(Clone)]
+pub struct DbHandle {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl DbHandle {
+    /// Start a worker that owns `store`. Up to `capacity` jobs wait in the
+    /// queue; beyond that, callers wait for room, which keeps a fast
+    /// crawler from running ahead of the database.
+    pub fn spawn(store: DuckStore, capacity: usize) -> Result<Self, ActorError> {
+        let (jobs, mut queue) = mpsc::channel::<Job>(capacity.max(1));
+        thread::Builder::new()
+            .name("duckdb-worker".to_string())
+            .spawn(move || {
+                let mut store = store;
+                while let Some(job) = queue.blocking_recv() {
+                    job(&mut store);
+                }
+            })
+            .map_err(StoreError::Io)?;
+        Ok(DbHandle { jobs })
+    }
+
+    /// Run `job` on the worker and return its result. A panicking job is
+    /// reported as [`ActorError::Panicked`] and the worker carries on.
+    pub async fn call<T, F>(&self, job: F) -> Result<T, ActorError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DuckStore) -> T + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |store| {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| job(store))).map_err(|payload| {
+                payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default()
+            });
+            // The caller may have given up waiting; that is fine.
+            let _ = reply.send(outcome);
+        });
+        self.jobs.send(job).await.map_err(|_| ActorError::Stopped)?;
+        result.await.map_err(|_| ActorError::Stopped)?.map_err(ActorError::Panicked)
+    }
+
+    pub async fn insert_domains(&self, domains: Vec<Domain>) -> Result<usize, ActorError> {
+        Ok(self.call(move |store| store.insert_domains(&domains)).await??)
+    }
+
+    pub async fn delete_domains(&self, names: Vec<String>) -> Result<usize, ActorError> {
+        Ok(self.call(move |store| store.delete_domains(&names)).await??)
+    }
+
+    pub async fn list_domains(&self) -> Result<Vec<Domain>, ActorError> {
+        Ok(self.call(|store| store.list_domains()).await??)
+    }
+
+    pub async fn count_domains(&self) -> Result<usize, ActorError> {
+        Ok(self.call(|store| store.count_domains()).await??)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_writers_share_one_worker() {
+        let db = DbHandle::spawn(DuckStore::open_in_memory().unwrap(), 4).unwrap();
+        let writers = (0..8).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let domains = (0..25).map(|j| Domain::new(&format!("writer{i}-{j}.com"), true, None)).collect();
+                db.insert_domains(domains).await.unwrap()
+            })
+        });
+        for writer in writers.collect::<Vec<_>>() {
+            assert_eq!(writer.await.unwrap(), 25);
+        }
+
+        assert_eq!(db.count_domains().await.unwrap(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_worker_survives_a_panicking_job() {
+        let db = DbHandle::spawn(DuckStore::open_in_memory().unwrap(), 1).unwrap();
+
+        let result = db.call(|_| -> usize { panic!("boom") }).await;
+        assert!(matches!(result, Err(ActorError::Panicked(message)) if message == "boom"));
+        assert_eq!(db.insert_domains(vec![Domain::new("after.com", true, None)]).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::util::db::export::sha256;
+use crate::util::db::store::{DOMAINS_TABLE, TOMBSTONES_TABLE};
+use chrono::{NaiveDateTime, Utc};
+use dotenv::dotenv;
+use duckdb::{AccessMode, Config, Connection};
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// The first schema version with change timestamps, see migration 5.
+const TIMESTAMPED_SCHEMA: u32 = 5;
+
+/// When a backup was taken, as it appears in the file name.
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%6fZ";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("export: {0}")]
+    Export(#[from] crate::util::db::export::ExportError),
+    #[error("no backups of {0} found")]
+    NoBackups(PathBuf),
+    #[error("{0} does not exist")]
+    NoDatabase(PathBuf),
+    #[error("backup {path} failed its integrity check: {reason}")]
+    Integrity { path: PathBuf, reason: String },
+    #[error("{database} has changes up to {current}, newer than the backup's {backup:?}; pass --force to overwrite it")]
+    Newer { database: PathBuf, current: NaiveDateTime, backup: Option<NaiveDateTime> },
+}
+
+/// Where backups go and how many are kept, from `DUCKDB_BACKUP_DIRECTORY`
+/// (default `./data/backups`) and `DUCKDB_BACKUP_GENERATIONS` (default 7).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupConfig {
+    pub directory: PathBuf,
+    pub generations: usize,
+}
+
+impl BackupConfig {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        BackupConfig {
+            directory: env::var("DUCKDB_BACKUP_DIRECTORY").unwrap_or("./data/backups".to_string()).into(),
+            generations: env::var("DUCKDB_BACKUP_GENERATIONS").ok().and_then(|s| s.parse().ok()).unwrap_or(7),
+        }
+    }
+}
+
+/// What [`verify_backup`] found in a database file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupCheck {
+    pub path: PathBuf,
+    pub schema_version: u32,
+    pub tables: usize,
+    pub rows: usize,
+    /// The newest insert, update or delete of a domain.
+    pub last_change: Option<NaiveDateTime>,
+}
+
+fn stem(db: &Path) -> String {
+    db.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or("duckdb".to_string())
+}
+
+fn checksum_path(backup: &Path) -> PathBuf {
+    let mut path = backup.as_os_str().to_owned();
+    path.push(".sha256");
+    path.into()
+}
+
+/// When the backup at `path` of `db` was taken, if it is one: its name must
+/// be `db`'s stem, a dash and the time, so another database whose name
+/// merely starts the same way is not mistaken for it.
+fn taken_at(db: &Path, path: &Path) -> Option<NaiveDateTime> {
+    let name = path.file_name()?.to_str()?;
+    let time = name.strip_prefix(&format!("{}-", stem(db)))?.strip_suffix(".duckdb")?;
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()
+}
+
+/// Backups of `db` in `directory`, oldest first.
+pub fn list_backups(db: &Path, directory: &Path) -> Result<Vec<PathBuf>, BackupError> {
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if let Some(taken) = taken_at(db, &path) {
+            backups.push((taken, path));
+        }
+    }
+    backups.sort();
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Snapshot `db` into the backup directory, then delete all but the newest
+/// `config.generations` backups.
+///
+/// Holding the connection keeps every other process out, and the forced
+/// checkpoint moves the WAL into the file, so a byte copy of the file is a
+/// consistent snapshot. A `<backup>.sha256` file in `sha256sum` format is
+/// written next to it. A missing `db` is an error rather than an empty
+/// backup.
+pub fn backup(db: &Path, config: &BackupConfig) -> Result<PathBuf, BackupError> {
+    if !db.is_file() {
+        return Err(BackupError::NoDatabase(db.to_path_buf()));
+    }
+    fs::create_dir_all(&config.directory)?;
+    let name = format!("{}-{}.duckdb", stem(db), Utc::now().format(BACKUP_TIME_FORMAT));
+    let path = config.directory.join(&name);
+    let partial = path.with_extension("duckdb.tmp");
+
+    let conn = Connection::open(db)?;
+    conn.execute_batch("FORCE CHECKPOINT")?;
+    fs::copy(db, &partial)?;
+    drop(conn);
+    File::open(&partial)?.sync_all()?;
+    fs::rename(&partial, &path)?;
+    fs::write(checksum_path(&path), format!("{}  {name}\n", sha256(&path)?))?;
+
+    let backups = list_backups(db, &config.directory)?;
+    for old in &backups[..backups.len().saturating_sub(config.generations.max(1))] {
+        fs::remove_file(old)?;
+        fs::remove_file(checksum_path(old)).ok();
+    }
+    Ok(path)
+}
+
+fn last_change(conn: &Connection, schema_version: u32) -> Result<Option<NaiveDateTime>, duckdb::Error> {
+    if schema_version < TIMESTAMPED_SCHEMA {
+        return Ok(None);
+    }
+    conn.query_row(
+        &format!("SELECT greatest((SELECT max(updated_at) FROM {DOMAINS_TABLE}), (SELECT max(deleted_at) FROM {TOMBSTONES_TABLE}))"),
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Inspect a database file read-only: its checksum file must match, it must
+/// have been migrated, and every table must scan cleanly, which makes
+/// DuckDB verify the checksum of every block.
+pub fn verify_backup(path: &Path) -> Result<BackupCheck, BackupError> {
+    let integrity = |reason: String| BackupError::Integrity { path: path.to_path_buf(), reason };
+    if let Ok(expected) = fs::read_to_string(checksum_path(path)) {
+        let expected = expected.split_whitespace().next().unwrap_or_default().to_string();
+        if expected != sha256(path)? {
+            return Err(integrity("checksum does not match".to_string()));
+        }
+    }
+
+    let conn = Connection::open_with_flags(path, Config::default().access_mode(AccessMode::ReadOnly)?)
+        .map_err(|e| integrity(e.to_string()))?;
+    let schema_version = conn
+        .query_row("SELECT coalesce(max(version), 0) FROM schema_migrations", [], |row| row.get::<_, i64>(0))
+        .map_err(|_| integrity("no schema_migrations table".to_string()))? as u32;
+
+    let mut stmt = conn.prepare("SELECT schema_name, table_name FROM duckdb_tables() WHERE NOT temporary")?;
+    let tables = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?.collect::<Result<Vec<_>, _>>()?;
+    let mut rows = 0;
+    for (schema, table) in &tables {
+        let scan = format!("SELECT count(*) FROM (SELECT * FROM \"{schema}\".\"{table}\")");
+        rows += conn.query_row(&scan, [], |row| row.get::<_, i64>(0)).map_err(|e| integrity(format!("{schema}.{table}: {e}")))? as usize;
+    }
+
+    Ok(BackupCheck {
+        path: path.to_path_buf(),
+        schema_version,
+        tables: tables.len(),
+        rows,
+        last_change: last_change(&conn, schema_version)?,
+    })
+}
+
+/// `path` with `suffix` added to its name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+/// Replace `db` with `backup` after verifying it. `db` is held open until
+/// the backup is in its place, which keeps every other process out of it
+/// throughout, even with `force`; without `force`, a `db` holding changes
+/// newer than anything in the backup is refused.
+///
+/// The old file and its WAL are moved aside before the backup is moved
+/// into place, and only deleted once it is, so a failure part way leaves
+/// the old database where it was.
+pub fn restore(backup: &Path, db: &Path, force: bool) -> Result<BackupCheck, BackupError> {
+    let check = verify_backup(backup)?;
+    let conn = match db.exists() {
+        true => Some(Connection::open(db)?),
+        false => None,
+    };
+    if let Some(conn) = &conn {
+        let version = conn
+            .query_row("SELECT coalesce(max(version), 0) FROM schema_migrations", [], |row| row.get::<_, i64>(0))
+            .unwrap_or(0) as u32;
+        if let (false, Some(current)) = (force, last_change(conn, version)?) {
+            if check.last_change.is_none_or(|backup| current > backup) {
+                return Err(BackupError::Newer { database: db.to_path_buf(), current, backup: check.last_change });
+            }
+        }
+    }
+
+    if let Some(parent) = db.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = with_suffix(db, ".restore.tmp");
+    fs::copy(backup, &partial)?;
+    File::open(&partial)?.sync_all()?;
+
+    // A WAL left by the old file would be replayed onto the restored one,
+    // so it goes aside with the file.
+    let wal = with_suffix(db, ".wal");
+    let aside = [(db.to_path_buf(), with_suffix(db, ".restore.old")), (wal.clone(), with_suffix(&wal, ".restore.old"))];
+    let mut moved = Vec::new();
+    let swapped = aside
+        .iter()
+        .filter(|(from, _)| from.exists())
+        .try_for_each(|(from, to)| fs::rename(from, to).map(|()| moved.push((from, to))))
+        .and_then(|()| fs::rename(&partial, db));
+    // The connection only read, so closing it writes nothing; its lock was
+    // on the old file, now moved aside.
+    drop(conn);
+    if let Err(e) = swapped {
+        for (from, to) in moved {
+            fs::rename(to, from).ok();
+        }
+        fs::remove_file(&partial).ok();
+        return Err(e.into());
+    }
+    for (_, old) in moved {
+        fs::remove_file(old)?;
+    }
+    Ok(check)
+}
+
+/// The newest backup of `db` in `directory`.
+pub fn latest_backup(db: &Path, directory: &Path) -> Result<PathBuf, BackupError> {
+    list_backups(db, directory)?.pop().ok_or_else(|| BackupError::NoBackups(db.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::{Domain, DuckStore};
+    use crate::util::db::store::DomainStore;
+
+    fn database(dir: &Path, names: &[&str]) -> PathBuf {
+        let path = dir.join("domain-hunter.duckdb");
+        let mut store = DuckStore::open(&path.display().to_string()).unwrap();
+        store.insert_domains(&names.iter().map(|name| Domain::new(name, true, None)).collect::<Vec<_>>()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_backups_rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = database(dir.path(), &["one.com"]);
+        let config = BackupConfig { directory: dir.path().join("backups"), generations: 2 };
+
+        let first = backup(&db, &config).unwrap();
+        let second = backup(&db, &config).unwrap();
+        let third = backup(&db, &config).unwrap();
+
+        assert_eq!(list_backups(&db, &config.directory).unwrap(), vec![second, third.clone()]);
+        assert!(!first.exists() && !checksum_path(&first).exists());
+        let check = verify_backup(&third).unwrap();
+        assert_eq!(check.schema_version, crate::util::db::migrations::latest_version());
+        assert!(check.rows >= 1 && check.last_change.is_some());
+    }
+
+    #[test]
+    fn test_only_timestamped_names_are_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("domain-hunter.duckdb");
+        for name in ["domain-hunter-20240501T120000000000Z.duckdb", "domain-hunter-old-20240501T120000000000Z.duckdb", "domain-hunter-copy.duckdb", "domain-hunter-20240501T110000000000Z.duckdb.tmp"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        assert_eq!(list_backups(&db, dir.path()).unwrap(), vec![dir.path().join("domain-hunter-20240501T120000000000Z.duckdb")]);
+    }
+
+    #[test]
+    fn test_corrupt_backup_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = database(dir.path(), &["one.com"]);
+        let config = BackupConfig { directory: dir.path().join("backups"), generations: 2 };
+        let path = backup(&db, &config).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        assert!(matches!(verify_backup(&path), Err(BackupError::Integrity { .. })));
+        assert!(matches!(restore(&path, &db, true), Err(BackupError::Integrity { .. })));
+    }
+
+    #[test]
+    fn test_restore_refuses_newer_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = database(dir.path(), &["one.com"]);
+        let config = BackupConfig { directory: dir.path().join("backups"), generations: 2 };
+        let path = backup(&db, &config).unwrap();
+        DuckStore::open(&db.display().to_string()).unwrap().insert_domains(&[Domain::new("two.com", true, None)]).unwrap();
+
+        assert!(matches!(restore(&path, &db, false), Err(BackupError::Newer { .. })));
+        restore(&path, &db, true).unwrap();
+        let restored = DuckStore::open(&db.display().to_string()).unwrap().list_domains().unwrap();
+        assert_eq!(restored, vec![Domain::new("one.com", true, None)]);
+        assert!(!with_suffix(&db, ".restore.old").exists());
+    }
+
+    #[test]
+    fn test_backup_of_missing_database_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("missing.duckdb");
+        let config = BackupConfig { directory: dir.path().join("backups"), generations: 2 };
+
+        assert!(matches!(backup(&db, &config), Err(BackupError::NoDatabase(_))));
+        assert!(!db.exists());
+        assert!(list_backups(&db, &config.directory).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::util::db::actor::{ActorError, DbHandle};
+use crate::util::db::store::{CRAWL_CHECKPOINTS_TABLE, CRAWL_RUNS_TABLE};
+use chrono::Utc;
+use duckdb::{params, Connection, OptionalExt};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error(transparent)]
+    Actor(#[from] ActorError),
+    #[error("no crawl run '{0}'")]
+    UnknownRun(String),
+    #[error("run '{run}' crawled {found}, not {expected}")]
+    WrongSource { run: String, expected: String, found: String },
+    #[error("run '{0}' already finished; start a new crawl instead")]
+    Finished(String),
+    #[error("unknown run status '{0}'")]
+    Status(String),
+}
+
+/// Where a crawl run got to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Running,
+    /// Read every page.
+    Complete,
+    /// Cancelled, or timed out, before the last page.
+    Stopped,
+    Failed,
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RunStatus::Running => "running",
+            RunStatus::Complete => "complete",
+            RunStatus::Stopped => "stopped",
+            RunStatus::Failed => "failed",
+        })
+    }
+}
+
+impl FromStr for RunStatus {
+    type Err = CheckpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(RunStatus::Running),
+            "complete" => Ok(RunStatus::Complete),
+            "stopped" => Ok(RunStatus::Stopped),
+            "failed" => Ok(RunStatus::Failed),
+            _ => Err(CheckpointError::Status(s.to_string())),
+        }
+    }
+}
+
+/// One crawl, and the pages it has stored so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrawlRun {
+    pub id: String,
+    pub source: String,
+    pub status: RunStatus,
+    /// The first page not yet checkpointed, where a resumed crawl starts.
+    pub next_page: usize,
+    /// Domains on the checkpointed pages.
+    pub domains: usize,
+}
+
+/// A new run of `source`, named after the time it started, without
+/// recording it; a crawl that cannot checkpoint still has a name for its
+/// files.
+pub fn new_run(source: &str) -> CrawlRun {
+    let id = Utc::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+    CrawlRun { id, source: source.to_string(), status: RunStatus::Running, next_page: 0, domains: 0 }
+}
+
+/// Record a new run of `source`, named after the time it started.
+pub fn start_run(conn: &Connection, source: &str) -> Result<CrawlRun, CheckpointError> {
+    let run = new_run(source);
+    conn.execute(
+        &format!("INSERT INTO {CRAWL_RUNS_TABLE} (run_id, source, status) VALUES (?, ?, ?)"),
+        params![run.id, source, RunStatus::Running.to_string()],
+    )?;
+    Ok(run)
+}
+
+pub fn find_run(conn: &Connection, id: &str) -> Result<Option<CrawlRun>, CheckpointError> {
+    let row = conn
+        .query_row(
+            &format!(
+                "SELECT r.source, r.status, coalesce(max(c.page) + 1, 0), coalesce(sum(c.domains), 0)
+                 FROM {CRAWL_RUNS_TABLE} r LEFT JOIN {CRAWL_CHECKPOINTS_TABLE} c USING (run_id)
+                 WHERE r.run_id = ? GROUP BY r.source, r.status"
+            ),
+            params![id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)),
+        )
+        .optional()?;
+    row.map(|(source, status, next_page, domains)| {
+        Ok(CrawlRun { id: id.to_string(), source, status: status.parse()?, next_page: next_page as usize, domains: domains as usize })
+    })
+    .transpose()
+}
+
+/// Pick `id` up again where it stopped. It must be a run of `source` that
+/// did not complete.
+pub fn resume_run(conn: &Connection, id: &str, source: &str) -> Result<CrawlRun, CheckpointError> {
+    let run = find_run(conn, id)?.ok_or_else(|| CheckpointError::UnknownRun(id.to_string()))?;
+    if run.source != source {
+        return Err(CheckpointError::WrongSource { run: run.id, expected: source.to_string(), found: run.source });
+    }
+    if run.status == RunStatus::Complete {
+        return Err(CheckpointError::Finished(run.id));
+    }
+    set_status(conn, id, RunStatus::Running)?;
+    Ok(CrawlRun { status: RunStatus::Running, ..run })
+}
+
+/// Note that `page` of run `id`, holding `domains` domains, is stored.
+pub fn record_page(conn: &Connection, id: &str, page: usize, domains: usize) -> Result<(), CheckpointError> {
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO {CRAWL_CHECKPOINTS_TABLE} (run_id, page, domains, completed_at) VALUES (?, ?, ?, now())"),
+        params![id, page as i64, domains as i64],
+    )?;
+    conn.execute(&format!("UPDATE {CRAWL_RUNS_TABLE} SET updated_at = now() WHERE run_id = ?"), params![id])?;
+    Ok(())
+}
+
+pub fn set_status(conn: &Connection, id: &str, status: RunStatus) -> Result<(), CheckpointError> {
+    match conn.execute(&format!("UPDATE {CRAWL_RUNS_TABLE} SET status = ?, updated_at = now() WHERE run_id = ?"), params![status.to_string(), id])? {
+        0 => Err(CheckpointError::UnknownRun(id.to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// The pages of one run, checkpointed through the database worker as the
+/// crawl stores them.
+#[derive(Clone)]
+pub struct Checkpoints {
+    db: DbHandle,
+    run: String,
+}
+
+impl Checkpoints {
+    pub fn new(db: DbHandle, run: &str) -> Self {
+        Checkpoints { db, run: run.to_string() }
+    }
+
+    pub fn run(&self) -> &str {
+        &self.run
+    }
+
+    pub async fn record(&self, page: usize, domains: usize) -> Result<(), CheckpointError> {
+        let run = self.run.clone();
+        self.db.call(move |store| record_page(store.connection(), &run, page, domains)).await?
+    }
+
+    pub async fn finish(&self, status: RunStatus) -> Result<(), CheckpointError> {
+        let run = self.run.clone();
+        self.db.call(move |store| set_status(store.connection(), &run, status)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+
+    #[test]
+    fn test_resume_starts_after_the_last_checkpoint() {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let conn = store.connection();
+        let run = start_run(conn, "expired-domains.co").unwrap();
+        for page in 0..3 {
+            record_page(conn, &run.id, page, 25).unwrap();
+        }
+        set_status(conn, &run.id, RunStatus::Failed).unwrap();
+
+        let resumed = resume_run(conn, &run.id, "expired-domains.co").unwrap();
+        assert_eq!((resumed.next_page, resumed.domains, resumed.status), (3, 75, RunStatus::Running));
+        assert!(matches!(resume_run(conn, &run.id, "other"), Err(CheckpointError::WrongSource { .. })));
+        assert!(matches!(resume_run(conn, "nope", "expired-domains.co"), Err(CheckpointError::UnknownRun(_))));
+
+        set_status(conn, &run.id, RunStatus::Complete).unwrap();
+        assert!(matches!(resume_run(conn, &run.id, "expired-domains.co"), Err(CheckpointError::Finished(_))));
+    }
+
+    #[test]
+    fn test_new_run_has_no_pages() {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let run = start_run(store.connection(), "expired-domains.co").unwrap();
+        assert_eq!(find_run(store.connection(), &run.id).unwrap(), Some(run));
+    }
+}
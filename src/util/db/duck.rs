@@ -1,13 +1,25 @@
 use duckdb::{params, Connection, Result};
 use duckdb::Statement;
 use duckdb::Transaction;
+use chrono::NaiveDate;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use dotenv::dotenv;
 use std::env;
 use std::path::Path;
-use std::fs;
+use crate::util::db::import::attach::{import_attached, AttachImport};
+use crate::util::db::import::csv::{import_csv, CsvImport};
+use crate::util::db::import::json::{import_json, JsonImport};
+use crate::util::db::import::lake::{import_lake, LakeImport};
+use crate::util::db::import::lakehouse::{import_lakehouse, LakehouseImport};
+use crate::util::db::import::object_store::{import_object_store, ObjectStoreImport};
+use crate::util::db::import::parquet::{import_parquet, ParquetImport};
+use crate::util::db::import::{merge_domain, ImportError, ImportSummary};
+use crate::util::db::manager::{DatabaseManager, DbError};
+use crate::util::db::migrations::{self, MIGRATIONS_TABLE};
+use crate::util::db::sqlite::SqliteStore;
+use crate::util::db::store::{DomainStore, StoreError, StoreKind, DOMAINS_TABLE, TOMBSTONES_TABLE};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Domain {
     id: Option<u64>,
     pub name: String,
@@ -15,6 +27,30 @@ pub struct Domain {
     valid: Option<bool>,
     pub page_rank: Option<f64>,
     censored: Option<bool>,
+    pub metrics: DomainMetrics,
+    /// Source fields with no matching column, kept as a JSON object.
+    pub extras: Option<serde_json::Value>,
+}
+
+/// Optional metadata about a domain gathered from crawls and imports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DomainMetrics {
+    pub backlinks: Option<i64>,
+    pub whois_created: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DomainError {
+    #[error("domain name is empty")]
+    Empty,
+    #[error("Domain name cannot contain spaces")]
+    ContainsSpace,
+    #[error("domain name is longer than 253 characters")]
+    TooLong,
+    #[error("domain name has no TLD")]
+    MissingTld,
+    #[error("invalid label '{0}'")]
+    InvalidLabel(String),
 }
 
 pub enum DuckDbType {
@@ -24,17 +60,18 @@ pub enum DuckDbType {
 }
 
 pub enum DuckDbImportSource {
-    Csv,
-    Json,
-    Parquet,
+    Csv(CsvImport),
+    Json(JsonImport),
+    Parquet(ParquetImport),
     SQLite,
-    PostgreSQL,
-    MySQL,
-    Iceberg,
-    DeltaLake,
-    CloudflareR2,
-    AzureBlob,
-    S3
+    PostgreSQL(AttachImport),
+    MySQL(AttachImport),
+    Iceberg(LakehouseImport),
+    DeltaLake(LakehouseImport),
+    Lake(LakeImport),
+    CloudflareR2(ObjectStoreImport),
+    AzureBlob(ObjectStoreImport),
+    S3(ObjectStoreImport),
 }
 
 pub enum DuckDbExportFormat {
@@ -43,30 +80,137 @@ pub enum DuckDbExportFormat {
 }
 
 impl Domain {
-    pub fn new(name: &String, available: bool, page_rank: Option<f64>) -> Self {
+    pub fn new(name: &str, available: bool, page_rank: Option<f64>) -> Self {
         if name.contains(' ') {
             panic!("Domain name cannot contain spaces");
         }
         match page_rank {
             Some(page_rank) => Domain {
                 id: Some(Self::calculate_hash(&name)),
-                name: name.clone(),
-                available: available,
+                name: name.to_string(),
+                available,
                 valid: None,
                 page_rank: Some(page_rank),
                 censored: None,
+                metrics: DomainMetrics::default(),
+                extras: None,
             },
             None => Domain {
                 id: Some(Self::calculate_hash(&name)),
-                name: name.clone(),
-                available: available,
+                name: name.to_string(),
+                available,
                 valid: None,
                 page_rank: None,
                 censored: None,
+                metrics: DomainMetrics::default(),
+                extras: None,
             },
         }
     }
 
+    /// Like [`Domain::new`], but reports an invalid name instead of panicking.
+    pub fn try_new(name: &str, available: bool, page_rank: Option<f64>) -> Result<Self, DomainError> {
+        Self::validate_name(name)?;
+        Ok(Domain::new(name, available, page_rank))
+    }
+
+    /// Check `name` is a plausible hostname: dot-separated labels of 1-63
+    /// alphanumerics or hyphens, without leading or trailing hyphens.
+    pub fn validate_name(name: &str) -> Result<(), DomainError> {
+        if name.is_empty() {
+            return Err(DomainError::Empty);
+        }
+        if name.chars().any(char::is_whitespace) {
+            return Err(DomainError::ContainsSpace);
+        }
+        if name.len() > 253 {
+            return Err(DomainError::TooLong);
+        }
+        if !name.contains('.') {
+            return Err(DomainError::MissingTld);
+        }
+        for label in name.split('.') {
+            if label.is_empty()
+                || label.len() > 63
+                || label.starts_with('-')
+                || label.ends_with('-')
+                || !label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            {
+                return Err(DomainError::InvalidLabel(label.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_valid(mut self, valid: Option<bool>) -> Self {
+        self.valid = valid;
+        self
+    }
+
+    pub fn with_censored(mut self, censored: Option<bool>) -> Self {
+        self.censored = censored;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: DomainMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_extras(mut self, extras: Option<serde_json::Value>) -> Self {
+        self.extras = extras;
+        self
+    }
+
+    /// The domain as a JSON object with the same fields as the domains table.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "available": self.available,
+            "valid": self.valid,
+            "page_rank": self.page_rank,
+            "censored": self.censored,
+            "backlinks": self.metrics.backlinks,
+            "whois_created": self.metrics.whois_created.map(|date| date.to_string()),
+            "extras": self.extras,
+        })
+    }
+
+    /// `extras` serialised for storage in a JSON/TEXT column.
+    pub(crate) fn extras_json(&self) -> Option<String> {
+        self.extras.as_ref().map(|extras| extras.to_string())
+    }
+
+    /// Rebuild a domain exactly as a store persisted it.
+    pub(crate) fn from_parts(
+        id: Option<u64>,
+        name: String,
+        available: bool,
+        valid: Option<bool>,
+        page_rank: Option<f64>,
+        censored: Option<bool>,
+    ) -> Self {
+        Domain { id, name, available, valid, page_rank, censored, metrics: DomainMetrics::default(), extras: None }
+    }
+
+    /// Parse an `extras` column read back from a store.
+    pub(crate) fn parse_extras(extras: Option<String>) -> Option<serde_json::Value> {
+        extras.and_then(|extras| serde_json::from_str(&extras).ok())
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    pub fn valid(&self) -> Option<bool> {
+        self.valid
+    }
+
+    pub fn censored(&self) -> Option<bool> {
+        self.censored
+    }
+
     fn calculate_hash<T: Hash>(t: &T) -> u64 {
         let mut s = DefaultHasher::new();
         t.hash(&mut s);
@@ -74,11 +218,62 @@ impl Domain {
     }
 }
 
+/// The columns [`read_domain`] expects, in order.
+pub(crate) const DOMAIN_COLUMNS: &str =
+    "id, name, available, valid, CAST(page_rank AS DOUBLE), censored, backlinks, whois_created, CAST(extras AS VARCHAR)";
+
+/// A domain from a row that starts with [`DOMAIN_COLUMNS`].
+pub(crate) fn read_domain(row: &duckdb::Row) -> Result<Domain> {
+    Ok(Domain::from_parts(
+        row.get(0)?,
+        row.get(1)?,
+        row.get::<_, Option<bool>>(2)?.unwrap_or_default(),
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+    )
+    .with_metrics(DomainMetrics {
+        backlinks: row.get(6)?,
+        whois_created: row.get(7)?,
+    })
+    .with_extras(Domain::parse_extras(row.get(8)?)))
+}
+
+/// Insert or replace a domain. `updated_at` only moves when a column
+/// actually changes, so re-crawling a domain does not make it look new to
+/// incremental exports.
+fn upsert_sql(table: &str) -> String {
+    // DuckDB 1.1 aborts on `DO UPDATE ... WHERE` when the condition is
+    // false, so the change test lives in the CASE instead.
+    format!(
+        "INSERT INTO {table} (id, name, available, valid, page_rank, censored, backlinks, whois_created, extras)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            available = EXCLUDED.available,
+            valid = EXCLUDED.valid,
+            page_rank = EXCLUDED.page_rank,
+            censored = EXCLUDED.censored,
+            backlinks = EXCLUDED.backlinks,
+            whois_created = EXCLUDED.whois_created,
+            extras = EXCLUDED.extras,
+            updated_at = CASE
+                WHEN name IS DISTINCT FROM EXCLUDED.name
+                  OR available IS DISTINCT FROM EXCLUDED.available
+                  OR valid IS DISTINCT FROM EXCLUDED.valid
+                  OR page_rank IS DISTINCT FROM EXCLUDED.page_rank
+                  OR censored IS DISTINCT FROM EXCLUDED.censored
+                  OR backlinks IS DISTINCT FROM EXCLUDED.backlinks
+                  OR whois_created IS DISTINCT FROM EXCLUDED.whois_created
+                  OR CAST(extras AS VARCHAR) IS DISTINCT FROM CAST(EXCLUDED.extras AS VARCHAR)
+                THEN now() ELSE updated_at END"
+    )
+}
+
 // TODO: Can this take an iterator?
-#[cfg(debug_assertions)]
 pub fn insert_domain(tx: &Transaction, domain: &Domain) -> Result<()> {
     let mut stmt: Statement;
-    stmt = tx.prepare("INSERT OR REPLACE INTO dev.domains (id, name, available, valid, page_rank, censored) VALUES (?, ?, ?, ?, ?, ?)")?;
+    stmt = tx.prepare(&upsert_sql(DOMAINS_TABLE))?;
     stmt.execute(params![
         domain.id,
         domain.name,
@@ -86,43 +281,53 @@ pub fn insert_domain(tx: &Transaction, domain: &Domain) -> Result<()> {
         domain.valid,
         domain.page_rank,
         domain.censored,
+        domain.metrics.backlinks,
+        domain.metrics.whois_created,
+        domain.extras_json(),
     ])?;
     Ok(())
 }
 
-#[cfg(not(debug_assertions))]
-pub fn insert_domain(tx: &Transaction, domain: &Domain) -> Result<()> {
-    let mut stmt: Statement;
-    stmt = tx.prepare("INSERT OR REPLACE INTO prod.domains (id, name, available, valid, page_rank, censored) VALUES (?, ?, ?, ?, ?, ?)")?;
-    stmt.execute(params![
-        domain.id,
-        domain.name,
-        domain.available,
-        domain.valid,
-        domain.page_rank,
-        domain.censored,
-    ])?;
-    Ok(())
+/// Delete the domain called `name`, recording a tombstone. Returns whether
+/// it existed.
+pub fn delete_domain(tx: &Transaction, name: &str) -> Result<bool> {
+    tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {TOMBSTONES_TABLE} (id, name, deleted_at)
+             SELECT id, name, CURRENT_TIMESTAMP FROM {DOMAINS_TABLE} WHERE name = ?"
+        ),
+        params![name],
+    )?;
+    let deleted = tx.execute(&format!("DELETE FROM {DOMAINS_TABLE} WHERE name = ?"), params![name])?;
+    Ok(deleted > 0)
 }
 
-pub fn update_domains(conn: &mut Connection, domain: &Domain) -> Result<()> {
-    let mut stmt: Statement;
-    let tx = conn.transaction()?;
-    stmt = tx.prepare("UPDATE id, name, available, valid, page_rank, censored INTO dev.domains VALUES (?, ?, ?, ?, ?, ?)")?;
-    stmt.execute(params![
-        domain.id,
-        domain.name,
-        domain.available,
-        domain.valid,
-        domain.page_rank,
-        domain.censored,
-    ])?;
-    tx.commit()?;
-    Ok(())
+/// Overwrite the stored domain with `domain`'s ID. Returns whether there
+/// was one; unlike [`insert_domain`], a domain not stored yet is left out.
+pub fn update_domain(tx: &Transaction, domain: &Domain) -> Result<bool> {
+    let updated = tx.execute(
+        &format!(
+            "UPDATE {DOMAINS_TABLE}
+             SET name = ?, available = ?, valid = ?, page_rank = ?, censored = ?, backlinks = ?, whois_created = ?, extras = ?, updated_at = now()
+             WHERE id = ?"
+        ),
+        params![
+            domain.name,
+            domain.available,
+            domain.valid,
+            domain.page_rank,
+            domain.censored,
+            domain.metrics.backlinks,
+            domain.metrics.whois_created,
+            domain.extras_json(),
+            domain.id,
+        ],
+    )?;
+    Ok(updated > 0)
 }
 
 pub fn list_valid_domains(conn: &Connection) -> Result<Vec<String>> {
-    let mut stmt = conn.prepare("SELECT name FROM dev.domains WHERE valid = true AND page_rank > 0 AND name LIKE '%.com' AND name LIKE '%.net' AND name LIKE '%.org' AND censored = false")?;
+    let mut stmt = conn.prepare(&format!("SELECT name FROM {DOMAINS_TABLE} WHERE valid = true AND page_rank > 0 AND (name LIKE '%.com' OR name LIKE '%.net' OR name LIKE '%.org') AND censored = false"))?;
     let mut rows = stmt.query([])?;
 
     let mut domains = Vec::new();
@@ -133,76 +338,18 @@ pub fn list_valid_domains(conn: &Connection) -> Result<Vec<String>> {
     Ok(domains)
 }
 
-pub fn db_init(db_type: DuckDbType) -> Result<Connection, duckdb::Error> {
+/// Open the DuckDB store. `Persistent` creates or upgrades the file at
+/// `DUCKDB_PATH`; `Existing` only opens a file that is already current. See
+/// [`DatabaseManager`] for the checks and errors.
+pub fn db_init(db_type: DuckDbType) -> Result<Connection, DbError> {
     match db_type {
         DuckDbType::InMemory => {
             let mut conn = Connection::open_in_memory()?;
-            let tx = conn.transaction().unwrap();
-            tx.execute_batch("
-                CREATE SCHEMA IF NOT EXISTS dev;
-                CREATE TYPE domainLanguage AS ENUM ('en', 'se', 'de', 'fr', 'es');
-                CREATE TYPE tld AS ENUM ('.com', '.net', '.org');
-                CREATE TABLE IF NOT EXISTS dev.domains (
-                    id          UBIGINT PRIMARY KEY,
-                    name        VARCHAR CHECK (NOT contains(name, ' ')),
-                    available   BOOLEAN DEFAULT NULL,
-                    valid       BOOLEAN DEFAULT NULL,
-                    page_rank   DECIMAL DEFAULT 0,
-                    censored    BOOLEAN DEFAULT NULL
-                );
-                COMMENT ON TABLE dev.domains IS 'All domains from expired-domains.co';
-                COMMENT ON COLUMN dev.domains.id IS 'random uuid';
-                COMMENT ON COLUMN dev.domains.name IS 'domain name';
-                COMMENT ON COLUMN dev.domains.available IS 'was domain available at the time of the scan';
-                COMMENT ON COLUMN dev.domains.valid IS 'is domain still available';
-                COMMENT ON COLUMN dev.domains.page_rank IS 'page rank score from expired-domains.co';
-                COMMENT ON COLUMN dev.domains.censored IS 'did domain fail to pass the censor check (true == bad words found)';",
-            ).unwrap();
-            tx.commit().unwrap();
-            Ok(conn)
-        },
-        DuckDbType::Persistent => {
-            dotenv().ok();
-            let dbpath = env::var("DUCKDB_PATH").unwrap_or("./data/domain-hunter.duckdb".to_string());
-            if !Path::new(&dbpath).exists() {
-                match fs::create_dir_all(Path::new(&dbpath)) {
-                    Ok(_) => {},
-                    Err(_) => {
-                        return Err(duckdb::Error::InvalidPath((&dbpath).into()));
-                    }
-                }
-            }
-            let mut conn = Connection::open(&dbpath)?;
-            let tx = conn.transaction().unwrap();
-            tx.execute_batch("
-                CREATE SCHEMA IF NOT EXISTS dev;
-                CREATE TYPE domainLanguage AS ENUM ('en', 'se', 'de', 'fr', 'es');
-                CREATE TYPE tld AS ENUM ('.com', '.net', '.org');
-                CREATE TABLE IF NOT EXISTS dev.domains (
-                    id          UBIGINT PRIMARY KEY,
-                    name        VARCHAR CHECK (NOT contains(name, ' ')),
-                    available   BOOLEAN DEFAULT NULL,
-                    valid       BOOLEAN DEFAULT NULL,
-                    page_rank   DECIMAL DEFAULT 0,
-                    censored    BOOLEAN DEFAULT NULL
-                );
-                COMMENT ON TABLE dev.domains IS 'All domains from expired-domains.co';
-                COMMENT ON COLUMN dev.domains.id IS 'random uuid';
-                COMMENT ON COLUMN dev.domains.name IS 'domain name';
-                COMMENT ON COLUMN dev.domains.available IS 'was domain available at the time of the scan';
-                COMMENT ON COLUMN dev.domains.valid IS 'is domain still available';
-                COMMENT ON COLUMN dev.domains.page_rank IS 'page rank score from expired-domains.co';
-                COMMENT ON COLUMN dev.domains.censored IS 'did domain fail to pass the censor check (true == bad words found)';",
-            ).unwrap();
-            tx.commit().unwrap();
+            apply_migrations(&mut conn)?;
             Ok(conn)
         },
-        DuckDbType::Existing => {
-            dotenv().ok();
-            let dbpath = env::var("DUCKDB_PATH").unwrap_or("./data/duck.db".to_string());
-            let conn = Connection::open(&dbpath)?;
-            Ok(conn)
-        }
+        DuckDbType::Persistent => DatabaseManager::from_env().open_or_upgrade(),
+        DuckDbType::Existing => DatabaseManager::from_env().open(),
     }
     // conn.execute("PRAGMA journal_mode = WAL")?;
     // conn.execute("PRAGMA synchronous = NORMAL")?;
@@ -216,85 +363,116 @@ pub fn db_init(db_type: DuckDbType) -> Result<Connection, duckdb::Error> {
     // conn.execute("PRAGMA wal_checkpoint(TRUNCATE)")?;
 }
 
-pub fn db_import(conn: &mut Connection, source: Option<DuckDbImportSource>) -> Result<()> {
+pub fn db_import(conn: &mut Connection, source: Option<DuckDbImportSource>) -> Result<ImportSummary, ImportError> {
     dotenv().ok();
     let src_directory = env::var("DUCKDB_EXPORT_TARGET_DIRECTORY").unwrap_or("./duckdb".to_string());
-    let mut stmt: Statement;
     let tx = conn.transaction()?;
 
     match source {
-        Some(DuckDbImportSource::Csv) => todo!(),
-        Some(DuckDbImportSource::Json) => todo!(),
-        Some(DuckDbImportSource::Parquet) => todo!(),
-        Some(DuckDbImportSource::SQLite) => todo!(),
-        Some(DuckDbImportSource::PostgreSQL) => todo!(),
-        Some(DuckDbImportSource::MySQL) => {
-            tx.execute_batch("BEGIN;
-                        INSTALL mysql;
-                        LOAD mysql;",
-            )?;
-            Ok(())
+        Some(DuckDbImportSource::Csv(import)) => {
+            let summary = import_csv(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
         },
-        Some(DuckDbImportSource::Iceberg) => {
-            tx.execute_batch("BEGIN;
-                        INSTALL iceberg;
-                        LOAD iceberg;
-                        UPDATE EXTENSIONS (iceberg);",
-            )?;
-            Ok(())
+        Some(DuckDbImportSource::Json(import)) => {
+            let summary = import_json(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
         },
-        Some(DuckDbImportSource::DeltaLake) => {
-            tx.execute_batch("BEGIN;
-                        INSTALL delta;
-                        LOAD delta;",
-            )?;
-            Ok(())
+        Some(DuckDbImportSource::Parquet(import)) => {
+            let summary = import_parquet(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
+        },
+        Some(DuckDbImportSource::SQLite) => {
+            let sqlite_path = StoreKind::Sqlite.default_path();
+            if !Path::new(&sqlite_path).exists() {
+                return Err(duckdb::Error::InvalidPath(sqlite_path.into()).into());
+            }
+            let domains = SqliteStore::open(&sqlite_path)?.list_domains()?;
+            let mut summary = ImportSummary::default();
+            for domain in &domains {
+                // A bad name would fail the CHECK and abort the whole import,
+                // so it is counted and skipped like a rejected file record.
+                match Domain::validate_name(&domain.name) {
+                    Ok(()) => merge_domain(&tx, domain, &mut summary)?,
+                    Err(_) => summary.rejected += 1,
+                }
+            }
+            tx.commit()?;
+            Ok(summary)
+        },
+        Some(DuckDbImportSource::PostgreSQL(import)) | Some(DuckDbImportSource::MySQL(import)) => {
+            let summary = import_attached(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
+        },
+        Some(DuckDbImportSource::Iceberg(import)) | Some(DuckDbImportSource::DeltaLake(import)) => {
+            let summary = import_lakehouse(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
+        },
+        Some(DuckDbImportSource::Lake(import)) => {
+            let summary = import_lake(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
+        },
+        Some(DuckDbImportSource::CloudflareR2(import))
+        | Some(DuckDbImportSource::AzureBlob(import))
+        | Some(DuckDbImportSource::S3(import)) => {
+            let summary = import_object_store(&tx, &import)?;
+            tx.commit()?;
+            Ok(summary)
         },
-        Some(DuckDbImportSource::CloudflareR2) => todo!(),
-        Some(DuckDbImportSource::AzureBlob) => todo!(),
-        Some(DuckDbImportSource::S3) => todo!(),
         _ => {
-            stmt = tx.prepare(r"IMPORT DATABASE '?';")?;
-            match stmt.execute([src_directory]) {
+            // Like EXPORT DATABASE, IMPORT DATABASE takes no parameters.
+            match tx.execute_batch(&format!("IMPORT DATABASE '{}';", src_directory.replace('\'', "''"))) {
                 Ok(_) => {
                     tx.commit()?;
-                    Ok(())
+                    Ok(ImportSummary::default())
                 },
                 Err(e) => {
                     tx.rollback()?;
-                    Err(e)
+                    Err(e.into())
                 },
             }
         }
     }
 }
 
+/// Dump the whole database to `DUCKDB_EXPORT_TARGET_DIRECTORY`. For
+/// filtered exports of the domains table see [`export_domains`].
+///
+/// [`export_domains`]: crate::util::db::export::export_domains
 pub fn db_export(conn: &mut Connection, format: DuckDbExportFormat) -> Result<()> {
     dotenv().ok();
     let target_directory = env::var("DUCKDB_EXPORT_TARGET_DIRECTORY").unwrap_or("./duckdb".to_string());
-    let mut stmt: Statement;
+    export_database(conn, &target_directory, format)
+}
+
+/// Dump the whole database to `target_directory`.
+pub fn export_database(conn: &mut Connection, target_directory: &str, format: DuckDbExportFormat) -> Result<()> {
+    // EXPORT DATABASE takes no parameters, so the path is quoted into the SQL.
+    let target_directory = target_directory.replace('\'', "''");
     let tx = conn.transaction()?;
 
-    match format {
-        DuckDbExportFormat::Parquet => {
-            stmt = tx.prepare(
-              r"EXPORT DATABASE '?' (
-                    FORMAT PARQUET,
-                    COMPRESSION ZSTD,
-                    ROW_GROUP_SIZE 100_000
-                );
-              ")?;
-        },
-        DuckDbExportFormat::Csv => {
-            stmt = tx.prepare(
-              r"EXPORT DATABASE '?' (
-                    FORMAT CSV, 
-                    DELIMITER '|'
-                );")?;
-        },
-    }
-    
-    match stmt.execute([target_directory]) {
+    let export = match format {
+        DuckDbExportFormat::Parquet => format!(
+            "EXPORT DATABASE '{target_directory}' (
+                FORMAT PARQUET,
+                COMPRESSION ZSTD,
+                ROW_GROUP_SIZE 100_000
+            );"
+        ),
+        DuckDbExportFormat::Csv => format!(
+            "EXPORT DATABASE '{target_directory}' (
+                FORMAT CSV,
+                DELIMITER '|'
+            );"
+        ),
+    };
+
+    match tx.execute_batch(&export) {
         Ok(_) => {
             tx.commit()?;
             Ok(())
@@ -306,9 +484,111 @@ pub fn db_export(conn: &mut Connection, format: DuckDbExportFormat) -> Result<()
     }
 }
 
+/// [`DomainStore`] backed by a DuckDB file, kept current by the shared migrations.
+pub struct DuckStore {
+    conn: Connection,
+}
+
+impl DuckStore {
+    /// Open the file at `path`, creating or upgrading it as needed.
+    pub fn open(path: &str) -> Result<Self, StoreError> {
+        Ok(DuckStore { conn: DatabaseManager::new(path).open_or_upgrade()? })
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    pub fn from_connection(mut conn: Connection) -> Result<Self, StoreError> {
+        apply_migrations(&mut conn)?;
+        Ok(DuckStore { conn })
+    }
+
+    pub fn connection(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
+
+/// Bring `conn` up to the latest schema. Safe to run on every open, and on
+/// files created before migrations were tracked.
+pub fn apply_migrations(conn: &mut Connection) -> Result<()> {
+    conn.execute_batch(MIGRATIONS_TABLE)?;
+    let current = duck_schema_version(conn)?;
+    for migration in migrations::pending(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.duckdb)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, description) VALUES (?, ?)",
+            params![migration.version, migration.description],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn select_domains(conn: &Connection) -> Result<Vec<Domain>> {
+    let mut stmt = conn.prepare(&format!("SELECT {DOMAIN_COLUMNS} FROM {DOMAINS_TABLE}"))?;
+    let rows = stmt.query_map([], read_domain)?;
+    rows.collect()
+}
+
+fn duck_schema_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("SELECT coalesce(max(version), 0) FROM schema_migrations", [], |row| row.get::<_, i64>(0))
+        .map(|version| version as u32)
+}
+
+impl DomainStore for DuckStore {
+    fn insert_domains(&mut self, domains: &[Domain]) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        for domain in domains {
+            insert_domain(&tx, domain)?;
+        }
+        tx.commit()?;
+        Ok(domains.len())
+    }
+
+    fn insert_domains_checked(&mut self, domains: &[Domain], check: &dyn Fn(&[Domain]) -> Result<(), StoreError>) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        for domain in domains {
+            insert_domain(&tx, domain)?;
+        }
+        check(&select_domains(&tx)?)?;
+        tx.commit()?;
+        Ok(domains.len())
+    }
+
+    fn list_domains(&self) -> Result<Vec<Domain>, StoreError> {
+        Ok(select_domains(&self.conn)?)
+    }
+
+    fn delete_domains(&mut self, names: &[String]) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        let mut deleted = 0;
+        for name in names {
+            deleted += usize::from(delete_domain(&tx, name)?);
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn list_valid_domains(&self) -> Result<Vec<String>, StoreError> {
+        Ok(list_valid_domains(&self.conn)?)
+    }
+
+    fn count_domains(&self) -> Result<usize, StoreError> {
+        let count: i64 = self.conn.query_row(&format!("SELECT count(*) FROM {DOMAINS_TABLE}"), [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn schema_version(&self) -> Result<u32, StoreError> {
+        Ok(duck_schema_version(&self.conn)?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
 
     #[test]
     fn test_insert_domain() {
@@ -316,11 +596,11 @@ mod tests {
         let tx = conn.transaction().unwrap();
 
         // Insert a domain
-        let insert = insert_domain(&tx, &Domain::new(&"test.com".to_string(), true, None));
+        let insert = insert_domain(&tx, &Domain::new("test.com", true, None));
         assert!(insert.is_ok());
 
         // Check if the domain was inserted
-        let mut stmt = tx.prepare("SELECT name FROM dev.domains WHERE name = ?").unwrap();
+        let mut stmt = tx.prepare(&format!("SELECT name FROM {DOMAINS_TABLE} WHERE name = ?")).unwrap();
         let mut rows = stmt.query(["test.com"]).unwrap();
         
         let mut names: Vec<String> = Vec::new();
@@ -341,15 +621,15 @@ mod tests {
         let tx = conn.transaction().unwrap();
 
         // Insert a domain
-        let insert = insert_domain(&tx, &Domain::new(&"test.com".to_string(), true, None));
+        let insert = insert_domain(&tx, &Domain::new("test.com", true, None));
         assert!(insert.is_ok());
         
         // Try to insert the same domain again
-        let insert = insert_domain(&tx, &Domain::new(&"test.com".to_string(), true, None));
+        let insert = insert_domain(&tx, &Domain::new("test.com", true, None));
         assert!(insert.is_ok());
 
         // Check if the domain was inserted more than once
-        let mut stmt = tx.prepare("SELECT name FROM dev.domains WHERE name = ?").unwrap();
+        let mut stmt = tx.prepare(&format!("SELECT name FROM {DOMAINS_TABLE} WHERE name = ?")).unwrap();
         let mut rows = stmt.query(["test.com"]).unwrap();
         
         let mut names: Vec<String> = Vec::new();
@@ -371,19 +651,95 @@ mod tests {
         let tx = conn.transaction().unwrap();
 
         // Insert a domain
-        let insert = insert_domain(&tx, &Domain::new(&"test com".to_string(), true, None));
+        let _insert = insert_domain(&tx, &Domain::new("test com", true, None));
         
         // Rollback the transaction
         tx.rollback().unwrap();
     }
 
+    #[test]
+    fn test_update_domain() {
+        let mut conn = db_init(DuckDbType::InMemory).unwrap();
+        let tx = conn.transaction().unwrap();
+        insert_domain(&tx, &Domain::new("test.com", true, Some(1.0))).unwrap();
+
+        let taken = Domain::new("test.com", false, Some(2.5));
+        assert!(update_domain(&tx, &taken).unwrap());
+        assert!(!update_domain(&tx, &Domain::new("other.com", true, None)).unwrap());
+
+        let row = tx
+            .query_row(&format!("SELECT available, page_rank, (SELECT count(*) FROM {DOMAINS_TABLE}) FROM {DOMAINS_TABLE} WHERE id = ?"), params![taken.id], |row| {
+                Ok((row.get::<_, bool>(0)?, row.get::<_, Option<f64>>(1)?, row.get::<_, i64>(2)?))
+            })
+            .unwrap();
+        assert_eq!(row, (false, Some(2.5), 1));
+        tx.rollback().unwrap();
+    }
+
+    #[test]
+    fn test_list_valid_domains() {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let checked = |name: &str, page_rank| Domain { valid: Some(true), censored: Some(false), ..Domain::new(name, true, page_rank) };
+        store.insert_domains(&[checked("ranked.com", Some(2.0)), checked("unranked.com", None), checked("ranked.io", Some(3.0)), Domain::new("unchecked.com", true, Some(1.0))]).unwrap();
+        assert_eq!(store.list_valid_domains().unwrap(), vec!["ranked.com"]);
+    }
+
     // TODO: Try to insert a domain with a bad page rank
     // TODO: Try to insert a domain with a bad censored value
     // TODO: Try to insert a domain with a bad available value
-    // TODO: Try to Load a CSV file
-    // TODO: Try to Load a CSV file that doesn't exist
-    // TODO: Try to export a CSV file
-    // TODO: Try to export a Parquet file
+    #[test]
+    fn test_validate_name() {
+        assert!(Domain::try_new("sub.example.co.uk", true, None).is_ok());
+        assert_eq!(Domain::try_new("", true, None).unwrap_err(), DomainError::Empty);
+        assert_eq!(Domain::try_new("example", true, None).unwrap_err(), DomainError::MissingTld);
+        assert_eq!(Domain::try_new("-bad.com", true, None).unwrap_err(), DomainError::InvalidLabel("-bad".to_string()));
+        assert_eq!(Domain::try_new("a..com", true, None).unwrap_err(), DomainError::InvalidLabel("".to_string()));
+    }
+
+    // Load a CSV file
+    #[test]
+    fn test_import_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("domains.csv");
+        fs::write(&path, "name,available,page_rank
+test.com,true,1.5
+bad name.com,true,1
+").unwrap();
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let summary = db_import(store.connection(), Some(DuckDbImportSource::Csv(CsvImport::new(&path)))).unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 1, updated: 0, rejected: 1 });
+        assert_eq!(store.list_domains().unwrap(), vec![Domain::new("test.com", true, Some(1.5))]);
+    }
+
+    // Try to Load a CSV file that doesn't exist
+    #[test]
+    fn test_import_missing_csv() {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let import = CsvImport::new("./does-not-exist.csv");
+
+        assert!(db_import(store.connection(), Some(DuckDbImportSource::Csv(import))).is_err());
+        assert_eq!(store.count_domains().unwrap(), 0);
+    }
+
+    // Export to a directory whose name needs quoting, which a '?' literal
+    // never bound
+    #[test]
+    fn test_export_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        store.insert_domains(&[Domain::new("test.com", true, Some(1.5))]).unwrap();
+
+        for (name, format) in [("it's csv", DuckDbExportFormat::Csv), ("parquet", DuckDbExportFormat::Parquet)] {
+            let target = dir.path().join(name);
+            export_database(store.connection(), &target.display().to_string(), format).unwrap();
+            assert!(target.join("schema.sql").exists());
+            assert!(target.join("load.sql").exists());
+        }
+        assert!(!Path::new("?").exists());
+    }
+
     // TODO: Verify that the rollbacks work
     // TODO: Verify DuckDbType::Persistent creates a new DB
 
@@ -0,0 +1,649 @@
+pub mod xlsx;
+
+use crate::util::db::export::xlsx::{write_xlsx, Cell};
+use crate::util::db::object_store::ObjectStore;
+use crate::util::db::store::{DOMAINS_TABLE, EXPORT_WATERMARKS_TABLE, TOMBSTONES_TABLE};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use duckdb::types::ValueRef;
+use duckdb::{params, Connection, OptionalExt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+/// Columns written by every format, in order.
+const COLUMNS: [&str; 9] = ["id", "name", "available", "valid", "page_rank", "censored", "backlinks", "whois_created", "extras"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("xlsx: {0}")]
+    Xlsx(#[from] zip::result::ZipError),
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("invalid filter '{sql}': {reason}")]
+    Filter { sql: String, reason: String },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    #[default]
+    Parquet,
+    /// A single JSON array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// An Excel workbook; local targets only.
+    Xlsx,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// Parquet is compressed with zstd unless told otherwise; nothing else is.
+    fn default_compression(&self) -> ExportCompression {
+        match self {
+            ExportFormat::Parquet => ExportCompression::Zstd,
+            _ => ExportCompression::None,
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportCompression {
+    None,
+    Gzip,
+    Zstd,
+    /// Parquet only.
+    Snappy,
+}
+
+impl ExportCompression {
+    fn duckdb_name(&self) -> &'static str {
+        match self {
+            ExportCompression::None => "uncompressed",
+            ExportCompression::Gzip => "gzip",
+            ExportCompression::Zstd => "zstd",
+            ExportCompression::Snappy => "snappy",
+        }
+    }
+
+    /// Text formats get a suffix such as `.csv.gz`; Parquet compresses
+    /// inside the file.
+    fn suffix(&self, format: ExportFormat) -> &'static str {
+        match (format, self) {
+            (ExportFormat::Parquet, _) => "",
+            (_, ExportCompression::Gzip) => ".gz",
+            (_, ExportCompression::Zstd) => ".zst",
+            _ => "",
+        }
+    }
+}
+
+/// Which domains to export. Every field that is set narrows the selection;
+/// the default selects everything.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DomainFilter {
+    pub available: Option<bool>,
+    pub valid: Option<bool>,
+    pub censored: Option<bool>,
+    /// TLDs to keep, without the dot, e.g. `com`.
+    pub tlds: Vec<String>,
+    pub min_page_rank: Option<f64>,
+    /// A `LIKE` pattern for the name, e.g. `%shop%`.
+    pub name_like: Option<String>,
+    /// Any further SQL predicate on the domains table.
+    pub sql: Option<String>,
+}
+
+pub(crate) fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl DomainFilter {
+    /// The filter as a `WHERE` predicate; `true` when nothing is set.
+    pub fn to_sql(&self) -> String {
+        let mut predicates = Vec::new();
+        for (column, value) in [("available", self.available), ("valid", self.valid), ("censored", self.censored)] {
+            if let Some(value) = value {
+                predicates.push(format!("{column} = {value}"));
+            }
+        }
+        if !self.tlds.is_empty() {
+            let tlds = self.tlds.iter().map(|tld| quote(&format!("%.{}", tld.trim_start_matches('.')))).collect::<Vec<_>>();
+            predicates.push(format!("({})", tlds.iter().map(|tld| format!("name LIKE {tld}")).collect::<Vec<_>>().join(" OR ")));
+        }
+        if let Some(min) = self.min_page_rank {
+            predicates.push(format!("page_rank >= {min}"));
+        }
+        if let Some(pattern) = &self.name_like {
+            predicates.push(format!("name LIKE {}", quote(pattern)));
+        }
+        if let Some(sql) = &self.sql {
+            predicates.push(format!("({sql})"));
+        }
+        match predicates.is_empty() {
+            true => "true".to_string(),
+            false => predicates.join(" AND "),
+        }
+    }
+
+    /// Check the free-form `sql` predicate is a single boolean expression
+    /// over the domains table, by preparing it there, before it is spliced
+    /// into a query. Parentheses must balance, and no comment or further
+    /// statement may follow, so it cannot escape the `WHERE` it is put in.
+    pub fn check(&self, conn: &Connection) -> Result<(), ExportError> {
+        let Some(sql) = &self.sql else { return Ok(()) };
+        let invalid = |reason: String| ExportError::Filter { sql: sql.clone(), reason };
+        let (mut depth, mut quote, mut previous) = (0i32, None, ' ');
+        for c in sql.chars() {
+            match (quote, c) {
+                (None, '-') if previous == '-' => return Err(invalid("comments are not allowed".to_string())),
+                (None, '*') if previous == '/' => return Err(invalid("comments are not allowed".to_string())),
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), _) => {},
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '(') => depth += 1,
+                (None, ')') if depth == 0 => return Err(invalid("unbalanced ')'".to_string())),
+                (None, ')') => depth -= 1,
+                (None, ';') => return Err(invalid("only one expression is allowed".to_string())),
+                _ => {},
+            }
+            previous = c;
+        }
+        if depth != 0 || quote.is_some() {
+            return Err(invalid("unbalanced parentheses or quotes".to_string()));
+        }
+        conn.prepare(&format!("SELECT CAST(({sql}) AS BOOLEAN) FROM {DOMAINS_TABLE} LIMIT 0"))
+            .map(drop)
+            .map_err(|e| invalid(e.to_string()))
+    }
+}
+
+/// An export of the domains table into a local directory or an object
+/// store prefix such as `s3://bucket/exports`.
+#[derive(Debug, Clone)]
+pub struct DomainExport {
+    pub target: String,
+    pub format: ExportFormat,
+    /// Defaults to zstd for Parquet and none for everything else.
+    pub compression: Option<ExportCompression>,
+    /// File name with `{table}`, `{date}`, `{timestamp}` and `{ext}`
+    /// placeholders, e.g. `domains-{date}.{ext}`.
+    pub name_template: String,
+    pub filter: DomainFilter,
+    /// Only write what changed since the previous incremental export to
+    /// the same target with the same filter, see [`export_domains`].
+    pub incremental: bool,
+    /// How far behind the clock an incremental export stops. Changes are
+    /// stamped when their transaction starts, so one still open when the
+    /// export runs is only safe to pick up next time if it began at least
+    /// this long ago.
+    pub skew: Duration,
+}
+
+impl DomainExport {
+    pub fn new(target: impl Into<String>, format: ExportFormat) -> Self {
+        DomainExport {
+            target: target.into(),
+            format,
+            compression: None,
+            name_template: "{table}-{timestamp}.{ext}".to_string(),
+            filter: DomainFilter::default(),
+            incremental: false,
+            skew: Duration::from_secs(60),
+        }
+    }
+
+    fn compression(&self) -> ExportCompression {
+        self.compression.unwrap_or(self.format.default_compression())
+    }
+
+    fn check(&self) -> Result<(), ExportError> {
+        let unsupported = match (self.format, self.compression()) {
+            (ExportFormat::Xlsx, ExportCompression::None) => None,
+            (ExportFormat::Xlsx, compression) => Some(format!("xlsx cannot be {compression:?} compressed")),
+            (ExportFormat::Parquet, _) | (_, ExportCompression::None | ExportCompression::Gzip | ExportCompression::Zstd) => None,
+            (format, compression) => Some(format!("{format} cannot be {compression:?} compressed")),
+        };
+        match unsupported {
+            Some(reason) => Err(ExportError::Unsupported(reason)),
+            None if self.format == ExportFormat::Xlsx && self.incremental => {
+                Err(ExportError::Unsupported("xlsx cannot hold incremental changes".to_string()))
+            },
+            None if ObjectStore::from_url(&self.target) == Some(ObjectStore::Azure) => {
+                Err(ExportError::Unsupported("DuckDB cannot write to Azure".to_string()))
+            },
+            None if self.format == ExportFormat::Xlsx && ObjectStore::from_url(&self.target).is_some() => {
+                Err(ExportError::Unsupported("xlsx can only be written to a local directory".to_string()))
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// The file name for an export started at `now`.
+    pub fn file_name(&self, now: DateTime<Utc>) -> String {
+        let table = DOMAINS_TABLE.rsplit('.').next().unwrap_or(DOMAINS_TABLE);
+        let extension = format!("{}{}", self.format.extension(), self.compression().suffix(self.format));
+        self.name_template
+            .replace("{table}", table)
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{timestamp}", &now.format("%Y%m%dT%H%M%SZ").to_string())
+            .replace("{ext}", &extension)
+    }
+
+    fn copy_options(&self) -> String {
+        let compression = self.compression().duckdb_name();
+        match self.format {
+            ExportFormat::Csv => format!("FORMAT CSV, HEADER, COMPRESSION {compression}"),
+            ExportFormat::Parquet => format!("FORMAT PARQUET, COMPRESSION {compression}"),
+            ExportFormat::Json => format!("FORMAT JSON, ARRAY true, COMPRESSION {compression}"),
+            ExportFormat::Ndjson => format!("FORMAT JSON, COMPRESSION {compression}"),
+            ExportFormat::Xlsx => unreachable!("xlsx is not written by COPY"),
+        }
+    }
+}
+
+/// One file written by an export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedFile {
+    pub path: String,
+    pub rows: usize,
+    /// Size and checksum are only known for local files.
+    pub bytes: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+/// The changes an incremental export covers: everything at or after
+/// `since` (or from the start, the first time) and before `until`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeWindow {
+    pub since: Option<NaiveDateTime>,
+    /// Fixed before anything is read, and the next export's `since`.
+    pub until: NaiveDateTime,
+}
+
+/// What an export wrote. Local exports also save it as
+/// `<file>.manifest.json` next to the data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportManifest {
+    pub created_at: DateTime<Utc>,
+    pub table: String,
+    pub format: ExportFormat,
+    pub compression: ExportCompression,
+    pub filter: String,
+    /// Set for incremental exports.
+    pub changes: Option<ChangeWindow>,
+    pub files: Vec<ExportedFile>,
+}
+
+impl ExportManifest {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "created_at": self.created_at.to_rfc3339(),
+            "table": self.table,
+            "format": self.format.extension(),
+            "compression": self.compression.duckdb_name(),
+            "filter": self.filter,
+            "changes": self.changes.as_ref().map(|changes| json!({
+                "since": changes.since.map(|since| since.to_string()),
+                "until": changes.until.to_string(),
+            })),
+            "files": self.files.iter().map(|file| json!({
+                "path": file.path,
+                "rows": file.rows,
+                "bytes": file.bytes,
+                "sha256": file.sha256,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.files.iter().map(|file| file.rows).sum()
+    }
+}
+
+pub(crate) fn sha256(path: &Path) -> Result<String, ExportError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn to_cell(value: ValueRef) -> Cell {
+    match value {
+        ValueRef::Null => Cell::Empty,
+        ValueRef::Boolean(value) => Cell::Bool(value),
+        ValueRef::Double(value) => Cell::Number(value),
+        ValueRef::BigInt(value) => Cell::Number(value as f64),
+        ValueRef::Text(text) => Cell::Text(String::from_utf8_lossy(text).into_owned()),
+        other => Cell::Text(format!("{other:?}")),
+    }
+}
+
+fn timestamp(value: NaiveDateTime) -> String {
+    format!("TIMESTAMP '{}'", value.format("%Y-%m-%d %H:%M:%S%.6f"))
+}
+
+/// Domains changed within `window` as upserts, followed by tombstones for
+/// deleted domains, both stamped with `changed_at`. The filter only applies
+/// to upserts: a tombstone has nothing left to filter on, and deleting an
+/// id a consumer never saw is harmless.
+fn changes_sql(filter: &str, window: &ChangeWindow) -> String {
+    let after = |column: &str| {
+        let since = window.since.map_or("true".to_string(), |since| format!("{column} >= {}", timestamp(since)));
+        format!("{since} AND {column} < {}", timestamp(window.until))
+    };
+    let nulls = COLUMNS[2..].iter().map(|column| format!("NULL AS {column}")).collect::<Vec<_>>().join(", ");
+    format!(
+        "SELECT 'upsert' AS op, updated_at AS changed_at, {} FROM {DOMAINS_TABLE} WHERE ({filter}) AND {}
+         UNION ALL
+         SELECT 'delete' AS op, deleted_at AS changed_at, id, name, {nulls} FROM {TOMBSTONES_TABLE}
+         WHERE {} AND id NOT IN (SELECT id FROM {DOMAINS_TABLE})",
+        COLUMNS.join(", "),
+        after("updated_at"),
+        after("deleted_at"),
+    )
+}
+
+/// Where the last incremental export of the domains matching `filter` to
+/// `target` stopped.
+pub fn watermark(conn: &Connection, target: &str, filter: &str) -> Result<Option<NaiveDateTime>, ExportError> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT watermark FROM {EXPORT_WATERMARKS_TABLE} WHERE target = ? AND filter = ?"),
+            params![target, filter],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Write the domains matching `export.filter`, ordered by name, and
+/// describe what was written.
+///
+/// An incremental export instead writes the changes since the watermark of
+/// the target and filter, oldest first, with an `op` column of `upsert` or
+/// `delete` and a `changed_at` column, then advances the watermark. A
+/// failed export leaves the watermark alone, so the next one repeats the
+/// same changes.
+pub fn export_domains(conn: &Connection, export: &DomainExport) -> Result<ExportManifest, ExportError> {
+    export.check()?;
+    export.filter.check(conn)?;
+    let remote = ObjectStore::from_url(&export.target);
+    if let Some(store) = remote {
+        store.configure(conn)?;
+    }
+
+    let created_at = Utc::now();
+    let path = format!("{}/{}", export.target.trim_end_matches('/'), export.file_name(created_at));
+    if let (None, Some(parent)) = (remote, Path::new(&path).parent()) {
+        fs::create_dir_all(parent)?;
+    }
+    let filter = export.filter.to_sql();
+    let changes = match export.incremental {
+        true => {
+            let since = watermark(conn, &export.target, &filter)?;
+            // Stamped the way writers stamp updated_at, so the two compare.
+            let until = conn.query_row("SELECT CAST(now() AS TIMESTAMP) - to_milliseconds(?)", params![export.skew.as_millis() as i64], |row| row.get(0))?;
+            Some(ChangeWindow { since, until })
+        },
+        false => None,
+    };
+    let select = match &changes {
+        Some(window) => format!("SELECT * FROM ({}) ORDER BY changed_at, name", changes_sql(&filter, window)),
+        None => format!("SELECT {} FROM {DOMAINS_TABLE} WHERE {filter} ORDER BY name", COLUMNS.join(", ")),
+    };
+    let rows = match export.format {
+        ExportFormat::Xlsx => {
+            // Ids are text because spreadsheets hold numbers as doubles.
+            let mut stmt = conn.prepare(&format!(
+                "SELECT CAST(id AS VARCHAR), name, available, valid, CAST(page_rank AS DOUBLE), censored, backlinks,
+                        CAST(whois_created AS VARCHAR), CAST(extras AS VARCHAR)
+                 FROM {DOMAINS_TABLE} WHERE {filter} ORDER BY name"
+            ))?;
+            let rows = stmt
+                .query_map([], |row| (0..COLUMNS.len()).map(|i| Ok(to_cell(row.get_ref(i)?))).collect())?
+                .collect::<Result<Vec<Vec<Cell>>, _>>()?;
+            write_xlsx(Path::new(&path), &COLUMNS, rows)?
+        },
+        _ => conn.execute(&format!("COPY ({select}) TO {} ({})", quote(&path), export.copy_options()), [])?,
+    };
+
+    let (bytes, sha256) = match remote {
+        Some(_) => (None, None),
+        None => (Some(fs::metadata(&path)?.len()), Some(sha256(Path::new(&path))?)),
+    };
+    let manifest = ExportManifest {
+        created_at,
+        table: DOMAINS_TABLE.to_string(),
+        format: export.format,
+        compression: export.compression(),
+        filter,
+        changes,
+        files: vec![ExportedFile { path: path.clone(), rows, bytes, sha256 }],
+    };
+    if remote.is_none() {
+        fs::write(format!("{path}.manifest.json"), serde_json::to_string_pretty(&manifest.to_json())?.as_bytes())?;
+    }
+    if let Some(ChangeWindow { until, .. }) = &manifest.changes {
+        conn.execute(
+            &format!("INSERT OR REPLACE INTO {EXPORT_WATERMARKS_TABLE} (target, filter, watermark, exported_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)"),
+            params![export.target, manifest.filter, until],
+        )?;
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::{insert_domain, Domain, DuckStore};
+    use crate::util::db::import::parquet::{import_parquet, ParquetImport};
+    use crate::util::db::store::DomainStore;
+    use chrono::TimeZone;
+
+    fn store() -> DuckStore {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        insert_domain(&tx, &Domain::new("shop.com", true, Some(3.0)).with_extras(Some(json!({"registrar": "gandi"})))).unwrap();
+        insert_domain(&tx, &Domain::new("blog.net", true, Some(1.0))).unwrap();
+        insert_domain(&tx, &Domain::new("taken.com", false, Some(5.0))).unwrap();
+        tx.commit().unwrap();
+        store
+    }
+
+    #[test]
+    fn test_filter_sql() {
+        assert_eq!(DomainFilter::default().to_sql(), "true");
+        let filter = DomainFilter {
+            available: Some(true),
+            tlds: vec!["com".to_string(), ".io".to_string()],
+            min_page_rank: Some(2.0),
+            name_like: Some("%o'k%".to_string()),
+            ..DomainFilter::default()
+        };
+        assert_eq!(filter.to_sql(), "available = true AND (name LIKE '%.com' OR name LIKE '%.io') AND page_rank >= 2 AND name LIKE '%o''k%'");
+    }
+
+    #[test]
+    fn test_filter_sql_is_checked() {
+        let mut store = store();
+        let conn = store.connection();
+        let filter = |sql: &str| DomainFilter { sql: Some(sql.to_string()), ..DomainFilter::default() };
+        assert!(filter("page_rank > 2 AND name <> ')' AND name NOT LIKE '%--%'").check(conn).is_ok());
+        for sql in ["no_such_column = 1", "true) OR (true", "true; DROP TABLE dev.domains", "true --", "true /* x */", "name = 'open", "name LIKE"] {
+            assert!(matches!(filter(sql).check(conn), Err(ExportError::Filter { .. })), "{sql}");
+        }
+        let export = DomainExport { filter: filter("no_such_column = 1"), ..DomainExport::new("unused", ExportFormat::Csv) };
+        assert!(matches!(export_domains(conn, &export), Err(ExportError::Filter { .. })));
+    }
+
+    #[test]
+    fn test_file_name_template() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+        let export = DomainExport {
+            compression: Some(ExportCompression::Gzip),
+            name_template: "{table}/{date}-{timestamp}.{ext}".to_string(),
+            ..DomainExport::new("out", ExportFormat::Csv)
+        };
+        assert_eq!(export.file_name(now), "domains/2024-05-01-20240501T123000Z.csv.gz");
+        assert_eq!(DomainExport::new("out", ExportFormat::Parquet).file_name(now), "domains-20240501T123000Z.parquet");
+    }
+
+    #[test]
+    fn test_unsupported_combinations() {
+        let snappy_csv = DomainExport { compression: Some(ExportCompression::Snappy), ..DomainExport::new("out", ExportFormat::Csv) };
+        assert!(matches!(snappy_csv.check(), Err(ExportError::Unsupported(_))));
+        assert!(matches!(DomainExport::new("s3://bucket", ExportFormat::Xlsx).check(), Err(ExportError::Unsupported(_))));
+        assert!(DomainExport::new("out", ExportFormat::Xlsx).check().is_ok());
+        let azure = DomainExport::new("az://container/exports", ExportFormat::Parquet).check();
+        assert!(matches!(azure, Err(ExportError::Unsupported(reason)) if reason == "DuckDB cannot write to Azure"));
+    }
+
+    #[test]
+    fn test_filtered_parquet_export_imports_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = store();
+        let export = DomainExport {
+            filter: DomainFilter { available: Some(true), ..DomainFilter::default() },
+            ..DomainExport::new(dir.path().display().to_string(), ExportFormat::Parquet)
+        };
+
+        let manifest = export_domains(source.connection(), &export).unwrap();
+
+        assert_eq!(manifest.rows(), 2);
+        let file = &manifest.files[0];
+        assert_eq!(file.sha256.as_ref().map(String::len), Some(64));
+        let saved: Value = serde_json::from_str(&fs::read_to_string(format!("{}.manifest.json", file.path)).unwrap()).unwrap();
+        assert_eq!(saved, manifest.to_json());
+
+        let mut target = DuckStore::open_in_memory().unwrap();
+        let tx = target.connection().transaction().unwrap();
+        import_parquet(&tx, &ParquetImport::new(file.path.clone())).unwrap();
+        tx.commit().unwrap();
+        let mut expected = source.list_domains().unwrap();
+        expected.retain(|domain| domain.available);
+        let mut imported = target.list_domains().unwrap();
+        expected.sort_by(|a, b| a.name.cmp(&b.name));
+        imported.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn test_incremental_exports_changes_and_tombstones() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = store();
+        let target = dir.path().display().to_string();
+        let export = |name: &str| DomainExport {
+            incremental: true,
+            name_template: format!("{name}.{{ext}}"),
+            skew: Duration::ZERO,
+            ..DomainExport::new(&target, ExportFormat::Ndjson)
+        };
+        let read = |name: &str| -> Vec<Value> {
+            fs::read_to_string(dir.path().join(format!("{name}.ndjson"))).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        };
+
+        let first = export_domains(source.connection(), &export("first")).unwrap();
+        assert_eq!(first.rows(), 3);
+        assert_eq!(first.changes.as_ref().unwrap().since, None);
+        assert_eq!(watermark(source.connection(), &target, "true").unwrap(), Some(first.changes.unwrap().until));
+        assert_eq!(export_domains(source.connection(), &export("empty")).unwrap().rows(), 0);
+
+        let tx = source.connection().transaction().unwrap();
+        insert_domain(&tx, &Domain::new("shop.com", false, Some(3.0))).unwrap();
+        // Unchanged, so not exported again.
+        insert_domain(&tx, &Domain::new("blog.net", true, Some(1.0))).unwrap();
+        tx.commit().unwrap();
+        source.delete_domains(&["taken.com".to_string()]).unwrap();
+
+        assert_eq!(export_domains(source.connection(), &export("second")).unwrap().rows(), 2);
+        let changes = read("second");
+        assert_eq!((&changes[0]["op"], &changes[0]["name"], &changes[0]["available"]), (&json!("upsert"), &json!("shop.com"), &json!(false)));
+        assert_eq!((&changes[1]["op"], &changes[1]["name"], &changes[1]["available"]), (&json!("delete"), &json!("taken.com"), &Value::Null));
+    }
+
+    #[test]
+    fn test_watermarks_are_kept_per_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = store();
+        let target = dir.path().display().to_string();
+        let export = |name: &str, available: Option<bool>| DomainExport {
+            incremental: true,
+            name_template: format!("{name}.{{ext}}"),
+            skew: Duration::ZERO,
+            filter: DomainFilter { available, ..DomainFilter::default() },
+            ..DomainExport::new(&target, ExportFormat::Ndjson)
+        };
+
+        assert_eq!(export_domains(source.connection(), &export("available", Some(true))).unwrap().rows(), 2);
+        assert_eq!(export_domains(source.connection(), &export("taken", Some(false))).unwrap().rows(), 1);
+        assert_eq!(export_domains(source.connection(), &export("again", Some(true))).unwrap().rows(), 0);
+    }
+
+    #[test]
+    fn test_skew_leaves_recent_changes_for_the_next_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = store();
+        let export = DomainExport { incremental: true, ..DomainExport::new(dir.path().display().to_string(), ExportFormat::Ndjson) };
+
+        let manifest = export_domains(source.connection(), &export).unwrap();
+
+        assert_eq!(manifest.rows(), 0);
+        assert_eq!(manifest.changes.unwrap().since, None);
+    }
+
+    #[test]
+    fn test_text_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = store();
+        let target = dir.path().display().to_string();
+
+        for (format, compression) in [
+            (ExportFormat::Csv, None),
+            (ExportFormat::Json, None),
+            (ExportFormat::Ndjson, Some(ExportCompression::Gzip)),
+            (ExportFormat::Xlsx, None),
+        ] {
+            let export = DomainExport { compression, name_template: "domains.{ext}".to_string(), ..DomainExport::new(&target, format) };
+            assert_eq!(export_domains(source.connection(), &export).unwrap().rows(), 3);
+        }
+
+        let csv = fs::read_to_string(dir.path().join("domains.csv")).unwrap();
+        assert!(csv.starts_with("id,name,available"));
+        let json: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("domains.json")).unwrap()).unwrap();
+        assert_eq!(json.as_array().map(Vec::len), Some(3));
+        assert!(dir.path().join("domains.ndjson.gz").exists());
+        assert!(dir.path().join("domains.xlsx.manifest.json").exists());
+    }
+}
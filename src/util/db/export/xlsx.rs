@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// A cell value, written with the matching spreadsheet type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Empty,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
+<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>
+</Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>
+</Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+<sheets><sheet name="domains" sheetId="1" r:id="rId1"/></sheets>
+</workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>
+</Relationships>"#;
+
+/// Column letters for a zero-based index: 0 is `A`, 26 is `AA`.
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// Escape `text` for a cell. XML cannot hold most control characters at
+/// all, so like Excel they are written as `_xHHHH_`, and an underscore
+/// that would start such an escape is itself escaped as `_x005F_`.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '_' if is_escape(&text[i..]) => escaped.push_str("_x005F_"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\0'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => escaped.push_str(&format!("_x{:04X}_", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether `text` starts with something a reader would take for an
+/// `_xHHHH_` escape.
+fn is_escape(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 7 && bytes[1] == b'x' && bytes[2..6].iter().all(u8::is_ascii_hexdigit) && bytes[6] == b'_'
+}
+
+fn write_row(sheet: &mut impl Write, row: usize, cells: &[Cell]) -> io::Result<()> {
+    write!(sheet, r#"<row r="{row}">"#)?;
+    for (column, cell) in cells.iter().enumerate() {
+        let reference = format!("{}{row}", column_name(column));
+        match cell {
+            Cell::Empty => {},
+            Cell::Bool(value) => write!(sheet, r#"<c r="{reference}" t="b"><v>{}</v></c>"#, u8::from(*value))?,
+            Cell::Number(value) => write!(sheet, r#"<c r="{reference}"><v>{value}</v></c>"#)?,
+            Cell::Text(value) => write!(sheet, r#"<c r="{reference}" t="inlineStr"><is><t>{}</t></is></c>"#, escape(value))?,
+        }
+    }
+    write!(sheet, "</row>")
+}
+
+/// Write a single-sheet workbook with a header row. Strings are stored
+/// inline rather than in a shared table, which every spreadsheet reader
+/// accepts and keeps this a single pass over the rows.
+pub fn write_xlsx(path: &Path, headers: &[&str], rows: impl IntoIterator<Item = Vec<Cell>>) -> zip::result::ZipResult<usize> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, contents) in [
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("xl/workbook.xml", WORKBOOK),
+        ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+    ] {
+        zip.start_file(name, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+
+    zip.start_file("xl/worksheets/sheet1.xml", options)?;
+    write!(
+        zip,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#
+    )?;
+    let headers = headers.iter().map(|header| Cell::Text(header.to_string())).collect::<Vec<_>>();
+    write_row(&mut zip, 1, &headers)?;
+    let mut written = 0;
+    for row in rows {
+        written += 1;
+        write_row(&mut zip, written + 1, &row)?;
+    }
+    write!(zip, "</sheetData></worksheet>")?;
+    zip.finish()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a&b<c>\"d\""), "a&amp;b&lt;c&gt;&quot;d&quot;");
+        assert_eq!(escape("bell\u{7}tab\tnul\0"), "bell_x0007_tab\tnul_x0000_");
+        assert_eq!(escape("literal _x0041_ and _x_"), "literal _x005F_x0041_ and _x_");
+    }
+
+    #[test]
+    fn test_write_xlsx() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("domains.xlsx");
+
+        let rows = vec![vec![Cell::Text("a&b.com".to_string()), Cell::Bool(true), Cell::Number(2.5), Cell::Empty]];
+        assert_eq!(write_xlsx(&path, &["name", "available", "page_rank", "backlinks"], rows).unwrap(), 1);
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut sheet = String::new();
+        archive.by_name("xl/worksheets/sheet1.xml").unwrap().read_to_string(&mut sheet).unwrap();
+        assert!(sheet.contains(r#"<c r="A2" t="inlineStr"><is><t>a&amp;b.com</t></is></c><c r="B2" t="b"><v>1</v></c><c r="C2"><v>2.5</v></c></row>"#));
+        assert!(archive.by_name("[Content_Types].xml").is_ok());
+    }
+}
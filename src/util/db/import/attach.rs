@@ -0,0 +1,183 @@
+use crate::util::db::import::{import_relation, ColumnMapping, ImportError, ImportSummary};
+use crate::util::db::store::StoreKind;
+use duckdb::Transaction;
+use std::env;
+use std::path::PathBuf;
+
+/// Name the source database is attached under for the length of an import.
+const ALIAS: &str = "import_source";
+
+/// A database server DuckDB can attach through one of its scanner extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AttachKind {
+    Mysql,
+    Postgres,
+}
+
+impl AttachKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            AttachKind::Mysql => "mysql",
+            AttachKind::Postgres => "postgres",
+        }
+    }
+
+    /// `MYSQL_URL` or `POSTGRES_URL`, falling back to the docker-compose services.
+    pub fn default_connection(&self) -> String {
+        match self {
+            AttachKind::Mysql => env::var("MYSQL_URL")
+                .unwrap_or("host=localhost port=3306 user=root password=mysql database=domain_hunter".to_string()),
+            AttachKind::Postgres => StoreKind::Postgres.default_path(),
+        }
+    }
+}
+
+/// What to read from the attached database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachSelect {
+    /// A table, optionally schema-qualified, e.g. `public.domains`.
+    Table(String),
+    /// A query run by the source server itself, in its own SQL dialect.
+    Query(String),
+}
+
+/// Domains held in a MySQL or PostgreSQL database, attached read-only.
+#[derive(Debug, Clone)]
+pub struct AttachImport {
+    pub kind: AttachKind,
+    /// A libpq/MySQL connection string (`host=... user=...`) or, for
+    /// PostgreSQL, a `postgresql://` URI.
+    pub connection: String,
+    pub select: AttachSelect,
+    pub mapping: ColumnMapping,
+    /// Where rejected rows are written; defaults to `<kind>.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl AttachImport {
+    pub fn new(kind: AttachKind, connection: impl Into<String>, select: AttachSelect) -> Self {
+        AttachImport {
+            kind,
+            connection: connection.into(),
+            select,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path
+            .clone()
+            .unwrap_or_else(|| format!("{}.rejects.csv", self.kind.extension()).into())
+    }
+
+    fn attach(&self) -> String {
+        format!(
+            "ATTACH '{}' AS {ALIAS} (TYPE {}, READ_ONLY)",
+            quote_literal(&self.connection),
+            self.kind.extension(),
+        )
+    }
+
+    /// The relation rows are read from once the source is attached.
+    fn relation(&self) -> String {
+        match &self.select {
+            AttachSelect::Table(table) => {
+                let path = table.split('.').map(quote_identifier).collect::<Vec<_>>().join(".");
+                format!("{ALIAS}.{path}")
+            },
+            AttachSelect::Query(query) => {
+                format!("{}_query('{ALIAS}', '{}')", self.kind.extension(), quote_literal(query))
+            },
+        }
+    }
+}
+
+fn quote_literal(value: &str) -> String {
+    value.replace('\'', "''")
+}
+
+fn quote_identifier(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Attach the source, merge its rows into the domains table within `tx`, and
+/// detach again. Nothing is committed here, so a failure part way leaves the
+/// domains table untouched once the caller rolls back.
+pub fn import_attached(tx: &Transaction, import: &AttachImport) -> Result<ImportSummary, ImportError> {
+    let extension = import.kind.extension();
+    tx.execute_batch(&format!("INSTALL {extension}"))?;
+    tx.execute_batch(&format!("LOAD {extension}"))?;
+    // A failed import can leave the alias attached to the connection.
+    tx.execute_batch(&format!("DETACH DATABASE IF EXISTS {ALIAS}"))?;
+    tx.execute_batch(&import.attach())?;
+    let summary = import_relation(tx, &import.relation(), &import.mapping, &import.reject_path())?;
+    tx.execute_batch(&format!("DETACH {ALIAS}"))?;
+    Ok(summary)
+}
+
+/// The ignored tests need `docker compose up -d postgres mysql` and the
+/// scanner extensions, and run with `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+
+    #[test]
+    fn test_relation_quoting() {
+        let table = AttachImport::new(AttachKind::Postgres, "", AttachSelect::Table("public.Domain List".to_string()));
+        assert_eq!(table.relation(), r#"import_source."public"."Domain List""#);
+
+        let query = AttachImport::new(AttachKind::Mysql, "", AttachSelect::Query("SELECT * FROM t WHERE tld = 'com'".to_string()));
+        assert_eq!(query.relation(), "mysql_query('import_source', 'SELECT * FROM t WHERE tld = ''com''')");
+        assert_eq!(query.attach(), "ATTACH '' AS import_source (TYPE mysql, READ_ONLY)");
+    }
+
+    /// Seed `listings` through a writable attach, then import it back.
+    fn seed_and_import(kind: AttachKind, select: AttachSelect) -> (ImportSummary, DuckStore) {
+        let connection = kind.default_connection();
+        let seed = duckdb::Connection::open_in_memory().unwrap();
+        seed.execute_batch(&format!(
+            "INSTALL {ext}; LOAD {ext};
+             ATTACH '{connection}' AS src (TYPE {ext});
+             DROP TABLE IF EXISTS src.listings;
+             CREATE TABLE src.listings (domain VARCHAR, pr DOUBLE, registrar VARCHAR);
+             INSERT INTO src.listings VALUES ('a.com', 1.5, 'gandi'), ('b.net', NULL, NULL), ('bad name.org', 1, NULL);",
+            ext = kind.extension(),
+        )).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let import = AttachImport {
+            mapping: ColumnMapping::parse(&["name=domain", "page_rank=pr"]).unwrap(),
+            reject_path: Some(dir.path().join("rejects.csv")),
+            ..AttachImport::new(kind, connection, select)
+        };
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_attached(&tx, &import).unwrap();
+        tx.commit().unwrap();
+        (summary, store)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_import_postgres_table() {
+        let (summary, store) = seed_and_import(AttachKind::Postgres, AttachSelect::Table("listings".to_string()));
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 1 });
+        let domain = store.list_domains().unwrap().into_iter().find(|d| d.name == "a.com").unwrap();
+        assert_eq!(domain.page_rank, Some(1.5));
+        assert_eq!(domain.extras, Some(serde_json::json!({"registrar": "gandi"})));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_import_mysql_query() {
+        let select = AttachSelect::Query("SELECT domain, pr FROM listings WHERE pr IS NOT NULL".to_string());
+        let (summary, store) = seed_and_import(AttachKind::Mysql, select);
+
+        assert_eq!(summary, ImportSummary { inserted: 1, updated: 0, rejected: 1 });
+        assert_eq!(store.list_domains().unwrap()[0].name, "a.com");
+    }
+}
@@ -0,0 +1,191 @@
+use crate::util::db::import::{merge_domain, parse_bool, parse_date, parse_f64, parse_i64, ColumnMapping, ImportError, ImportSummary, RawDomain, RejectWriter};
+use csv::{ReaderBuilder, StringRecord};
+use duckdb::Transaction;
+use std::path::PathBuf;
+
+/// A CSV export from a registrar or marketplace, with a header row.
+#[derive(Debug, Clone)]
+pub struct CsvImport {
+    pub path: PathBuf,
+    pub mapping: ColumnMapping,
+    pub delimiter: u8,
+    /// Where rejected rows are written; defaults to `<path>.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl CsvImport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CsvImport {
+            path: path.into(),
+            mapping: ColumnMapping::default(),
+            delimiter: b',',
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(".rejects.csv");
+            path.into()
+        })
+    }
+}
+
+/// Header positions of the mapped columns.
+struct Columns {
+    name: usize,
+    available: Option<usize>,
+    valid: Option<usize>,
+    page_rank: Option<usize>,
+    censored: Option<usize>,
+    backlinks: Option<usize>,
+    whois_created: Option<usize>,
+}
+
+impl Columns {
+    fn resolve(headers: &StringRecord, mapping: &ColumnMapping) -> Result<Self, ImportError> {
+        let find = |column: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(column));
+        Ok(Columns {
+            name: find(&mapping.name).ok_or_else(|| ImportError::MissingColumn(mapping.name.clone()))?,
+            available: find(&mapping.available),
+            valid: find(&mapping.valid),
+            page_rank: find(&mapping.page_rank),
+            censored: find(&mapping.censored),
+            backlinks: find(&mapping.backlinks),
+            whois_created: find(&mapping.whois_created),
+        })
+    }
+
+    fn read(&self, record: &StringRecord) -> Result<RawDomain, String> {
+        let cell = |idx: Option<usize>| idx.and_then(|i| record.get(i)).unwrap_or("");
+        Ok(RawDomain {
+            name: cell(Some(self.name)).trim().to_lowercase(),
+            available: parse_bool(cell(self.available)).map_err(|e| format!("available: {e}"))?,
+            valid: parse_bool(cell(self.valid)).map_err(|e| format!("valid: {e}"))?,
+            page_rank: parse_f64(cell(self.page_rank)).map_err(|e| format!("page_rank: {e}"))?,
+            censored: parse_bool(cell(self.censored)).map_err(|e| format!("censored: {e}"))?,
+            backlinks: parse_i64(cell(self.backlinks)).map_err(|e| format!("backlinks: {e}"))?,
+            whois_created: parse_date(cell(self.whois_created)).map_err(|e| format!("whois_created: {e}"))?,
+            extras: None,
+        })
+    }
+}
+
+/// Stream `import.path` into the domains table. Rows that fail to parse or
+/// hold an invalid name are written to the reject file instead.
+pub fn import_csv(tx: &Transaction, import: &CsvImport) -> Result<ImportSummary, ImportError> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(import.delimiter)
+        .flexible(true)
+        .from_path(&import.path)?;
+    let columns = Columns::resolve(reader.headers()?, &import.mapping)?;
+    let reject_path = import.reject_path();
+    let mut rejects = RejectWriter::new(&reject_path);
+    let mut summary = ImportSummary::default();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                rejects.reject(line, &e.to_string(), "")?;
+                summary.rejected += 1;
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let domain = columns
+            .read(&record)
+            .and_then(|raw| raw.into_domain().map_err(|e| format!("name: {e}")));
+        match domain {
+            Ok(domain) => merge_domain(tx, &domain, &mut summary)?,
+            Err(reason) => {
+                let raw = record.iter().collect::<Vec<_>>().join(&(import.delimiter as char).to_string());
+                rejects.reject(line, &reason, &raw)?;
+                summary.rejected += 1;
+            }
+        }
+    }
+
+    rejects.finish()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+    use std::fs;
+
+    #[test]
+    fn test_import_with_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registrar.csv");
+        fs::write(&path, "Domain;PR;Status\nexample.com;3;yes\nEXAMPLE.net;;no\n").unwrap();
+        let import = CsvImport {
+            mapping: ColumnMapping::parse(&["name=Domain", "page_rank=PR", "available=Status"]).unwrap(),
+            delimiter: b';',
+            ..CsvImport::new(&path)
+        };
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_csv(&tx, &import).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        let mut domains = store.list_domains().unwrap();
+        domains.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(domains[0], crate::util::db::duck::Domain::new("example.com", true, Some(3.0)));
+        assert_eq!(domains[1].name, "example.net");
+        assert!(!import.reject_path().exists());
+    }
+
+    #[test]
+    fn test_rejects_are_written_with_reasons() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.csv");
+        fs::write(&path, "name,page_rank\ngood.com,1\nbad name.com,1\nnodot,1\nrank.com,high\n").unwrap();
+        let import = CsvImport::new(&path);
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_csv(&tx, &import).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 1, updated: 0, rejected: 3 });
+        let rejects = fs::read_to_string(import.reject_path()).unwrap();
+        assert!(rejects.contains("3,name: Domain name cannot contain spaces,\"bad name.com,1\""));
+        assert!(rejects.contains("name: domain name has no TLD"));
+        assert!(rejects.contains("page_rank: 'high' is not a number"));
+    }
+
+    #[test]
+    fn test_reimport_counts_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.csv");
+        fs::write(&path, "name\na.com\nb.com\n").unwrap();
+        let import = CsvImport::new(&path);
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        import_csv(&tx, &import).unwrap();
+        let summary = import_csv(&tx, &import).unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 0, updated: 2, rejected: 0 });
+    }
+
+    #[test]
+    fn test_missing_name_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.csv");
+        fs::write(&path, "domain\na.com\n").unwrap();
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        assert!(matches!(import_csv(&tx, &CsvImport::new(&path)), Err(ImportError::MissingColumn(_))));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::util::db::import::{merge_domain, parse_bool, parse_date, parse_f64, parse_i64, ColumnMapping, ImportError, ImportSummary, RawDomain, RejectWriter};
+use duckdb::Transaction;
+use serde::de;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum JsonFormat {
+    /// Pick from the first non-whitespace byte: `[` means an array.
+    #[default]
+    Auto,
+    /// A single top-level array of records.
+    Array,
+    /// One record per line.
+    Ndjson,
+}
+
+/// A JSON or NDJSON file of domain records. The mapping's columns are
+/// dotted paths into each record, e.g. `backlinks=metrics.backlinks`.
+#[derive(Debug, Clone)]
+pub struct JsonImport {
+    pub path: PathBuf,
+    pub format: JsonFormat,
+    pub mapping: ColumnMapping,
+    /// Where rejected records are written; defaults to `<path>.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl JsonImport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonImport {
+            path: path.into(),
+            format: JsonFormat::Auto,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(".rejects.csv");
+            path.into()
+        })
+    }
+}
+
+/// Find `path` in `record`, first as a literal key and then as a dotted path.
+fn lookup<'v>(record: &'v Value, path: &str) -> Option<&'v Value> {
+    record
+        .get(path)
+        .or_else(|| path.split('.').try_fold(record, |value, key| value.get(key)))
+}
+
+/// Render a field as text so JSON goes through the same parsers as CSV.
+pub(crate) fn text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn read_record(record: &Value, mapping: &ColumnMapping) -> Result<RawDomain, String> {
+    let field = |path: &str| text(lookup(record, path));
+    Ok(RawDomain {
+        name: field(&mapping.name).trim().to_lowercase(),
+        available: parse_bool(&field(&mapping.available)).map_err(|e| format!("available: {e}"))?,
+        valid: parse_bool(&field(&mapping.valid)).map_err(|e| format!("valid: {e}"))?,
+        page_rank: parse_f64(&field(&mapping.page_rank)).map_err(|e| format!("page_rank: {e}"))?,
+        censored: parse_bool(&field(&mapping.censored)).map_err(|e| format!("censored: {e}"))?,
+        backlinks: parse_i64(&field(&mapping.backlinks)).map_err(|e| format!("backlinks: {e}"))?,
+        whois_created: parse_date(&field(&mapping.whois_created)).map_err(|e| format!("whois_created: {e}"))?,
+        extras: None,
+    })
+}
+
+/// Splits a top-level array into the raw text of each element as it is
+/// read, so only one record is held in memory at a time and an element that
+/// is not valid JSON can be rejected on its own instead of ending the import.
+struct Elements<R> {
+    bytes: std::io::Bytes<R>,
+    started: bool,
+    done: bool,
+}
+
+impl<R: BufRead> Elements<R> {
+    fn new(reader: R) -> Self {
+        Elements { bytes: reader.bytes(), started: false, done: false }
+    }
+
+    /// The next element's text, or `None` after the closing `]`.
+    fn next_element(&mut self) -> Result<Option<String>, ImportError> {
+        if !self.started {
+            match self.bytes.by_ref().find(|b| !b.as_ref().is_ok_and(u8::is_ascii_whitespace)).transpose()? {
+                Some(b'[') => self.started = true,
+                _ => return Err(ImportError::Json(de::Error::custom("expected an array of domain records"))),
+            }
+        }
+        let (mut element, mut depth, mut in_string, mut escaped) = (Vec::new(), 0usize, false, false);
+        while !self.done {
+            let Some(byte) = self.bytes.next().transpose()? else {
+                // An unterminated array: whatever is left is one bad record.
+                self.done = true;
+                break;
+            };
+            match byte {
+                _ if in_string => {
+                    in_string = escaped || byte != b'"';
+                    escaped = !escaped && byte == b'\\';
+                },
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth > 0 => depth -= 1,
+                b']' => self.done = true,
+                b',' if depth == 0 => break,
+                _ => {},
+            }
+            if !self.done {
+                element.push(byte);
+            }
+        }
+        let element = String::from_utf8_lossy(&element).trim().to_string();
+        match element.is_empty() {
+            true if self.done => Ok(None),
+            _ => Ok(Some(element)),
+        }
+    }
+}
+
+fn detect_format(reader: &mut impl BufRead) -> Result<JsonFormat, ImportError> {
+    let buf = reader.fill_buf()?;
+    match buf.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => Ok(JsonFormat::Array),
+        _ => Ok(JsonFormat::Ndjson),
+    }
+}
+
+/// Stream `import.path` into the domains table. Records that fail to parse
+/// or hold an invalid name are written to the reject file instead; the
+/// reject file's `line` is the line number for NDJSON and the element
+/// number for arrays.
+pub fn import_json(tx: &Transaction, import: &JsonImport) -> Result<ImportSummary, ImportError> {
+    let mut reader = BufReader::new(File::open(&import.path)?);
+    let format = match import.format {
+        JsonFormat::Auto => detect_format(&mut reader)?,
+        format => format,
+    };
+    let reject_path = import.reject_path();
+    let mut rejects = RejectWriter::new(&reject_path);
+    let mut summary = ImportSummary::default();
+
+    // Either a parsed record, or why it could not be parsed and its raw text.
+    let mut handle = |index: u64, record: Result<Value, (String, String)>| -> Result<(), ImportError> {
+        let domain = record.and_then(|record| {
+            read_record(&record, &import.mapping)
+                .and_then(|raw| raw.into_domain().map_err(|e| format!("name: {e}")))
+                .map_err(|reason| (reason, record.to_string()))
+        });
+        match domain {
+            Ok(domain) => merge_domain(tx, &domain, &mut summary),
+            Err((reason, raw)) => {
+                summary.rejected += 1;
+                rejects.reject(index, &reason, &raw)
+            }
+        }
+    };
+
+    match format {
+        JsonFormat::Ndjson | JsonFormat::Auto => {
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line).map_err(|e| (e.to_string(), line.clone()));
+                handle(i as u64 + 1, record)?;
+            }
+        },
+        JsonFormat::Array => {
+            let mut elements = Elements::new(reader);
+            let mut index = 0;
+            while let Some(element) = elements.next_element()? {
+                index += 1;
+                let record = serde_json::from_str(&element).map_err(|e| (e.to_string(), element.clone()));
+                handle(index, record)?;
+            }
+        },
+    }
+
+    rejects.finish()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+    use chrono::NaiveDate;
+    use std::fs;
+
+    fn import(import: &JsonImport) -> (ImportSummary, DuckStore) {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_json(&tx, import).unwrap();
+        tx.commit().unwrap();
+        (summary, store)
+    }
+
+    #[test]
+    fn test_import_array_with_nested_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.json");
+        fs::write(&path, r#"[
+            {"domain": "example.com", "whois": {"created": "2009-03-14"}, "metrics": {"backlinks": 120, "pr": 2.5}},
+            {"domain": "example.net", "metrics": {"backlinks": null}}
+        ]"#).unwrap();
+        let json = JsonImport {
+            mapping: ColumnMapping::parse(&["name=domain", "whois_created=whois.created", "backlinks=metrics.backlinks", "page_rank=metrics.pr"]).unwrap(),
+            ..JsonImport::new(&path)
+        };
+
+        let (summary, store) = import(&json);
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        let domain = store.list_domains().unwrap().into_iter().find(|d| d.name == "example.com").unwrap();
+        assert_eq!(domain.page_rank, Some(2.5));
+        assert_eq!(domain.metrics.backlinks, Some(120));
+        assert_eq!(domain.metrics.whois_created, NaiveDate::from_ymd_opt(2009, 3, 14));
+    }
+
+    #[test]
+    fn test_import_ndjson_rejects_bad_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.ndjson");
+        fs::write(&path, "{\"name\": \"a.com\", \"available\": true}\n\n{not json}\n{\"name\": \"bad name.com\"}\n{\"name\": \"b.com\", \"backlinks\": \"many\"}\n").unwrap();
+        let json = JsonImport::new(&path);
+
+        let (summary, store) = import(&json);
+
+        assert_eq!(summary, ImportSummary { inserted: 1, updated: 0, rejected: 3 });
+        assert!(store.list_domains().unwrap()[0].available);
+        let rejects = fs::read_to_string(json.reject_path()).unwrap();
+        assert!(rejects.contains("3,key must be a string"));
+        assert!(rejects.contains("4,name: Domain name cannot contain spaces"));
+        assert!(rejects.contains("5,backlinks: 'many' is not an integer"));
+    }
+
+    #[test]
+    fn test_import_array_rejects_bad_elements_and_goes_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.json");
+        fs::write(&path, r#"[{"name": "a.com", "note": "has ] and , and \" in it"}, {not json}, 42, {"name": "bad name.com"}, {"name": "b.com"}]"#).unwrap();
+        let json = JsonImport::new(&path);
+
+        let (summary, store) = import(&json);
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 3 });
+        assert_eq!(store.count_domains().unwrap(), 2);
+        let rejects = fs::read_to_string(json.reject_path()).unwrap();
+        assert!(rejects.contains("2,key must be a string"));
+        assert!(rejects.contains("4,name: Domain name cannot contain spaces"));
+    }
+
+    #[test]
+    fn test_array_elements() {
+        let mut elements = Elements::new(" [ {\"a\": [1, 2]}, \"x,y\" ,3]".as_bytes());
+        assert_eq!(elements.next_element().unwrap().as_deref(), Some("{\"a\": [1, 2]}"));
+        assert_eq!(elements.next_element().unwrap().as_deref(), Some("\"x,y\""));
+        assert_eq!(elements.next_element().unwrap().as_deref(), Some("3"));
+        assert_eq!(elements.next_element().unwrap(), None);
+        assert_eq!(Elements::new("[]".as_bytes()).next_element().unwrap(), None);
+        assert!(Elements::new("{}".as_bytes()).next_element().is_err());
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(&mut "  \n[{}]".as_bytes()).unwrap(), JsonFormat::Array);
+        assert_eq!(detect_format(&mut "{}\n{}".as_bytes()).unwrap(), JsonFormat::Ndjson);
+    }
+}
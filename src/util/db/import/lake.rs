@@ -0,0 +1,96 @@
+use crate::util::db::import::{import_relation, ColumnMapping, ImportError, ImportSummary};
+use crate::util::db::lake::lake_scan;
+use chrono::NaiveDate;
+use duckdb::Transaction;
+use std::path::PathBuf;
+
+/// The crawl lake written by [`LakeWriter`](crate::util::db::lake::LakeWriter).
+/// A domain seen by several crawls is imported once, from its newest
+/// partition; the `source` it came from is kept in `extras`.
+#[derive(Debug, Clone)]
+pub struct LakeImport {
+    pub root: PathBuf,
+    /// Only this source partition.
+    pub source: Option<String>,
+    /// Only partitions dated on or after this day.
+    pub since: Option<NaiveDate>,
+    pub mapping: ColumnMapping,
+    /// Where rejected rows are written; defaults to `<root>/lake.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl LakeImport {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LakeImport {
+            root: root.into(),
+            source: None,
+            since: None,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| self.root.join("lake.rejects.csv"))
+    }
+
+    /// The newest row for each name among the selected partitions.
+    fn relation(&self) -> String {
+        let mut predicates = vec!["true".to_string()];
+        if let Some(source) = &self.source {
+            predicates.push(format!("source = '{}'", source.replace('\'', "''")));
+        }
+        if let Some(since) = self.since {
+            predicates.push(format!("date >= DATE '{since}'"));
+        }
+        format!(
+            "(SELECT * EXCLUDE (newest, date, crawled_at) FROM (
+                SELECT *, row_number() OVER (PARTITION BY name ORDER BY date DESC, crawled_at DESC) AS newest
+                FROM {} WHERE {}
+             ) WHERE newest = 1)",
+            lake_scan(&self.root),
+            predicates.join(" AND "),
+        )
+    }
+}
+
+pub fn import_lake(tx: &Transaction, import: &LakeImport) -> Result<ImportSummary, ImportError> {
+    import_relation(tx, &import.relation(), &import.mapping, &import.reject_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::{Domain, DuckStore};
+    use crate::util::db::lake::LakeWriter;
+    use crate::util::db::store::DomainStore;
+    use serde_json::json;
+
+    #[test]
+    fn test_import_newest_row_per_domain() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut first = LakeWriter::new(dir.path(), "expired-domains.co").unwrap();
+        first.write(&[Domain::new("one.com", true, Some(1.0)), Domain::new("two.net", true, None)]).unwrap();
+        let mut second = LakeWriter::new(dir.path(), "other").unwrap();
+        second.write(&[Domain::new("one.com", false, Some(4.0))]).unwrap();
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_lake(&tx, &LakeImport::new(dir.path())).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        let one = store.list_domains().unwrap().into_iter().find(|domain| domain.name == "one.com").unwrap();
+        assert_eq!(one, Domain::new("one.com", false, Some(4.0)).with_extras(Some(json!({"source": "other"}))));
+    }
+
+    #[test]
+    fn test_relation_filters_partitions() {
+        let import = LakeImport {
+            source: Some("it's".to_string()),
+            since: NaiveDate::from_ymd_opt(2024, 5, 1),
+            ..LakeImport::new("lake")
+        };
+        assert!(import.relation().contains("WHERE true AND source = 'it''s' AND date >= DATE '2024-05-01'"));
+    }
+}
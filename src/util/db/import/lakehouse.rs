@@ -0,0 +1,739 @@
+use crate::util::db::import::{import_relation, ColumnMapping, ImportError, ImportSummary};
+use crate::util::db::store::IMPORT_SNAPSHOTS_TABLE;
+use duckdb::{params, OptionalExt, Transaction};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LakeFormat {
+    Iceberg,
+    Delta,
+}
+
+impl LakeFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            LakeFormat::Iceberg => "iceberg",
+            LakeFormat::Delta => "delta",
+        }
+    }
+}
+
+/// A local Iceberg or Delta Lake table. The snapshot each import reads is
+/// recorded, and the next import of the same table only reads what changed
+/// since: the files added by later Delta commits, or the rows of the new
+/// Iceberg snapshot that were not in the old one. Going back to an older
+/// snapshot reads all of it. Deletes are not carried over.
+#[derive(Debug, Clone)]
+pub struct LakehouseImport {
+    pub format: LakeFormat,
+    /// Table root: the directory holding `metadata/` or `_delta_log/`.
+    pub path: PathBuf,
+    /// Iceberg snapshot id or Delta version to read; defaults to the latest.
+    pub snapshot: Option<u64>,
+    /// Read the whole snapshot even if an earlier one was imported.
+    pub full: bool,
+    pub mapping: ColumnMapping,
+    /// Where rejected rows are written; defaults to `<path>.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl LakehouseImport {
+    pub fn new(format: LakeFormat, path: impl Into<PathBuf>) -> Self {
+        LakehouseImport {
+            format,
+            path: path.into(),
+            snapshot: None,
+            full: false,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(".rejects.csv");
+            path.into()
+        })
+    }
+
+    /// Key the imported snapshot is recorded under.
+    fn source(&self) -> String {
+        let path = self.path.canonicalize().unwrap_or_else(|_| self.path.clone());
+        format!("{}:{}", self.format.name(), path.display())
+    }
+
+    fn iceberg_scan(&self, snapshot: u64) -> String {
+        format!(
+            "iceberg_scan('{}', snapshot_from_id = {snapshot}, allow_moved_paths = true)",
+            quote_literal(&self.path),
+        )
+    }
+}
+
+fn quote_literal(path: &Path) -> String {
+    path.display().to_string().replace('\'', "''")
+}
+
+fn quote_identifier(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Each column of `scan` with its type.
+fn columns(tx: &Transaction, scan: &str) -> Result<Vec<(String, String)>, ImportError> {
+    let mut stmt = tx.prepare(&format!("DESCRIBE SELECT * FROM {scan}"))?;
+    let columns = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(columns.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// The rows of `target` that are not in `previous`, two snapshots of one
+/// table whose schema may differ. Both are read as `target`'s columns, so
+/// the mapped fields and the extras: a column `previous` lacks reads as
+/// NULL there, and one whose type changed is cast to the new type.
+fn changed_rows(target: &str, target_columns: &[(String, String)], previous: &str, previous_columns: &[(String, String)]) -> String {
+    let new = target_columns.iter().map(|(name, _)| quote_identifier(name)).collect::<Vec<_>>();
+    let old = target_columns
+        .iter()
+        .map(|(name, kind)| {
+            let column = quote_identifier(name);
+            match previous_columns.iter().any(|(previous, _)| previous == name) {
+                true => format!("TRY_CAST({column} AS {kind}) AS {column}"),
+                false => format!("CAST(NULL AS {kind}) AS {column}"),
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("(SELECT {} FROM {target} EXCEPT SELECT {} FROM {previous})", new.join(", "), old.join(", "))
+}
+
+/// Each snapshot of the Iceberg table at `path`, as its id and sequence
+/// number, oldest first. Snapshot ids are random; only sequence numbers
+/// say which came first.
+fn iceberg_snapshots(tx: &Transaction, path: &Path) -> Result<Vec<(u64, u64)>, ImportError> {
+    let mut stmt = tx.prepare(&format!(
+        "SELECT snapshot_id, sequence_number FROM iceberg_snapshots('{}') ORDER BY sequence_number",
+        quote_literal(path),
+    ))?;
+    let snapshots = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(snapshots.collect::<Result<Vec<_>, _>>()?)
+}
+
+fn last_snapshot(tx: &Transaction, source: &str) -> Result<Option<u64>, ImportError> {
+    Ok(tx
+        .query_row(
+            &format!("SELECT snapshot FROM {IMPORT_SNAPSHOTS_TABLE} WHERE source = ?"),
+            params![source],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn record_snapshot(tx: &Transaction, source: &str, snapshot: u64) -> Result<(), ImportError> {
+    tx.execute(
+        &format!("INSERT OR REPLACE INTO {IMPORT_SNAPSHOTS_TABLE} (source, snapshot, imported_at) VALUES (?, ?, current_timestamp)"),
+        params![source, snapshot],
+    )?;
+    Ok(())
+}
+
+/// A data file of a Delta table, with the partition values its `add`
+/// action gives it; partitioned files do not hold those columns themselves.
+#[derive(Debug, Clone, PartialEq)]
+struct DeltaFile {
+    path: PathBuf,
+    partition: Partition,
+}
+
+/// A file's partition values by column; a `None` value is a null partition.
+type Partition = BTreeMap<String, Option<String>>;
+
+/// Replays a Delta table's log to find its data files: the JSON commits,
+/// from the latest checkpoint when a full read needs one, so tables whose
+/// early commits were cleaned up can still be read. Reader features beyond
+/// protocol version 1 (column mapping, deletion vectors) are not supported.
+struct DeltaLog<'a> {
+    root: &'a Path,
+}
+
+impl DeltaLog<'_> {
+    fn log(&self) -> PathBuf {
+        self.root.join("_delta_log")
+    }
+
+    fn commit(&self, version: u64) -> PathBuf {
+        self.log().join(format!("{version:020}.json"))
+    }
+
+    /// Every commit and checkpoint in the log, by version.
+    fn versions(&self) -> Result<Vec<(u64, String)>, ImportError> {
+        let mut versions = Vec::new();
+        for entry in fs::read_dir(self.log())? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(version) = name.split('.').next().filter(|version| version.len() == 20).and_then(|version| version.parse().ok()) {
+                versions.push((version, name));
+            }
+        }
+        Ok(versions)
+    }
+
+    fn latest(&self) -> Result<u64, ImportError> {
+        self.versions()?
+            .into_iter()
+            .filter(|(_, name)| name.ends_with(".json") || name.ends_with(".parquet"))
+            .map(|(version, _)| version)
+            .max()
+            .ok_or_else(|| ImportError::Lakehouse(format!("{} has no Delta commits", self.root.display())))
+    }
+
+    /// The parts of the latest checkpoint at or before `to`: the one
+    /// `_last_checkpoint` names if it is early enough, else the latest found.
+    fn checkpoint(&self, to: u64) -> Result<Option<(u64, Vec<PathBuf>)>, ImportError> {
+        let last = match fs::read_to_string(self.log().join("_last_checkpoint")) {
+            Ok(last) => serde_json::from_str::<Value>(&last)?.get("version").and_then(Value::as_u64),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let checkpoints = self
+            .versions()?
+            .into_iter()
+            .filter(|(version, name)| *version <= to && name.contains(".checkpoint.") && name.ends_with(".parquet"))
+            .collect::<Vec<_>>();
+        let found = |version: &u64| checkpoints.iter().any(|(checkpoint, _)| checkpoint == version);
+        let Some(version) = last.filter(found).or_else(|| checkpoints.iter().map(|(version, _)| *version).max()) else {
+            return Ok(None);
+        };
+        let parts = checkpoints.into_iter().filter(|(part, _)| *part == version).map(|(_, name)| self.log().join(name)).collect();
+        Ok(Some((version, parts)))
+    }
+
+    /// Files added after version `after` up to `to`, less any removed in
+    /// between; with no `after`, or once the commits since it have been
+    /// cleaned up, every file live at `to`.
+    fn files(&self, tx: &Transaction, after: Option<u64>, to: u64) -> Result<Vec<DeltaFile>, ImportError> {
+        let mut files = BTreeMap::new();
+        let after = after.filter(|after| (after + 1..=to).all(|version| self.commit(version).exists()));
+        let start = match (after, self.checkpoint(to)?) {
+            (Some(after), _) => after + 1,
+            (None, Some((version, parts))) => {
+                self.read_checkpoint(tx, &parts, &mut files)?;
+                version + 1
+            },
+            (None, None) => 0,
+        };
+        for version in start..=to {
+            let commit = self.commit(version);
+            if !commit.exists() {
+                return Err(ImportError::Lakehouse(format!("{} is missing", commit.display())));
+            }
+            for line in fs::read_to_string(&commit)?.lines().filter(|line| !line.trim().is_empty()) {
+                let action: Value = serde_json::from_str(line)?;
+                check_protocol(action.pointer("/protocol/minReaderVersion").and_then(Value::as_u64))?;
+                if let Some(add) = action.get("add") {
+                    let partition = add.get("partitionValues").cloned().unwrap_or_default();
+                    self.add(&mut files, add.get("path").and_then(Value::as_str), partition)?;
+                }
+                if let Some(path) = action.pointer("/remove/path").and_then(Value::as_str) {
+                    files.remove(&self.data_file(path));
+                }
+            }
+        }
+        Ok(files.into_iter().map(|(path, partition)| DeltaFile { path, partition }).collect())
+    }
+
+    /// The files live at a checkpoint. Its rows each hold one action; only
+    /// `add`s are live, since the removes it keeps are tombstones.
+    fn read_checkpoint(&self, tx: &Transaction, parts: &[PathBuf], files: &mut BTreeMap<PathBuf, Partition>) -> Result<(), ImportError> {
+        let parts = parts.iter().map(|part| format!("'{}'", quote_literal(part))).collect::<Vec<_>>().join(", ");
+        let scan = format!("read_parquet([{parts}], union_by_name = true)");
+        let protocol = tx.query_row(&format!("SELECT max(protocol.minReaderVersion) FROM {scan}"), [], |row| row.get::<_, Option<u64>>(0))?;
+        check_protocol(protocol)?;
+        let mut stmt = tx.prepare(&format!(
+            "SELECT add.path, CAST(to_json(add.partitionValues) AS VARCHAR) FROM {scan} WHERE add IS NOT NULL"
+        ))?;
+        let adds = stmt.query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?)))?;
+        for add in adds {
+            let (path, partition) = add?;
+            let partition = partition.map(|partition| serde_json::from_str(&partition)).transpose()?.unwrap_or_default();
+            self.add(files, path.as_deref(), partition)?;
+        }
+        Ok(())
+    }
+
+    fn add(&self, files: &mut BTreeMap<PathBuf, Partition>, path: Option<&str>, partition: Value) -> Result<(), ImportError> {
+        let path = path.ok_or_else(|| ImportError::Lakehouse("a Delta add action has no path".to_string()))?;
+        let partition = match partition {
+            Value::Null => BTreeMap::new(),
+            partition => serde_json::from_value(partition)?,
+        };
+        files.insert(self.data_file(path), partition);
+        Ok(())
+    }
+
+    /// Log paths are URIs, usually relative to the table root.
+    fn data_file(&self, path: &str) -> PathBuf {
+        let path = percent_decode(path);
+        match path.strip_prefix("file://") {
+            Some(absolute) => PathBuf::from(absolute),
+            None => self.root.join(path),
+        }
+    }
+}
+
+fn check_protocol(reader: Option<u64>) -> Result<(), ImportError> {
+    match reader {
+        Some(reader) if reader > 1 => Err(ImportError::Lakehouse(format!("Delta reader version {reader} is not supported"))),
+        _ => Ok(()),
+    }
+}
+
+/// One scan of `files`, each read with its partition values as columns.
+/// Files with the same values are read together.
+fn delta_relation(files: &[DeltaFile]) -> Option<String> {
+    let mut groups: Vec<(&Partition, Vec<String>)> = Vec::new();
+    for file in files {
+        let path = format!("'{}'", quote_literal(&file.path));
+        match groups.iter_mut().find(|(partition, _)| **partition == file.partition) {
+            Some((_, paths)) => paths.push(path),
+            None => groups.push((&file.partition, vec![path])),
+        }
+    }
+    let scans = groups.into_iter().map(|(partition, paths)| {
+        let values = partition.iter().map(|(column, value)| {
+            let value = value.as_ref().map_or("NULL".to_string(), |value| format!("'{}'", value.replace('\'', "''")));
+            format!(", {value} AS {}", quote_identifier(column))
+        });
+        format!("SELECT *{} FROM read_parquet([{}], union_by_name = true)", values.collect::<String>(), paths.join(", "))
+    });
+    let scans = scans.collect::<Vec<_>>();
+    (!scans.is_empty()).then(|| format!("({})", scans.join(" UNION ALL BY NAME ")))
+}
+
+fn percent_decode(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        let escaped = (path.as_bytes()[i] == b'%')
+            .then(|| path.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .flatten();
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            },
+            None => {
+                bytes.push(path.as_bytes()[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Merge the table at `import.snapshot` into the domains table, then record
+/// that snapshot. Importing the snapshot recorded last does nothing.
+pub fn import_lakehouse(tx: &Transaction, import: &LakehouseImport) -> Result<ImportSummary, ImportError> {
+    let source = import.source();
+    let previous = match import.full {
+        true => None,
+        false => last_snapshot(tx, &source)?,
+    };
+
+    let (target, relation) = match import.format {
+        LakeFormat::Iceberg => {
+            tx.execute_batch("INSTALL iceberg")?;
+            tx.execute_batch("LOAD iceberg")?;
+            let snapshots = iceberg_snapshots(tx, &import.path)?;
+            let target = match (import.snapshot, snapshots.last()) {
+                (Some(snapshot), _) => snapshot,
+                (None, Some((latest, _))) => *latest,
+                (None, None) => return Err(ImportError::Lakehouse(format!("{} has no Iceberg snapshots", import.path.display()))),
+            };
+            let sequence = |snapshot: u64| snapshots.iter().find(|(id, _)| *id == snapshot).map(|(_, sequence)| *sequence);
+            // Going back to an older snapshot, or past one since expired,
+            // needs a full read of it.
+            let since = previous.filter(|previous| matches!((sequence(*previous), sequence(target)), (Some(from), Some(to)) if from < to));
+            let relation = match since {
+                Some(since) => {
+                    let (target, previous) = (import.iceberg_scan(target), import.iceberg_scan(since));
+                    changed_rows(&target, &columns(tx, &target)?, &previous, &columns(tx, &previous)?)
+                },
+                None => import.iceberg_scan(target),
+            };
+            (target, Some(relation))
+        },
+        LakeFormat::Delta => {
+            let log = DeltaLog { root: &import.path };
+            let target = match import.snapshot {
+                Some(version) => version,
+                None => log.latest()?,
+            };
+            // Going back to an older version needs a full read of it.
+            let files = log.files(tx, previous.filter(|previous| *previous < target), target)?;
+            (target, delta_relation(&files))
+        },
+    };
+
+    if previous == Some(target) {
+        return Ok(ImportSummary::default());
+    }
+    let summary = match relation {
+        Some(relation) => import_relation(tx, &relation, &import.mapping, &import.reject_path())?,
+        None => ImportSummary::default(),
+    };
+    record_snapshot(tx, &source, target)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+
+    /// A two-commit Delta table: version 1 rewrites `part-0` as `part-1`
+    /// with a changed row and a new one.
+    fn delta_table(store: &mut DuckStore, root: &Path) {
+        let log = root.join("_delta_log");
+        fs::create_dir_all(&log).unwrap();
+        store.connection().execute_batch(&format!(
+            "COPY (SELECT * FROM (VALUES ('a.com', 1.0), ('b.com', 2.0)) t(name, page_rank)) TO '{0}/part-0.parquet' (FORMAT PARQUET);
+             COPY (SELECT * FROM (VALUES ('a.com', 3.0), ('c.com', 1.0)) t(name, page_rank)) TO '{0}/part-1.parquet' (FORMAT PARQUET);",
+            root.display(),
+        )).unwrap();
+        fs::write(log.join(format!("{:020}.json", 0)), concat!(
+            r#"{"protocol":{"minReaderVersion":1,"minWriterVersion":2}}"#, "\n",
+            r#"{"metaData":{"id":"t","format":{"provider":"parquet"},"partitionColumns":[]}}"#, "\n",
+            r#"{"add":{"path":"part-0.parquet","partitionValues":{},"dataChange":true}}"#, "\n",
+        )).unwrap();
+        fs::write(log.join(format!("{:020}.json", 1)), concat!(
+            r#"{"remove":{"path":"part-0.parquet","dataChange":true}}"#, "\n",
+            r#"{"add":{"path":"part-1.parquet","partitionValues":{},"dataChange":true}}"#, "\n",
+        )).unwrap();
+    }
+
+    /// Avro's `long`: zigzag, then seven bits a byte. `int` is the same.
+    fn avro_long(out: &mut Vec<u8>, value: i64) {
+        let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+        while zigzag >= 0x80 {
+            out.push((zigzag as u8 & 0x7f) | 0x80);
+            zigzag >>= 7;
+        }
+        out.push(zigzag as u8);
+    }
+
+    /// Avro's `bytes` and `string`: the length, then the bytes.
+    fn avro_bytes(out: &mut Vec<u8>, value: &[u8]) {
+        avro_long(out, value.len() as i64);
+        out.extend_from_slice(value);
+    }
+
+    /// An Avro object container file, uncompressed, holding `records`
+    /// already encoded to `schema`, with `metadata` beside the schema.
+    fn avro_file(path: &Path, schema: &Value, metadata: &[(&str, String)], records: &[Vec<u8>]) {
+        let sync = [7u8; 16];
+        let mut file = b"Obj\x01".to_vec();
+        let schema = schema.to_string();
+        let mut entries = vec![("avro.schema", schema), ("avro.codec", "null".to_string())];
+        entries.extend(metadata.iter().cloned());
+        avro_long(&mut file, entries.len() as i64);
+        for (key, value) in &entries {
+            avro_bytes(&mut file, key.as_bytes());
+            avro_bytes(&mut file, value.as_bytes());
+        }
+        avro_long(&mut file, 0);
+        file.extend_from_slice(&sync);
+        avro_long(&mut file, records.len() as i64);
+        avro_bytes(&mut file, &records.concat());
+        file.extend_from_slice(&sync);
+        fs::write(path, file).unwrap();
+    }
+
+    /// Avro's encoding of the values a manifest record needs: `Some` for an
+    /// `int` or `long`, and for the second branch of a `[null, long]` union.
+    enum Avro<'a> {
+        Long(i64),
+        Optional(i64),
+        Str(&'a str),
+    }
+
+    fn avro_record(values: &[Avro]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for value in values {
+            match value {
+                Avro::Long(value) => avro_long(&mut out, *value),
+                Avro::Optional(value) => {
+                    avro_long(&mut out, 1);
+                    avro_long(&mut out, *value);
+                },
+                Avro::Str(value) => avro_bytes(&mut out, value.as_bytes()),
+            }
+        }
+        out
+    }
+
+    /// A two-snapshot Iceberg table: snapshot 9000 holds `data-0`, and
+    /// snapshot 4000 after it appends `data-1`, with a changed row and a new
+    /// one. The ids run backwards, as random ids may.
+    fn iceberg_table(store: &mut DuckStore, root: &Path) {
+        let (data, metadata) = (root.join("data"), root.join("metadata"));
+        fs::create_dir_all(&data).unwrap();
+        fs::create_dir_all(&metadata).unwrap();
+        store.connection().execute_batch(&format!(
+            "COPY (SELECT * FROM (VALUES ('a.com', 1.0::DOUBLE), ('b.com', 2.0)) t(name, page_rank)) TO '{0}/data-0.parquet' (FORMAT PARQUET, FIELD_IDS {{name: 1, page_rank: 2}});
+             COPY (SELECT * FROM (VALUES ('a.com', 3.0::DOUBLE), ('c.com', 1.0)) t(name, page_rank)) TO '{0}/data-1.parquet' (FORMAT PARQUET, FIELD_IDS {{name: 1, page_rank: 2}});",
+            data.display(),
+        )).unwrap();
+
+        let schema = serde_json::json!({"type": "struct", "schema-id": 0, "fields": [
+            {"id": 1, "name": "name", "required": false, "type": "string"},
+            {"id": 2, "name": "page_rank", "required": false, "type": "double"},
+        ]});
+        let entry_schema = serde_json::json!({"type": "record", "name": "manifest_entry", "fields": [
+            {"name": "status", "type": "int", "field-id": 0},
+            {"name": "snapshot_id", "type": ["null", "long"], "default": null, "field-id": 1},
+            {"name": "sequence_number", "type": ["null", "long"], "default": null, "field-id": 3},
+            {"name": "file_sequence_number", "type": ["null", "long"], "default": null, "field-id": 4},
+            {"name": "data_file", "field-id": 2, "type": {"type": "record", "name": "r2", "fields": [
+                {"name": "content", "type": "int", "field-id": 134},
+                {"name": "file_path", "type": "string", "field-id": 100},
+                {"name": "file_format", "type": "string", "field-id": 101},
+                {"name": "partition", "type": {"type": "record", "name": "r102", "fields": []}, "field-id": 102},
+                {"name": "record_count", "type": "long", "field-id": 103},
+                {"name": "file_size_in_bytes", "type": "long", "field-id": 104},
+            ]}},
+        ]});
+        let list_schema = serde_json::json!({"type": "record", "name": "manifest_file", "fields": [
+            {"name": "manifest_path", "type": "string", "field-id": 500},
+            {"name": "manifest_length", "type": "long", "field-id": 501},
+            {"name": "partition_spec_id", "type": "int", "field-id": 502},
+            {"name": "content", "type": "int", "field-id": 517},
+            {"name": "sequence_number", "type": "long", "field-id": 515},
+            {"name": "min_sequence_number", "type": "long", "field-id": 516},
+            {"name": "added_snapshot_id", "type": "long", "field-id": 503},
+            {"name": "added_files_count", "type": "int", "field-id": 504},
+            {"name": "existing_files_count", "type": "int", "field-id": 505},
+            {"name": "deleted_files_count", "type": "int", "field-id": 506},
+            {"name": "added_rows_count", "type": "long", "field-id": 512},
+            {"name": "existing_rows_count", "type": "long", "field-id": 513},
+            {"name": "deleted_rows_count", "type": "long", "field-id": 514},
+        ]});
+        let manifest_metadata = [
+            ("schema", schema.to_string()),
+            ("schema-id", "0".to_string()),
+            ("partition-spec", "[]".to_string()),
+            ("partition-spec-id", "0".to_string()),
+            ("format-version", "2".to_string()),
+            ("content", "data".to_string()),
+        ];
+
+        // One manifest per snapshot, each adding one data file.
+        let snapshots = [(9000, 1, "data-0.parquet"), (4000, 2, "data-1.parquet")];
+        let mut manifests = Vec::new();
+        for (snapshot, sequence, file) in snapshots {
+            let file = data.join(file);
+            let manifest = metadata.join(format!("manifest-{snapshot}.avro"));
+            let entry = avro_record(&[
+                Avro::Long(1),
+                Avro::Optional(snapshot),
+                Avro::Optional(sequence),
+                Avro::Optional(sequence),
+                Avro::Long(0),
+                Avro::Str(&file.display().to_string()),
+                Avro::Str("PARQUET"),
+                Avro::Long(2),
+                Avro::Long(fs::metadata(&file).unwrap().len() as i64),
+            ]);
+            avro_file(&manifest, &entry_schema, &manifest_metadata, &[entry]);
+            manifests.push((snapshot, sequence, manifest));
+        }
+        let mut logged = Vec::new();
+        for (i, (snapshot, sequence, _)) in manifests.iter().enumerate() {
+            let list = metadata.join(format!("snap-{snapshot}.avro"));
+            let records = manifests[..=i]
+                .iter()
+                .map(|(added, added_sequence, manifest)| {
+                    avro_record(&[
+                        Avro::Str(&manifest.display().to_string()),
+                        Avro::Long(fs::metadata(manifest).unwrap().len() as i64),
+                        Avro::Long(0),
+                        Avro::Long(0),
+                        Avro::Long(*added_sequence),
+                        Avro::Long(*added_sequence),
+                        Avro::Long(*added),
+                        Avro::Long(i64::from(added == snapshot)),
+                        Avro::Long(i64::from(added != snapshot)),
+                        Avro::Long(0),
+                        Avro::Long(if added == snapshot { 2 } else { 0 }),
+                        Avro::Long(if added == snapshot { 0 } else { 2 }),
+                        Avro::Long(0),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            avro_file(&list, &list_schema, &[("snapshot-id", snapshot.to_string()), ("sequence-number", sequence.to_string()), ("format-version", "2".to_string())], &records);
+            logged.push(serde_json::json!({
+                "snapshot-id": snapshot,
+                "parent-snapshot-id": i.checked_sub(1).map(|parent| manifests[parent].0),
+                "sequence-number": sequence,
+                "timestamp-ms": 1_700_000_000_000i64 + sequence * 1000,
+                "manifest-list": list.display().to_string(),
+                "summary": {"operation": "append"},
+                "schema-id": 0,
+            }));
+            let table = serde_json::json!({
+                "format-version": 2,
+                "table-uuid": "9c12d441-03fe-4693-9a96-a0705ddf69c1",
+                "location": root.display().to_string(),
+                "last-sequence-number": sequence,
+                "last-updated-ms": 1_700_000_000_000i64 + sequence * 1000,
+                "last-column-id": 2,
+                "current-schema-id": 0,
+                "schemas": [schema],
+                "default-spec-id": 0,
+                "partition-specs": [{"spec-id": 0, "fields": []}],
+                "last-partition-id": 999,
+                "default-sort-order-id": 0,
+                "sort-orders": [{"order-id": 0, "fields": []}],
+                "properties": {},
+                "current-snapshot-id": snapshot,
+                "refs": {"main": {"snapshot-id": snapshot, "type": "branch"}},
+                "snapshots": logged,
+                "snapshot-log": logged.iter().map(|snapshot| serde_json::json!({"snapshot-id": snapshot["snapshot-id"], "timestamp-ms": snapshot["timestamp-ms"]})).collect::<Vec<_>>(),
+                "metadata-log": [],
+            });
+            fs::write(metadata.join(format!("v{}.metadata.json", i + 1)), serde_json::to_string_pretty(&table).unwrap()).unwrap();
+            fs::write(metadata.join("version-hint.text"), (i + 1).to_string()).unwrap();
+        }
+    }
+
+    fn import(store: &mut DuckStore, import: &LakehouseImport) -> ImportSummary {
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_lakehouse(&tx, import).unwrap();
+        tx.commit().unwrap();
+        summary
+    }
+
+    #[test]
+    fn test_delta_versions_import_incrementally() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        delta_table(&mut store, dir.path());
+        let first = LakehouseImport { snapshot: Some(0), ..LakehouseImport::new(LakeFormat::Delta, dir.path()) };
+        let latest = LakehouseImport::new(LakeFormat::Delta, dir.path());
+
+        assert_eq!(import(&mut store, &first), ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        assert_eq!(import(&mut store, &latest), ImportSummary { inserted: 1, updated: 1, rejected: 0 });
+        assert_eq!(import(&mut store, &latest), ImportSummary::default());
+
+        let a = store.list_domains().unwrap().into_iter().find(|d| d.name == "a.com").unwrap();
+        assert_eq!(a.page_rank, Some(3.0));
+        let recorded: u64 = store.connection().query_row(&format!("SELECT snapshot FROM {IMPORT_SNAPSHOTS_TABLE}"), [], |row| row.get(0)).unwrap();
+        assert_eq!(recorded, 1);
+    }
+
+    #[test]
+    fn test_delta_full_import_reads_live_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        delta_table(&mut store, dir.path());
+
+        let tx = store.connection().transaction().unwrap();
+        let files = DeltaLog { root: dir.path() }.files(&tx, None, 1).unwrap();
+
+        assert_eq!(files, vec![DeltaFile { path: dir.path().join("part-1.parquet"), partition: Partition::new() }]);
+    }
+
+    #[test]
+    fn test_delta_reads_from_a_checkpoint_with_partition_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        delta_table(&mut store, dir.path());
+        let log = dir.path().join("_delta_log");
+        // The writer checkpointed version 1 and cleaned up the commits
+        // before it; version 2 adds a file whose partition value is only in
+        // the log, not in its directory's name.
+        store.connection().execute_batch(&format!(
+            "COPY (SELECT * FROM (VALUES ('d.com', 2.0)) t(name, page_rank)) TO '{}/part-2.parquet' (FORMAT PARQUET);
+             COPY (
+                 SELECT {{'minReaderVersion': 1, 'minWriterVersion': 2}} AS protocol, NULL::STRUCT(path VARCHAR, partitionValues MAP(VARCHAR, VARCHAR)) AS add
+                 UNION ALL SELECT NULL, {{'path': 'part-1.parquet', 'partitionValues': MAP {{'tier': 'silver'}}}}
+             ) TO '{}/{:020}.checkpoint.parquet' (FORMAT PARQUET);",
+            dir.path().display(),
+            log.display(),
+            1,
+        )).unwrap();
+        fs::write(log.join("_last_checkpoint"), r#"{"version":1,"size":2}"#).unwrap();
+        fs::remove_file(log.join(format!("{:020}.json", 0))).unwrap();
+        fs::remove_file(log.join(format!("{:020}.json", 1))).unwrap();
+        fs::write(log.join(format!("{:020}.json", 2)), concat!(
+            r#"{"add":{"path":"part-2.parquet","partitionValues":{"tier":"gold"},"dataChange":true}}"#, "\n",
+        )).unwrap();
+
+        let summary = import(&mut store, &LakehouseImport::new(LakeFormat::Delta, dir.path()));
+
+        assert_eq!(summary, ImportSummary { inserted: 3, updated: 0, rejected: 0 });
+        let tiers = store.list_domains().unwrap().into_iter().map(|d| (d.name, d.extras)).collect::<BTreeMap<_, _>>();
+        assert_eq!(tiers["a.com"], Some(serde_json::json!({"tier": "silver"})));
+        assert_eq!(tiers["d.com"], Some(serde_json::json!({"tier": "gold"})));
+        let recorded: u64 = store.connection().query_row(&format!("SELECT snapshot FROM {IMPORT_SNAPSHOTS_TABLE}"), [], |row| row.get(0)).unwrap();
+        assert_eq!(recorded, 2);
+    }
+
+    #[test]
+    fn test_delta_rejects_newer_reader_protocol() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("_delta_log")).unwrap();
+        fs::write(dir.path().join("_delta_log").join(format!("{:020}.json", 0)), r#"{"protocol":{"minReaderVersion":3}}"#).unwrap();
+
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        let result = DeltaLog { root: dir.path() }.files(&tx, None, 0);
+
+        assert!(matches!(result, Err(ImportError::Lakehouse(_))));
+    }
+
+    #[test]
+    #[ignore = "needs the iceberg extension"]
+    fn test_iceberg_snapshots_import_incrementally() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        iceberg_table(&mut store, dir.path());
+        let first = LakehouseImport { snapshot: Some(9000), ..LakehouseImport::new(LakeFormat::Iceberg, dir.path()) };
+        let latest = LakehouseImport::new(LakeFormat::Iceberg, dir.path());
+
+        assert_eq!(import(&mut store, &first), ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        assert_eq!(import(&mut store, &latest), ImportSummary { inserted: 1, updated: 1, rejected: 0 });
+        assert_eq!(import(&mut store, &latest), ImportSummary::default());
+        let page_rank = |store: &DuckStore| store.list_domains().unwrap().into_iter().find(|d| d.name == "a.com").unwrap().page_rank;
+        assert_eq!(page_rank(&store), Some(3.0));
+
+        // Going back reads the older snapshot whole, not a difference.
+        assert_eq!(import(&mut store, &first), ImportSummary { inserted: 0, updated: 2, rejected: 0 });
+        assert_eq!(page_rank(&store), Some(1.0));
+    }
+
+    #[test]
+    fn test_changed_rows_across_a_schema_change() {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        let tx = store.connection().transaction().unwrap();
+        // The newer snapshot dropped `note`, widened `page_rank` and added `backlinks`.
+        tx.execute_batch(
+            "CREATE TEMP TABLE old AS SELECT * FROM (VALUES ('a.com', 1, 'x'), ('b.com', 2, 'y')) t(name, page_rank, note);
+             CREATE TEMP TABLE new AS SELECT * FROM (VALUES ('a.com', 1.5::DOUBLE, NULL::BIGINT), ('a.com', 1.0, NULL), ('b.com', 3.0, NULL), ('c.com', 1.0, 5)) t(name, page_rank, backlinks);",
+        )
+        .unwrap();
+
+        let relation = changed_rows("new", &columns(&tx, "new").unwrap(), "old", &columns(&tx, "old").unwrap());
+        let mut stmt = tx.prepare(&format!("SELECT name, page_rank FROM {relation} ORDER BY ALL")).unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(rows, vec![("a.com".to_string(), 1.5), ("b.com".to_string(), 3.0), ("c.com".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("date=2024-01-01/part%20one.parquet"), "date=2024-01-01/part one.parquet");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,404 @@
+pub mod attach;
+pub mod csv;
+pub mod json;
+pub mod lake;
+pub mod lakehouse;
+pub mod object_store;
+pub mod parquet;
+
+use crate::util::db::duck::{insert_domain, Domain, DomainError, DomainMetrics};
+use chrono::NaiveDate;
+use crate::util::db::store::DOMAINS_TABLE;
+use crate::util::db::import::json::text;
+use duckdb::{params, Transaction};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::path::Path;
+
+/// Rows are pulled out of the staging table this many at a time.
+const BATCH_SIZE: i64 = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Store(#[from] crate::util::db::store::StoreError),
+    #[error("csv: {0}")]
+    Csv(#[from] ::csv::Error),
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("source has no '{0}' column")]
+    MissingColumn(String),
+    #[error("unknown domain field '{0}'")]
+    UnknownField(String),
+    #[error("expected FIELD=COLUMN, got '{0}'")]
+    BadMapping(String),
+    #[error("lakehouse table: {0}")]
+    Lakehouse(String),
+}
+
+/// Counts reported at the end of an import.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub updated: usize,
+    pub rejected: usize,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inserted, {} updated, {} rejected", self.inserted, self.updated, self.rejected)
+    }
+}
+
+/// Which source column feeds each [`Domain`] field. Only `name` is
+/// required; optional columns missing from the source are left unset.
+/// For JSON sources a column is a dotted path such as `whois.created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    pub name: String,
+    pub available: String,
+    pub valid: String,
+    pub page_rank: String,
+    pub censored: String,
+    pub backlinks: String,
+    pub whois_created: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            name: "name".to_string(),
+            available: "available".to_string(),
+            valid: "valid".to_string(),
+            page_rank: "page_rank".to_string(),
+            censored: "censored".to_string(),
+            backlinks: "backlinks".to_string(),
+            whois_created: "whois_created".to_string(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Point `field` at `column`, e.g. `set("name", "Domain")`.
+    pub fn set(mut self, field: &str, column: &str) -> Result<Self, ImportError> {
+        let slot = match field {
+            "name" => &mut self.name,
+            "available" => &mut self.available,
+            "valid" => &mut self.valid,
+            "page_rank" => &mut self.page_rank,
+            "censored" => &mut self.censored,
+            "backlinks" => &mut self.backlinks,
+            "whois_created" => &mut self.whois_created,
+            _ => return Err(ImportError::UnknownField(field.to_string())),
+        };
+        *slot = column.to_string();
+        Ok(self)
+    }
+
+    /// Every field paired with the column that feeds it.
+    pub fn fields(&self) -> [(&'static str, &str); 7] {
+        [
+            ("name", &self.name),
+            ("available", &self.available),
+            ("valid", &self.valid),
+            ("page_rank", &self.page_rank),
+            ("censored", &self.censored),
+            ("backlinks", &self.backlinks),
+            ("whois_created", &self.whois_created),
+        ]
+    }
+
+    /// Build a mapping from `FIELD=COLUMN` pairs on top of the defaults.
+    pub fn parse<S: AsRef<str>>(pairs: &[S]) -> Result<Self, ImportError> {
+        pairs.iter().try_fold(ColumnMapping::default(), |mapping, pair| {
+            let (field, column) = pair
+                .as_ref()
+                .split_once('=')
+                .ok_or_else(|| ImportError::BadMapping(pair.as_ref().to_string()))?;
+            mapping.set(field.trim(), column.trim())
+        })
+    }
+}
+
+/// A source row turned into domain fields, before name validation.
+#[derive(Debug, Default)]
+pub(crate) struct RawDomain {
+    pub name: String,
+    pub available: Option<bool>,
+    pub valid: Option<bool>,
+    pub page_rank: Option<f64>,
+    pub censored: Option<bool>,
+    pub backlinks: Option<i64>,
+    pub whois_created: Option<NaiveDate>,
+    pub extras: Option<serde_json::Value>,
+}
+
+impl RawDomain {
+    /// Validate through the [`Domain`] model. Imported lists rarely say
+    /// whether a name is available, so that defaults to `false`.
+    pub fn into_domain(self) -> Result<Domain, DomainError> {
+        Ok(Domain::try_new(&self.name, self.available.unwrap_or(false), self.page_rank)?
+            .with_valid(self.valid)
+            .with_censored(self.censored)
+            .with_metrics(DomainMetrics {
+                backlinks: self.backlinks,
+                whois_created: self.whois_created,
+            })
+            .with_extras(self.extras))
+    }
+}
+
+pub(crate) fn parse_bool(value: &str) -> Result<Option<bool>, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" | "null" => Ok(None),
+        "true" | "t" | "yes" | "y" | "1" => Ok(Some(true)),
+        "false" | "f" | "no" | "n" | "0" => Ok(Some(false)),
+        other => Err(format!("'{other}' is not a boolean")),
+    }
+}
+
+pub(crate) fn parse_f64(value: &str) -> Result<Option<f64>, String> {
+    match value.trim() {
+        "" | "null" => Ok(None),
+        other => other.parse().map(Some).map_err(|_| format!("'{other}' is not a number")),
+    }
+}
+
+pub(crate) fn parse_i64(value: &str) -> Result<Option<i64>, String> {
+    match value.trim() {
+        "" | "null" => Ok(None),
+        other => other.parse().map(Some).map_err(|_| format!("'{other}' is not an integer")),
+    }
+}
+
+/// Accepts `YYYY-MM-DD`, optionally followed by a time as in RFC 3339.
+pub(crate) fn parse_date(value: &str) -> Result<Option<NaiveDate>, String> {
+    match value.trim() {
+        "" | "null" => Ok(None),
+        other => other
+            .get(..10)
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            .map(Some)
+            .ok_or_else(|| format!("'{other}' is not a date")),
+    }
+}
+
+/// Upsert `domain`, reporting whether it already existed.
+pub(crate) fn merge_domain(tx: &Transaction, domain: &Domain, summary: &mut ImportSummary) -> Result<(), ImportError> {
+    let exists: bool = tx.query_row(
+        &format!("SELECT count(*) > 0 FROM {DOMAINS_TABLE} WHERE id = ?"),
+        params![domain.id()],
+        |row| row.get(0),
+    )?;
+    insert_domain(tx, domain)?;
+    match exists {
+        true => summary.updated += 1,
+        false => summary.inserted += 1,
+    }
+    Ok(())
+}
+
+/// Appends rejected source rows, with the reason, to a CSV file that is
+/// only created once the first row is rejected.
+pub struct RejectWriter<'a> {
+    path: &'a Path,
+    writer: Option<::csv::Writer<std::fs::File>>,
+}
+
+impl<'a> RejectWriter<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        RejectWriter { path, writer: None }
+    }
+
+    pub fn reject(&mut self, line: u64, reason: &str, record: &str) -> Result<(), ImportError> {
+        if self.writer.is_none() {
+            let mut writer = ::csv::Writer::from_path(self.path)?;
+            writer.write_record(["line", "reason", "record"])?;
+            self.writer = Some(writer);
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_record([line.to_string().as_str(), reason, record])?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), ImportError> {
+        if let Some(mut writer) = self.writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// How the columns found in a source line up with the [`ColumnMapping`].
+/// The domain tables' own `id` and `extras` columns are recognised so that
+/// exports import back unchanged: ids are always derived from the name, and
+/// an `extras` object is merged into the new one.
+#[derive(Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    /// Each field that has a column, with that column's name as it appears
+    /// in the source.
+    pub mapped: Vec<(&'static str, String)>,
+    /// Fields with no column; they take the same defaults as an empty cell.
+    pub missing: Vec<&'static str>,
+    /// Columns no field asked for; their values are kept in `extras`.
+    pub extras: Vec<String>,
+    /// A source `extras` column, from a previous export.
+    pub nested_extras: Option<String>,
+}
+
+impl Reconciliation {
+    /// Match `columns` to fields case-insensitively. Only `name` is required.
+    pub fn new(columns: &[String], mapping: &ColumnMapping) -> Result<Self, ImportError> {
+        let mut reconciliation = Reconciliation::default();
+        for (field, wanted) in mapping.fields() {
+            match columns.iter().find(|c| c.eq_ignore_ascii_case(wanted)) {
+                Some(column) => reconciliation.mapped.push((field, column.clone())),
+                None if field == "name" => return Err(ImportError::MissingColumn(wanted.to_string())),
+                None => reconciliation.missing.push(field),
+            }
+        }
+        for column in columns {
+            if reconciliation.mapped.iter().any(|(_, mapped)| mapped == column) || column == "id" {
+                continue;
+            }
+            match column.as_str() {
+                "extras" => reconciliation.nested_extras = Some(column.clone()),
+                _ => reconciliation.extras.push(column.clone()),
+            }
+        }
+        Ok(reconciliation)
+    }
+
+    fn column(&self, field: &str) -> Option<&str> {
+        self.mapped.iter().find(|(f, _)| *f == field).map(|(_, column)| column.as_str())
+    }
+
+    fn read(&self, record: &Value) -> Result<RawDomain, String> {
+        let field = |name: &str| text(self.column(name).and_then(|column| record.get(column)));
+        // JSON columns arrive as objects, or as strings from text formats.
+        let mut extras = match self.nested_extras.as_ref().and_then(|column| record.get(column)) {
+            Some(Value::Object(nested)) => nested.clone(),
+            Some(Value::String(nested)) => serde_json::from_str(nested).unwrap_or_default(),
+            _ => Map::new(),
+        };
+        extras.extend(
+            self.extras
+                .iter()
+                .filter_map(|column| record.get(column).filter(|v| !v.is_null()).map(|v| (column.clone(), v.clone()))),
+        );
+        Ok(RawDomain {
+            name: field("name").trim().to_lowercase(),
+            available: parse_bool(&field("available")).map_err(|e| format!("available: {e}"))?,
+            valid: parse_bool(&field("valid")).map_err(|e| format!("valid: {e}"))?,
+            page_rank: parse_f64(&field("page_rank")).map_err(|e| format!("page_rank: {e}"))?,
+            censored: parse_bool(&field("censored")).map_err(|e| format!("censored: {e}"))?,
+            backlinks: parse_i64(&field("backlinks")).map_err(|e| format!("backlinks: {e}"))?,
+            whois_created: parse_date(&field("whois_created")).map_err(|e| format!("whois_created: {e}"))?,
+            extras: (!extras.is_empty()).then_some(Value::Object(extras)),
+        })
+    }
+}
+
+/// Column names of `relation`.
+fn describe(tx: &Transaction, scan: &str) -> Result<Vec<String>, ImportError> {
+    let mut stmt = tx.prepare(&format!("DESCRIBE SELECT * FROM {scan}"))?;
+    let columns = stmt.query_map([], |row| row.get(0))?;
+    Ok(columns.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Load any relation DuckDB can scan (a table function, an attached table or
+/// a subquery) into the domains table. Rows are staged as JSON in a
+/// temporary table, then read back in batches through the same parsers as
+/// CSV and JSON imports. Columns no field asked for are kept in `extras`;
+/// the reject file's `line` is the row number in the relation.
+pub(crate) fn import_relation(tx: &Transaction, relation: &str, mapping: &ColumnMapping, reject_path: &Path) -> Result<ImportSummary, ImportError> {
+    let reconciliation = Reconciliation::new(&describe(tx, relation)?, mapping)?;
+    tx.execute_batch(&format!(
+        "CREATE OR REPLACE TEMP TABLE import_staging AS
+         SELECT row_number() OVER () AS line, CAST(to_json(t) AS VARCHAR) AS record FROM {relation} t"
+    ))?;
+
+    let mut rejects = RejectWriter::new(reject_path);
+    let mut summary = ImportSummary::default();
+    let mut last_line = 0;
+    let mut stmt = tx.prepare("SELECT line, record FROM import_staging WHERE line > ? ORDER BY line LIMIT ?")?;
+    loop {
+        let batch = stmt
+            .query_map(params![last_line, BATCH_SIZE], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some((line, _)) = batch.last() else { break };
+        last_line = *line;
+
+        for (line, record) in batch {
+            let domain = serde_json::from_str(&record)
+                .map_err(|e| e.to_string())
+                .and_then(|record| reconciliation.read(&record))
+                .and_then(|raw| raw.into_domain().map_err(|e| format!("name: {e}")));
+            match domain {
+                Ok(domain) => merge_domain(tx, &domain, &mut summary)?,
+                Err(reason) => {
+                    rejects.reject(line as u64, &reason, &record)?;
+                    summary.rejected += 1;
+                }
+            }
+        }
+    }
+
+    tx.execute_batch("DROP TABLE import_staging")?;
+    rejects.finish()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mapping() {
+        let mapping = ColumnMapping::parse(&["name=Domain", "page_rank = PR"]).unwrap();
+        assert_eq!(mapping.name, "Domain");
+        assert_eq!(mapping.page_rank, "PR");
+        assert_eq!(mapping.valid, "valid");
+    }
+
+    #[test]
+    fn test_parse_mapping_rejects_unknown_field() {
+        assert!(matches!(ColumnMapping::parse(&["owner=Owner"]), Err(ImportError::UnknownField(_))));
+        assert!(matches!(ColumnMapping::parse(&["name"]), Err(ImportError::BadMapping(_))));
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert_eq!(parse_bool("Yes"), Ok(Some(true)));
+        assert_eq!(parse_bool("0"), Ok(Some(false)));
+        assert_eq!(parse_bool(""), Ok(None));
+        assert!(parse_bool("maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let date = NaiveDate::from_ymd_opt(2009, 3, 14);
+        assert_eq!(parse_date("2009-03-14"), Ok(date));
+        assert_eq!(parse_date("2009-03-14T10:00:00Z"), Ok(date));
+        assert_eq!(parse_date(""), Ok(None));
+        assert!(parse_date("14/03/2009").is_err());
+    }
+
+    #[test]
+    fn test_reconcile_columns() {
+        let columns = ["Name", "PR", "registrar"].map(String::from);
+        let mapping = ColumnMapping::parse(&["page_rank=pr"]).unwrap();
+
+        let reconciliation = Reconciliation::new(&columns, &mapping).unwrap();
+
+        assert_eq!(reconciliation.mapped, vec![("name", "Name".to_string()), ("page_rank", "PR".to_string())]);
+        assert_eq!(reconciliation.missing, vec!["available", "valid", "censored", "backlinks", "whois_created"]);
+        assert_eq!(reconciliation.extras, vec!["registrar".to_string()]);
+        assert!(matches!(Reconciliation::new(&columns[1..], &mapping), Err(ImportError::MissingColumn(_))));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::util::db::import::{import_relation, ColumnMapping, ImportError, ImportSummary};
+use crate::util::db::object_store::ObjectStore;
+use duckdb::Transaction;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ObjectFormat {
+    #[default]
+    Parquet,
+    Csv,
+    Json,
+}
+
+impl ObjectFormat {
+    /// Guess from the URL's file extension, ignoring a trailing `.gz` or
+    /// `.zst`; anything unrecognised is read as Parquet.
+    pub fn from_url(url: &str) -> Self {
+        let url = url.trim_end_matches(".gz").trim_end_matches(".zst");
+        match url.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()) {
+            Some(extension) if extension == "csv" || extension == "tsv" => ObjectFormat::Csv,
+            Some(extension) if extension == "json" || extension == "ndjson" || extension == "jsonl" => ObjectFormat::Json,
+            _ => ObjectFormat::Parquet,
+        }
+    }
+}
+
+/// Objects in S3, R2 or Azure Blob Storage. The URL may be a glob, e.g.
+/// `s3://dumps/2024-*/*.parquet`; Parquet keys with `key=value` segments
+/// are read as Hive partitions.
+#[derive(Debug, Clone)]
+pub struct ObjectStoreImport {
+    pub store: ObjectStore,
+    pub url: String,
+    pub format: ObjectFormat,
+    pub mapping: ColumnMapping,
+    /// Where rejected rows are written; defaults to `<store>.rejects.csv`.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl ObjectStoreImport {
+    pub fn new(store: ObjectStore, url: impl Into<String>) -> Self {
+        let url = url.into();
+        ObjectStoreImport {
+            store,
+            format: ObjectFormat::from_url(&url),
+            url,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| {
+            let store = match self.store {
+                ObjectStore::S3 => "s3",
+                ObjectStore::R2 => "r2",
+                ObjectStore::Azure => "azure",
+            };
+            format!("{store}.rejects.csv").into()
+        })
+    }
+
+    fn relation(&self) -> String {
+        let url = self.url.replace('\'', "''");
+        match self.format {
+            ObjectFormat::Parquet => format!("read_parquet('{url}', hive_partitioning = true, union_by_name = true)"),
+            ObjectFormat::Csv => format!("read_csv('{url}', header = true, union_by_name = true)"),
+            ObjectFormat::Json => format!("read_json_auto('{url}')"),
+        }
+    }
+}
+
+/// Register the store's credentials and merge the objects into the domains
+/// table.
+pub fn import_object_store(tx: &Transaction, import: &ObjectStoreImport) -> Result<ImportSummary, ImportError> {
+    import.store.configure(tx)?;
+    import_relation(tx, &import.relation(), &import.mapping, &import.reject_path())
+}
+
+/// The ignored test needs `docker compose up -d minio minio-setup`, the
+/// httpfs extension, and `S3_ACCESS_KEY_ID=minio`,
+/// `S3_SECRET_ACCESS_KEY=minio123`, `S3_ENDPOINT=localhost:9000`,
+/// `S3_URL_STYLE=path` and `S3_USE_SSL=false`. Run it with
+/// `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::{insert_domain, DuckStore, Domain};
+    use crate::util::db::export::{export_domains, DomainExport, ExportFormat};
+    use crate::util::db::store::DomainStore;
+
+    #[test]
+    fn test_format_from_url() {
+        assert_eq!(ObjectFormat::from_url("s3://b/dump.csv.gz"), ObjectFormat::Csv);
+        assert_eq!(ObjectFormat::from_url("az://c/records.NDJSON"), ObjectFormat::Json);
+        assert_eq!(ObjectFormat::from_url("r2://b/date=2024-01-01/*"), ObjectFormat::Parquet);
+    }
+
+    #[test]
+    fn test_relation() {
+        let import = ObjectStoreImport::new(ObjectStore::S3, "s3://b/it's.csv");
+        assert_eq!(import.relation(), "read_csv('s3://b/it''s.csv', header = true, union_by_name = true)");
+        assert_eq!(import.reject_path(), PathBuf::from("s3.rejects.csv"));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_minio_round_trip() {
+        let mut source = DuckStore::open_in_memory().unwrap();
+        let tx = source.connection().transaction().unwrap();
+        insert_domain(&tx, &Domain::new("example.com", true, Some(2.0))).unwrap();
+        tx.commit().unwrap();
+        let export = DomainExport { name_template: "domains.{ext}".to_string(), ..DomainExport::new("s3://domain-hunter/test", ExportFormat::Parquet) };
+        let manifest = export_domains(source.connection(), &export).unwrap();
+        assert_eq!(manifest.rows(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let import = ObjectStoreImport {
+            reject_path: Some(dir.path().join("rejects.csv")),
+            ..ObjectStoreImport::new(ObjectStore::S3, manifest.files[0].path.clone())
+        };
+        let mut target = DuckStore::open_in_memory().unwrap();
+        let tx = target.connection().transaction().unwrap();
+        let summary = import_object_store(&tx, &import).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(summary, ImportSummary { inserted: 1, updated: 0, rejected: 0 });
+        assert_eq!(target.list_domains().unwrap(), source.list_domains().unwrap());
+    }
+}
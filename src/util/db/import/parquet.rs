@@ -0,0 +1,134 @@
+use crate::util::db::import::{import_relation, ColumnMapping, ImportError, ImportSummary};
+use duckdb::Transaction;
+use std::path::{Path, PathBuf};
+
+/// One or more Parquet files: a single file, a glob such as
+/// `dumps/*.parquet`, or a directory of Hive partitions
+/// (`source=expired/date=2024-01-01/part-0.parquet`). Files may have
+/// different schemas; columns are matched by name across all of them.
+#[derive(Debug, Clone)]
+pub struct ParquetImport {
+    pub source: String,
+    /// Read `key=value` directory names as columns. Always on for directories.
+    pub hive_partitioning: bool,
+    pub mapping: ColumnMapping,
+    /// Where rejected rows are written; defaults to `<source>.rejects.csv`,
+    /// or `<dir>/parquet.rejects.csv` next to a glob.
+    pub reject_path: Option<PathBuf>,
+}
+
+impl ParquetImport {
+    pub fn new(source: impl Into<String>) -> Self {
+        ParquetImport {
+            source: source.into(),
+            hive_partitioning: false,
+            mapping: ColumnMapping::default(),
+            reject_path: None,
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        Path::new(&self.source).is_dir()
+    }
+
+    fn is_glob(&self) -> bool {
+        self.source.contains(['*', '?', '['])
+    }
+
+    /// The `read_parquet` call that scans every file in the source.
+    fn scan(&self) -> String {
+        let pattern = match self.is_dir() {
+            true => format!("{}/**/*.parquet", self.source.trim_end_matches('/')),
+            false => self.source.clone(),
+        };
+        format!(
+            "read_parquet('{}', hive_partitioning = {}, union_by_name = true)",
+            pattern.replace('\'', "''"),
+            self.hive_partitioning || self.is_dir(),
+        )
+    }
+
+    pub fn reject_path(&self) -> PathBuf {
+        self.reject_path.clone().unwrap_or_else(|| {
+            if self.is_glob() {
+                let root = self.source.split(['*', '?', '[']).next().unwrap_or_default();
+                let dir = match root.ends_with('/') {
+                    true => Path::new(root),
+                    false => Path::new(root).parent().unwrap_or(Path::new("")),
+                };
+                return dir.join("parquet.rejects.csv");
+            }
+            format!("{}.rejects.csv", self.source.trim_end_matches('/')).into()
+        })
+    }
+}
+
+/// Load `import.source` into the domains table; the reject file's `line`
+/// is the row number across all files.
+pub fn import_parquet(tx: &Transaction, import: &ParquetImport) -> Result<ImportSummary, ImportError> {
+    import_relation(tx, &import.scan(), &import.mapping, &import.reject_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+    use serde_json::json;
+    use std::fs;
+
+    fn write_parquet(store: &mut DuckStore, select: &str, path: &Path, options: &str) {
+        let sql = format!("COPY ({select}) TO '{}' (FORMAT PARQUET{options})", path.display());
+        store.connection().execute_batch(&sql).unwrap();
+    }
+
+    fn import(store: &mut DuckStore, import: &ParquetImport) -> ImportSummary {
+        let tx = store.connection().transaction().unwrap();
+        let summary = import_parquet(&tx, import).unwrap();
+        tx.commit().unwrap();
+        summary
+    }
+
+    #[test]
+    fn test_glob_with_differing_schemas() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = DuckStore::open_in_memory().unwrap();
+        write_parquet(&mut store, "SELECT 'a.com' AS name, 2.5 AS page_rank, 'gandi' AS registrar", &dir.path().join("a.parquet"), "");
+        write_parquet(&mut store, "SELECT * FROM (VALUES ('b.com', 40, true), ('bad name.com', 1, false)) t(name, backlinks, available)", &dir.path().join("b.parquet"), "");
+        let parquet = ParquetImport::new(format!("{}/*.parquet", dir.path().display()));
+
+        let summary = import(&mut store, &parquet);
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 1 });
+        let mut domains = store.list_domains().unwrap();
+        domains.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(domains[0].page_rank, Some(2.5));
+        assert_eq!(domains[0].metrics.backlinks, None);
+        assert_eq!(domains[0].extras, Some(json!({"registrar": "gandi"})));
+        assert!(domains[1].available);
+        assert_eq!(domains[1].metrics.backlinks, Some(40));
+        assert_eq!(domains[1].extras, None);
+        assert_eq!(parquet.reject_path(), dir.path().join("parquet.rejects.csv"));
+        assert!(fs::read_to_string(parquet.reject_path()).unwrap().contains("name: Domain name cannot contain spaces"));
+    }
+
+    #[test]
+    fn test_hive_partitioned_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let lake = dir.path().join("lake");
+        let mut store = DuckStore::open_in_memory().unwrap();
+        write_parquet(
+            &mut store,
+            "SELECT * FROM (VALUES ('a.com', 'expired', 'x'), ('b.com', 'auction', 'y')) t(name, source, batch)",
+            &lake,
+            ", PARTITION_BY (source)",
+        );
+        let parquet = ParquetImport::new(lake.display().to_string());
+
+        let summary = import(&mut store, &parquet);
+
+        assert_eq!(summary, ImportSummary { inserted: 2, updated: 0, rejected: 0 });
+        let domain = store.list_domains().unwrap().into_iter().find(|d| d.name == "b.com").unwrap();
+        assert_eq!(domain.extras, Some(json!({"source": "auction", "batch": "y"})));
+    }
+}
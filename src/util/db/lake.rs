@@ -0,0 +1,180 @@
+use crate::util::db::duck::Domain;
+use chrono::{NaiveDate, Utc};
+use dotenv::dotenv;
+use duckdb::{params, Connection};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LakeError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Columns stored in every file; `source` and `date` come from the path.
+const BATCH_TABLE: &str = "CREATE TEMP TABLE lake_batch (
+    id            UBIGINT,
+    name          VARCHAR,
+    available     BOOLEAN,
+    valid         BOOLEAN,
+    page_rank     DOUBLE,
+    censored      BOOLEAN,
+    backlinks     BIGINT,
+    whois_created DATE,
+    extras        JSON,
+    crawled_at    TIMESTAMP
+);";
+
+/// The lake root from `LAKE_PATH`, or `./data/lake`.
+pub fn default_root() -> PathBuf {
+    dotenv().ok();
+    env::var("LAKE_PATH").unwrap_or("./data/lake".to_string()).into()
+}
+
+/// A partition value safe to use as a directory name.
+fn partition_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// Streams one crawl's results into the lake: Hive-partitioned Parquet
+/// under a root directory, one file per page written:
+///
+/// ```text
+/// data/lake/source=expired-domains.co/date=2024-05-01/20240501T120000Z-00000.parquet
+/// ```
+///
+/// Files are written under a temporary name and renamed into place, so a
+/// reader never sees half a page.
+pub struct LakeWriter {
+    conn: Connection,
+    root: PathBuf,
+    source: String,
+    run: String,
+    files: Vec<PathBuf>,
+}
+
+impl LakeWriter {
+    /// Start a run for `source`; its files are named after the start time.
+    pub fn new(root: impl Into<PathBuf>, source: &str) -> Result<Self, LakeError> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(BATCH_TABLE)?;
+        Ok(LakeWriter {
+            conn,
+            root: root.into(),
+            source: partition_value(source),
+            run: Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+            files: Vec::new(),
+        })
+    }
+
+    /// Files written so far.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    fn partition(&self, date: NaiveDate) -> PathBuf {
+        self.root.join(format!("source={}", self.source)).join(format!("date={date}"))
+    }
+
+    /// Write `domains` as the next file of this run, partitioned by today's
+    /// date. Nothing is written for an empty page.
+    pub fn write(&mut self, domains: &[Domain]) -> Result<Option<PathBuf>, LakeError> {
+        if domains.is_empty() {
+            return Ok(None);
+        }
+        let now = Utc::now();
+        let dir = self.partition(now.date_naive());
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}-{:05}.parquet", self.run, self.files.len()));
+        let partial = path.with_extension("parquet.tmp");
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO lake_batch VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for domain in domains {
+                stmt.execute(params![
+                    domain.id(),
+                    domain.name,
+                    domain.available,
+                    domain.valid(),
+                    domain.page_rank,
+                    domain.censored(),
+                    domain.metrics.backlinks,
+                    domain.metrics.whois_created,
+                    domain.extras_json(),
+                    now.naive_utc(),
+                ])?;
+            }
+        }
+        tx.execute(
+            &format!(
+                "COPY lake_batch TO '{}' (FORMAT PARQUET, COMPRESSION ZSTD)",
+                partial.display().to_string().replace('\'', "''")
+            ),
+            [],
+        )?;
+        tx.execute("DELETE FROM lake_batch", [])?;
+        tx.commit()?;
+
+        fs::rename(&partial, &path)?;
+        self.files.push(path.clone());
+        Ok(Some(path))
+    }
+}
+
+/// The `read_parquet` call that reads the whole lake as one table, with
+/// `source` and `date` columns from the partition directories.
+pub fn lake_scan(root: &Path) -> String {
+    format!(
+        "read_parquet('{}/**/*.parquet', hive_partitioning = true, union_by_name = true)",
+        root.display().to_string().trim_end_matches('/').replace('\'', "''")
+    )
+}
+
+/// Register the lake as the temporary view `lake` for ad-hoc queries, e.g.
+/// `SELECT source, date, count(*) FROM lake GROUP BY ALL`.
+pub fn register_lake(conn: &Connection, root: &Path) -> Result<(), LakeError> {
+    conn.execute_batch(&format!("CREATE OR REPLACE TEMP VIEW lake AS SELECT * FROM {}", lake_scan(root)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_value() {
+        assert_eq!(partition_value("expired-domains.co"), "expired-domains.co");
+        assert_eq!(partition_value("a/b=c d"), "a_b_c_d");
+    }
+
+    #[test]
+    fn test_pages_read_back_as_one_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = LakeWriter::new(dir.path(), "expired-domains.co").unwrap();
+        writer.write(&[Domain::new("one.com", true, Some(1.0)), Domain::new("two.net", true, None)]).unwrap();
+        assert_eq!(writer.write(&[]).unwrap(), None);
+        let second = writer.write(&[Domain::new("three.org", false, None)]).unwrap().unwrap();
+
+        assert_eq!(writer.files().len(), 2);
+        let relative = second.strip_prefix(dir.path()).unwrap().display().to_string();
+        assert!(relative.starts_with(&format!("source=expired-domains.co/date={}/", Utc::now().date_naive())));
+        assert!(relative.ends_with("-00001.parquet"));
+
+        let conn = Connection::open_in_memory().unwrap();
+        register_lake(&conn, dir.path()).unwrap();
+        let (rows, source): (i64, String) = conn
+            .query_row("SELECT count(*), any_value(source) FROM lake", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((rows, source.as_str()), (3, "expired-domains.co"));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::util::db::duck::apply_migrations;
+use crate::util::db::migrations;
+use crate::util::db::store::{
+    StoreKind, CRAWL_CHECKPOINTS_TABLE, CRAWL_RUNS_TABLE, DOMAINS_TABLE, EXPORT_WATERMARKS_TABLE, IMPORT_SNAPSHOTS_TABLE, TOMBSTONES_TABLE,
+};
+use duckdb::{AccessMode, Config, Connection};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Columns the domains table has at the latest schema version, in order.
+const DOMAIN_COLUMNS: [&str; 11] = [
+    "id", "name", "available", "valid", "page_rank", "censored", "backlinks", "whois_created", "extras", "created_at", "updated_at",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0} does not exist; create it with `db create`")]
+    Missing(PathBuf),
+    #[error("{0} already exists")]
+    AlreadyExists(PathBuf),
+    #[error("{0} is locked by another process")]
+    Locked(PathBuf),
+    #[error("{path} is not a DuckDB database: {reason}")]
+    NotADatabase { path: PathBuf, reason: String },
+    #[error("{0} was not created by domain-hunter (no schema_migrations table)")]
+    Unmanaged(PathBuf),
+    #[error("{path} is at schema v{found}, newer than the v{supported} this build knows")]
+    TooNew { path: PathBuf, found: u32, supported: u32 },
+    #[error("{path} is at schema v{found}, expected v{expected}; run `db upgrade`")]
+    Outdated { path: PathBuf, found: u32, expected: u32 },
+    #[error("{path} does not match schema v{version}: {problems:?}")]
+    Constraint { path: PathBuf, version: u32, problems: Vec<String> },
+}
+
+/// The result of [`DatabaseManager::verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaReport {
+    pub version: u32,
+    pub latest: u32,
+    /// Tables, columns or constraints that differ from the latest schema;
+    /// only checked once the file is at the latest version.
+    pub problems: Vec<String>,
+}
+
+/// Creates, opens, verifies and upgrades the DuckDB store at one path. This
+/// is the single place that decides the default path and what counts as a
+/// usable database; [`db_init`](crate::util::db::duck::db_init) and
+/// [`DuckStore::open`](crate::util::db::duck::DuckStore::open) go through it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseManager {
+    path: PathBuf,
+}
+
+impl DatabaseManager {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        DatabaseManager { path: path.into() }
+    }
+
+    /// The store at `DUCKDB_PATH`, or `./data/domain-hunter.duckdb`.
+    pub fn from_env() -> Self {
+        Self::new(StoreKind::DuckDb.default_path())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Turn DuckDB's open errors into ones that say what is wrong.
+    fn connect(&self, config: Config) -> Result<Connection, DbError> {
+        if !self.path.exists() {
+            return Err(DbError::Missing(self.path.clone()));
+        }
+        Connection::open_with_flags(&self.path, config).map_err(|e| classify_open_error(&self.path, e))
+    }
+
+    /// Create a new database at the latest schema. Fails if the file exists.
+    pub fn create(&self) -> Result<Connection, DbError> {
+        if self.path.exists() {
+            return Err(DbError::AlreadyExists(self.path.clone()));
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut conn = Connection::open(&self.path).map_err(|e| classify_open_error(&self.path, e))?;
+        apply_migrations(&mut conn)?;
+        Ok(conn)
+    }
+
+    /// Open an existing database that is already at the latest schema and
+    /// passes [`verify`](Self::verify). Never changes the file.
+    pub fn open(&self) -> Result<Connection, DbError> {
+        let conn = self.connect(Config::default())?;
+        self.check(&report(&conn, &self.path)?)?;
+        Ok(conn)
+    }
+
+    /// Open the database, creating it or upgrading it in place as needed.
+    pub fn open_or_upgrade(&self) -> Result<Connection, DbError> {
+        if !self.path.exists() {
+            return self.create();
+        }
+        let mut conn = self.connect(Config::default())?;
+        upgrade(&mut conn, &self.path)?;
+        Ok(conn)
+    }
+
+    /// Inspect the database read-only.
+    pub fn verify(&self) -> Result<SchemaReport, DbError> {
+        let conn = self.connect(Config::default().access_mode(AccessMode::ReadOnly)?)?;
+        report(&conn, &self.path)
+    }
+
+    /// Apply pending migrations and verify the result. Returns the versions
+    /// before and after.
+    pub fn upgrade(&self) -> Result<(u32, u32), DbError> {
+        let mut conn = self.connect(Config::default())?;
+        upgrade(&mut conn, &self.path)
+    }
+
+    fn check(&self, report: &SchemaReport) -> Result<(), DbError> {
+        if report.version < report.latest {
+            return Err(DbError::Outdated { path: self.path.clone(), found: report.version, expected: report.latest });
+        }
+        if !report.problems.is_empty() {
+            return Err(DbError::Constraint { path: self.path.clone(), version: report.version, problems: report.problems.clone() });
+        }
+        Ok(())
+    }
+}
+
+fn classify_open_error(path: &Path, error: duckdb::Error) -> DbError {
+    let message = error.to_string();
+    if message.contains("Could not set lock") || message.contains("Conflicting lock") {
+        DbError::Locked(path.to_path_buf())
+    } else if message.contains("not a valid DuckDB database") {
+        DbError::NotADatabase { path: path.to_path_buf(), reason: message }
+    } else {
+        DbError::DuckDb(error)
+    }
+}
+
+fn upgrade(conn: &mut Connection, path: &Path) -> Result<(u32, u32), DbError> {
+    let before = report(conn, path)?.version;
+    apply_migrations(conn)?;
+    let after = report(conn, path)?;
+    if !after.problems.is_empty() {
+        return Err(DbError::Constraint { path: path.to_path_buf(), version: after.version, problems: after.problems });
+    }
+    Ok((before, after.version))
+}
+
+fn report(conn: &Connection, path: &Path) -> Result<SchemaReport, DbError> {
+    let tracked: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM duckdb_tables() WHERE table_name = 'schema_migrations' AND schema_name = 'main'",
+        [],
+        |row| row.get(0),
+    )?;
+    let latest = migrations::latest_version();
+    if !tracked {
+        // An empty file is as good as new, and one with only the domains
+        // tables predates migration tracking; both upgrade from v0.
+        let foreign: i64 = conn.query_row(
+            "SELECT count(*) FROM duckdb_tables() WHERE NOT (schema_name IN ('dev', 'prod') AND table_name = 'domains')",
+            [],
+            |row| row.get(0),
+        )?;
+        return match foreign {
+            0 => Ok(SchemaReport { version: 0, latest, problems: Vec::new() }),
+            _ => Err(DbError::Unmanaged(path.to_path_buf())),
+        };
+    }
+    let version = conn.query_row("SELECT coalesce(max(version), 0) FROM schema_migrations", [], |row| row.get::<_, i64>(0))? as u32;
+    if version > latest {
+        return Err(DbError::TooNew { path: path.to_path_buf(), found: version, supported: latest });
+    }
+    let problems = match version == latest {
+        true => constraint_problems(conn)?,
+        false => Vec::new(),
+    };
+    Ok(SchemaReport { version, latest, problems })
+}
+
+/// Differences between the database and what the migrations create: missing
+/// tables, the domains table's columns, and its key and name check.
+fn constraint_problems(conn: &Connection) -> Result<Vec<String>, DbError> {
+    let mut problems = Vec::new();
+    for table in [DOMAINS_TABLE, IMPORT_SNAPSHOTS_TABLE, TOMBSTONES_TABLE, EXPORT_WATERMARKS_TABLE, CRAWL_RUNS_TABLE, CRAWL_CHECKPOINTS_TABLE] {
+        let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+        let exists: bool = conn.query_row(
+            "SELECT count(*) > 0 FROM duckdb_tables() WHERE schema_name = ? AND table_name = ?",
+            [schema, name],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            problems.push(format!("missing table {table}"));
+        }
+    }
+
+    let (schema, name) = DOMAINS_TABLE.split_once('.').unwrap_or(("main", DOMAINS_TABLE));
+    let mut stmt = conn.prepare("SELECT column_name FROM duckdb_columns() WHERE schema_name = ? AND table_name = ? ORDER BY column_index")?;
+    let columns = stmt.query_map([schema, name], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+    if !columns.is_empty() && columns != DOMAIN_COLUMNS {
+        problems.push(format!("{DOMAINS_TABLE} has columns {columns:?}, expected {DOMAIN_COLUMNS:?}"));
+    }
+
+    let mut stmt = conn.prepare("SELECT constraint_type FROM duckdb_constraints() WHERE schema_name = ? AND table_name = ?")?;
+    let constraints = stmt.query_map([schema, name], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+    if !columns.is_empty() {
+        for expected in ["PRIMARY KEY", "CHECK"] {
+            if !constraints.iter().any(|constraint| constraint == expected) {
+                problems.push(format!("{DOMAINS_TABLE} has no {expected} constraint"));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::migrations::MIGRATIONS_TABLE;
+
+    #[test]
+    fn test_create_then_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = DatabaseManager::new(dir.path().join("nested/domain-hunter.duckdb"));
+
+        assert!(matches!(manager.open(), Err(DbError::Missing(_))));
+        drop(manager.create().unwrap());
+        assert!(matches!(manager.create(), Err(DbError::AlreadyExists(_))));
+        let report = manager.verify().unwrap();
+        assert_eq!((report.version, report.problems), (migrations::latest_version(), Vec::<String>::new()));
+        manager.open().unwrap();
+    }
+
+    #[test]
+    fn test_old_database_needs_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.duckdb");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATIONS_TABLE).unwrap();
+            let first = &migrations::MIGRATIONS[0];
+            conn.execute_batch(first.duckdb).unwrap();
+            conn.execute("INSERT INTO schema_migrations (version, description) VALUES (1, ?)", [first.description]).unwrap();
+        }
+        let manager = DatabaseManager::new(&path);
+
+        assert!(matches!(manager.open(), Err(DbError::Outdated { found: 1, .. })));
+        assert_eq!(manager.upgrade().unwrap(), (1, migrations::latest_version()));
+        manager.open().unwrap();
+    }
+
+    #[test]
+    fn test_untracked_domains_table_upgrades() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.duckdb");
+        Connection::open(&path).unwrap().execute_batch(migrations::MIGRATIONS[0].duckdb).unwrap();
+
+        assert_eq!(DatabaseManager::new(&path).upgrade().unwrap(), (0, migrations::latest_version()));
+    }
+
+    #[test]
+    fn test_wrong_files_are_named() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("notes.duckdb");
+        fs::write(&text, "not a database, just some text that is long enough to have a header").unwrap();
+        assert!(matches!(DatabaseManager::new(&text).open(), Err(DbError::NotADatabase { .. })));
+
+        let foreign = dir.path().join("foreign.duckdb");
+        Connection::open(&foreign).unwrap().execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        assert!(matches!(DatabaseManager::new(&foreign).open(), Err(DbError::Unmanaged(_))));
+
+        let future = dir.path().join("future.duckdb");
+        drop(DatabaseManager::new(&future).create().unwrap());
+        Connection::open(&future).unwrap().execute("INSERT INTO schema_migrations (version, description) VALUES (999, 'future')", []).unwrap();
+        assert!(matches!(DatabaseManager::new(&future).open(), Err(DbError::TooNew { found: 999, .. })));
+    }
+
+    #[test]
+    fn test_missing_constraint_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = DatabaseManager::new(dir.path().join("db.duckdb"));
+        drop(manager.create().unwrap());
+        Connection::open(manager.path()).unwrap().execute_batch(&format!("DROP TABLE {TOMBSTONES_TABLE}")).unwrap();
+
+        match manager.open() {
+            Err(DbError::Constraint { problems, .. }) => assert_eq!(problems, vec![format!("missing table {TOMBSTONES_TABLE}")]),
+            other => panic!("expected a constraint error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_classify_lock_error() {
+        let error = duckdb::Error::DuckDBFailure(
+            duckdb::ffi::Error::new(1),
+            Some("IO Error: Could not set lock on file \"db.duckdb\": Conflicting lock is held".to_string()),
+        );
+        assert!(matches!(classify_open_error(Path::new("db.duckdb"), error), DbError::Locked(_)));
+    }
+}
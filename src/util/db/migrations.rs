@@ -0,0 +1,103 @@
+/// A versioned schema change, written once per storage backend so every
+/// store ends up with the same tables.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub duckdb: &'static str,
+    pub sqlite: &'static str,
+    pub postgres: &'static str,
+}
+
+/// Every migration, in the order it must be applied.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create domains table",
+        duckdb: include_str!("migrations/0001_domains.duckdb.sql"),
+        sqlite: include_str!("migrations/0001_domains.sqlite.sql"),
+        postgres: include_str!("migrations/0001_domains.postgres.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "add backlinks and whois_created metrics",
+        duckdb: include_str!("migrations/0002_domain_metrics.duckdb.sql"),
+        sqlite: include_str!("migrations/0002_domain_metrics.sqlite.sql"),
+        postgres: include_str!("migrations/0002_domain_metrics.postgres.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "add extras column for unmapped source fields",
+        duckdb: include_str!("migrations/0003_domain_extras.duckdb.sql"),
+        sqlite: include_str!("migrations/0003_domain_extras.sqlite.sql"),
+        postgres: include_str!("migrations/0003_domain_extras.postgres.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "track snapshots imported from lakehouse tables",
+        duckdb: include_str!("migrations/0004_import_snapshots.duckdb.sql"),
+        sqlite: include_str!("migrations/0004_import_snapshots.sqlite.sql"),
+        postgres: include_str!("migrations/0004_import_snapshots.postgres.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "add change timestamps, tombstones and export watermarks",
+        duckdb: include_str!("migrations/0005_domain_changes.duckdb.sql"),
+        sqlite: include_str!("migrations/0005_domain_changes.sqlite.sql"),
+        postgres: include_str!("migrations/0005_domain_changes.postgres.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "track crawl runs and the pages each has stored",
+        duckdb: include_str!("migrations/0006_crawl_checkpoints.duckdb.sql"),
+        sqlite: include_str!("migrations/0006_crawl_checkpoints.sqlite.sql"),
+        postgres: include_str!("migrations/0006_crawl_checkpoints.postgres.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "store page_rank as DOUBLE instead of DECIMAL(18,3)",
+        duckdb: include_str!("migrations/0007_page_rank_double.duckdb.sql"),
+        sqlite: include_str!("migrations/0007_page_rank_double.sqlite.sql"),
+        postgres: include_str!("migrations/0007_page_rank_double.postgres.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "key export watermarks on target and filter",
+        duckdb: include_str!("migrations/0008_export_watermark_filters.duckdb.sql"),
+        sqlite: include_str!("migrations/0008_export_watermark_filters.sqlite.sql"),
+        postgres: include_str!("migrations/0008_export_watermark_filters.postgres.sql"),
+    },
+];
+
+/// Bookkeeping table recording which migrations a store has applied.
+pub const MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version     INTEGER PRIMARY KEY,
+    description VARCHAR NOT NULL,
+    applied_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);";
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Migrations newer than `current`, i.e. the ones still to be applied.
+pub fn pending(current: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |m| m.version > current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn test_pending() {
+        assert_eq!(pending(0).count(), MIGRATIONS.len());
+        assert_eq!(pending(latest_version()).count(), 0);
+    }
+}
@@ -0,0 +1,30 @@
+-- Postgres has no unsigned integers, so `id` holds the u64 name hash
+-- reinterpreted as a signed BIGINT.
+CREATE SCHEMA IF NOT EXISTS dev;
+CREATE SCHEMA IF NOT EXISTS prod;
+
+CREATE TABLE IF NOT EXISTS dev.domains (
+    id          BIGINT PRIMARY KEY,
+    name        VARCHAR CHECK (strpos(name, ' ') = 0),
+    available   BOOLEAN DEFAULT NULL,
+    valid       BOOLEAN DEFAULT NULL,
+    page_rank   DOUBLE PRECISION DEFAULT 0,
+    censored    BOOLEAN DEFAULT NULL
+);
+
+CREATE TABLE IF NOT EXISTS prod.domains (
+    id          BIGINT PRIMARY KEY,
+    name        VARCHAR CHECK (strpos(name, ' ') = 0),
+    available   BOOLEAN DEFAULT NULL,
+    valid       BOOLEAN DEFAULT NULL,
+    page_rank   DOUBLE PRECISION DEFAULT 0,
+    censored    BOOLEAN DEFAULT NULL
+);
+
+COMMENT ON TABLE dev.domains IS 'All domains from expired-domains.co';
+COMMENT ON COLUMN dev.domains.id IS 'random uuid';
+COMMENT ON COLUMN dev.domains.name IS 'domain name';
+COMMENT ON COLUMN dev.domains.available IS 'was domain available at the time of the scan';
+COMMENT ON COLUMN dev.domains.valid IS 'is domain still available';
+COMMENT ON COLUMN dev.domains.page_rank IS 'page rank score from expired-domains.co';
+COMMENT ON COLUMN dev.domains.censored IS 'did domain fail to pass the censor check (true == bad words found)';
@@ -0,0 +1,10 @@
+-- SQLite has no schemas, so a store file holds a single environment.
+-- `id` is the u64 name hash stored bit-for-bit as a signed INTEGER.
+CREATE TABLE IF NOT EXISTS domains (
+    id          INTEGER PRIMARY KEY,
+    name        TEXT CHECK (instr(name, ' ') = 0),
+    available   BOOLEAN DEFAULT NULL,
+    valid       BOOLEAN DEFAULT NULL,
+    page_rank   REAL DEFAULT 0,
+    censored    BOOLEAN DEFAULT NULL
+);
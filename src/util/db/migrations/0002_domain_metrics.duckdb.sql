@@ -0,0 +1,7 @@
+ALTER TABLE dev.domains ADD COLUMN backlinks BIGINT DEFAULT NULL;
+ALTER TABLE dev.domains ADD COLUMN whois_created DATE DEFAULT NULL;
+ALTER TABLE prod.domains ADD COLUMN backlinks BIGINT DEFAULT NULL;
+ALTER TABLE prod.domains ADD COLUMN whois_created DATE DEFAULT NULL;
+
+COMMENT ON COLUMN dev.domains.backlinks IS 'number of backlinks pointing at the domain';
+COMMENT ON COLUMN dev.domains.whois_created IS 'registration date from whois';
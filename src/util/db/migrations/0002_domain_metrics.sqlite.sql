@@ -0,0 +1,2 @@
+ALTER TABLE domains ADD COLUMN backlinks INTEGER DEFAULT NULL;
+ALTER TABLE domains ADD COLUMN whois_created DATE DEFAULT NULL;
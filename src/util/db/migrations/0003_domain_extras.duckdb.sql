@@ -0,0 +1,4 @@
+ALTER TABLE dev.domains ADD COLUMN extras JSON DEFAULT NULL;
+ALTER TABLE prod.domains ADD COLUMN extras JSON DEFAULT NULL;
+
+COMMENT ON COLUMN dev.domains.extras IS 'source fields with no matching column, as a JSON object';
@@ -0,0 +1 @@
+ALTER TABLE domains ADD COLUMN extras TEXT DEFAULT NULL;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS dev.import_snapshots (
+    source      TEXT PRIMARY KEY,
+    snapshot    BIGINT NOT NULL,
+    imported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS prod.import_snapshots (
+    source      TEXT PRIMARY KEY,
+    snapshot    BIGINT NOT NULL,
+    imported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+COMMENT ON TABLE dev.import_snapshots IS 'last snapshot or version imported from each lakehouse table';
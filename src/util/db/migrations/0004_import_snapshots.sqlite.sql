@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS import_snapshots (
+    source      TEXT PRIMARY KEY,
+    snapshot    INTEGER NOT NULL,
+    imported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
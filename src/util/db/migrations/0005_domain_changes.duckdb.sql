@@ -0,0 +1,33 @@
+ALTER TABLE dev.domains ADD COLUMN created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE dev.domains ADD COLUMN updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE prod.domains ADD COLUMN created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE prod.domains ADD COLUMN updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+
+CREATE TABLE IF NOT EXISTS dev.domain_tombstones (
+    id          UBIGINT PRIMARY KEY,
+    name        VARCHAR NOT NULL,
+    deleted_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS prod.domain_tombstones (
+    id          UBIGINT PRIMARY KEY,
+    name        VARCHAR NOT NULL,
+    deleted_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS dev.export_watermarks (
+    target      VARCHAR PRIMARY KEY,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS prod.export_watermarks (
+    target      VARCHAR PRIMARY KEY,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+COMMENT ON COLUMN dev.domains.created_at IS 'when the domain was first stored';
+COMMENT ON COLUMN dev.domains.updated_at IS 'when any column of the domain last changed';
+COMMENT ON TABLE dev.domain_tombstones IS 'deleted domains, kept so incremental exports can report the delete';
+COMMENT ON TABLE dev.export_watermarks IS 'latest change already written to each incremental export target';
@@ -0,0 +1,17 @@
+-- SQLite cannot add a column with a non-constant default, so existing rows
+-- are stamped once here and writers set updated_at themselves.
+ALTER TABLE domains ADD COLUMN created_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE domains ADD COLUMN updated_at TIMESTAMP DEFAULT NULL;
+UPDATE domains SET created_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP;
+
+CREATE TABLE IF NOT EXISTS domain_tombstones (
+    id          INTEGER PRIMARY KEY,
+    name        TEXT NOT NULL,
+    deleted_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS export_watermarks (
+    target      TEXT PRIMARY KEY,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
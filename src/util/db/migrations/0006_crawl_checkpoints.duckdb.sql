@@ -0,0 +1,34 @@
+CREATE TABLE IF NOT EXISTS dev.crawl_runs (
+    run_id      VARCHAR PRIMARY KEY,
+    source      VARCHAR NOT NULL,
+    status      VARCHAR NOT NULL,
+    started_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS dev.crawl_checkpoints (
+    run_id       VARCHAR NOT NULL,
+    page         INTEGER NOT NULL,
+    domains      INTEGER NOT NULL,
+    completed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (run_id, page)
+);
+
+CREATE TABLE IF NOT EXISTS prod.crawl_runs (
+    run_id      VARCHAR PRIMARY KEY,
+    source      VARCHAR NOT NULL,
+    status      VARCHAR NOT NULL,
+    started_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS prod.crawl_checkpoints (
+    run_id       VARCHAR NOT NULL,
+    page         INTEGER NOT NULL,
+    domains      INTEGER NOT NULL,
+    completed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (run_id, page)
+);
+
+COMMENT ON TABLE dev.crawl_runs IS 'one row per crawl, so an interrupted one can be resumed by id';
+COMMENT ON TABLE dev.crawl_checkpoints IS 'pages of a crawl already written to every sink';
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS crawl_runs (
+    run_id      TEXT PRIMARY KEY,
+    source      TEXT NOT NULL,
+    status      TEXT NOT NULL,
+    started_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS crawl_checkpoints (
+    run_id       TEXT NOT NULL,
+    page         INTEGER NOT NULL,
+    domains      INTEGER NOT NULL,
+    completed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (run_id, page)
+);
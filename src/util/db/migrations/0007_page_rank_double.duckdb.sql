@@ -0,0 +1,4 @@
+-- A bare DECIMAL is DECIMAL(18,3), which rounded page ranks to three places
+-- and made copies from the other stores lossy.
+ALTER TABLE dev.domains ALTER COLUMN page_rank SET DATA TYPE DOUBLE;
+ALTER TABLE prod.domains ALTER COLUMN page_rank SET DATA TYPE DOUBLE;
@@ -0,0 +1,3 @@
+-- page_rank has been REAL here from the start; the version is kept in step
+-- with DuckDB, whose column was DECIMAL(18,3).
+SELECT 1;
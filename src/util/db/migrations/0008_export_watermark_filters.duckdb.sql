@@ -0,0 +1,24 @@
+-- Watermarks were kept per target alone, so two exports to one target with
+-- different filters skipped each other's changes. Which filter an old
+-- watermark belonged to is unknown, so they are dropped and the next
+-- incremental export to each target starts over.
+DROP TABLE IF EXISTS dev.export_watermarks;
+DROP TABLE IF EXISTS prod.export_watermarks;
+
+CREATE TABLE dev.export_watermarks (
+    target      VARCHAR NOT NULL,
+    filter      VARCHAR NOT NULL,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (target, filter)
+);
+
+CREATE TABLE prod.export_watermarks (
+    target      VARCHAR NOT NULL,
+    filter      VARCHAR NOT NULL,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (target, filter)
+);
+
+COMMENT ON TABLE dev.export_watermarks IS 'end of the changes already written to each incremental export target, per filter';
@@ -0,0 +1,21 @@
+-- See the DuckDB migration: old watermarks cannot be matched to a filter.
+DROP TABLE IF EXISTS dev.export_watermarks;
+DROP TABLE IF EXISTS prod.export_watermarks;
+
+CREATE TABLE dev.export_watermarks (
+    target      TEXT NOT NULL,
+    filter      TEXT NOT NULL,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (target, filter)
+);
+
+CREATE TABLE prod.export_watermarks (
+    target      TEXT NOT NULL,
+    filter      TEXT NOT NULL,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (target, filter)
+);
+
+COMMENT ON TABLE dev.export_watermarks IS 'end of the changes already written to each incremental export target, per filter';
@@ -0,0 +1,10 @@
+-- See the DuckDB migration: old watermarks cannot be matched to a filter.
+DROP TABLE IF EXISTS export_watermarks;
+
+CREATE TABLE export_watermarks (
+    target      TEXT NOT NULL,
+    filter      TEXT NOT NULL,
+    watermark   TIMESTAMP NOT NULL,
+    exported_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (target, filter)
+);
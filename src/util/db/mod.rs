@@ -1 +1,14 @@
-pub mod duck;
\ No newline at end of file
+pub mod actor;
+pub mod backup;
+pub mod checkpoint;
+pub mod duck;
+pub mod export;
+pub mod import;
+pub mod lake;
+pub mod manager;
+pub mod migrations;
+pub mod object_store;
+pub mod postgres;
+pub mod search;
+pub mod sqlite;
+pub mod store;
\ No newline at end of file
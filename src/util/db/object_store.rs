@@ -0,0 +1,136 @@
+use duckdb::Connection;
+use std::env;
+
+/// An object store DuckDB reads and writes through its httpfs or azure
+/// extension. Credentials and endpoints come from the environment, so the
+/// same code runs against MinIO or Azurite locally:
+///
+/// - S3: `S3_ACCESS_KEY_ID`, `S3_SECRET_ACCESS_KEY`, `S3_REGION`,
+///   `S3_ENDPOINT` (e.g. `localhost:9000`), `S3_URL_STYLE` (`path` for
+///   MinIO) and `S3_USE_SSL`.
+/// - R2: `R2_ACCOUNT_ID`, `R2_ACCESS_KEY_ID`, `R2_SECRET_ACCESS_KEY` and
+///   optionally `R2_ENDPOINT`.
+/// - Azure: `AZURE_STORAGE_CONNECTION_STRING`, or `AZURE_STORAGE_ACCOUNT` to
+///   use the Azure credential chain. DuckDB can only read from Azure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ObjectStore {
+    S3,
+    R2,
+    Azure,
+}
+
+impl ObjectStore {
+    /// The store a URL points at, from its scheme: `s3://`, `r2://`,
+    /// `az://` or `azure://`.
+    pub fn from_url(url: &str) -> Option<Self> {
+        match url.split_once("://")?.0 {
+            "s3" => Some(ObjectStore::S3),
+            "r2" => Some(ObjectStore::R2),
+            "az" | "azure" => Some(ObjectStore::Azure),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ObjectStore::S3 | ObjectStore::R2 => "httpfs",
+            ObjectStore::Azure => "azure",
+        }
+    }
+
+    /// A temporary secret holding whichever settings `var` provides.
+    fn secret_sql(&self, var: impl Fn(&str) -> Option<String>) -> String {
+        let mut options = Vec::new();
+        let mut option = |key: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                options.push(format!("{key} '{}'", value.replace('\'', "''")));
+            }
+        };
+        let (name, kind) = match self {
+            ObjectStore::S3 => {
+                option("KEY_ID", var("S3_ACCESS_KEY_ID"));
+                option("SECRET", var("S3_SECRET_ACCESS_KEY"));
+                option("REGION", var("S3_REGION"));
+                option("ENDPOINT", var("S3_ENDPOINT"));
+                option("URL_STYLE", var("S3_URL_STYLE"));
+                ("domain_hunter_s3", "S3")
+            },
+            ObjectStore::R2 => {
+                option("KEY_ID", var("R2_ACCESS_KEY_ID"));
+                option("SECRET", var("R2_SECRET_ACCESS_KEY"));
+                option("ACCOUNT_ID", var("R2_ACCOUNT_ID"));
+                option("ENDPOINT", var("R2_ENDPOINT"));
+                ("domain_hunter_r2", "R2")
+            },
+            ObjectStore::Azure => {
+                match var("AZURE_STORAGE_CONNECTION_STRING").filter(|value| !value.is_empty()) {
+                    Some(connection) => option("CONNECTION_STRING", Some(connection)),
+                    None => {
+                        option("PROVIDER", Some("credential_chain".to_string()));
+                        option("ACCOUNT_NAME", var("AZURE_STORAGE_ACCOUNT"));
+                    },
+                }
+                ("domain_hunter_azure", "AZURE")
+            },
+        };
+        // USE_SSL is a boolean, so it is not quoted like the rest.
+        if let (ObjectStore::S3, Some(ssl)) = (self, var("S3_USE_SSL").filter(|value| !value.is_empty())) {
+            options.push(format!("USE_SSL {}", matches!(ssl.to_ascii_lowercase().as_str(), "true" | "1" | "yes")));
+        }
+
+        let options = options.iter().map(|option| format!(", {option}")).collect::<String>();
+        format!("CREATE OR REPLACE SECRET {name} (TYPE {kind}{options})")
+    }
+
+    /// Load the store's extension and register its credentials for the
+    /// rest of the session.
+    pub fn configure(&self, conn: &Connection) -> duckdb::Result<()> {
+        dotenv::dotenv().ok();
+        let extension = self.extension();
+        conn.execute_batch(&format!("INSTALL {extension}"))?;
+        conn.execute_batch(&format!("LOAD {extension}"))?;
+        conn.execute_batch(&self.secret_sql(|key| env::var(key).ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn secret(store: ObjectStore, vars: &[(&str, &str)]) -> String {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        store.secret_sql(|key| vars.get(key).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(ObjectStore::from_url("s3://bucket/domains.parquet"), Some(ObjectStore::S3));
+        assert_eq!(ObjectStore::from_url("r2://bucket/x"), Some(ObjectStore::R2));
+        assert_eq!(ObjectStore::from_url("az://container/x"), Some(ObjectStore::Azure));
+        assert_eq!(ObjectStore::from_url("./data/domains.parquet"), None);
+    }
+
+    #[test]
+    fn test_secret_for_minio() {
+        let sql = secret(ObjectStore::S3, &[
+            ("S3_ACCESS_KEY_ID", "minio"),
+            ("S3_SECRET_ACCESS_KEY", "minio123"),
+            ("S3_ENDPOINT", "localhost:9000"),
+            ("S3_URL_STYLE", "path"),
+            ("S3_USE_SSL", "false"),
+            ("S3_REGION", ""),
+        ]);
+
+        assert_eq!(sql, "CREATE OR REPLACE SECRET domain_hunter_s3 (TYPE S3, KEY_ID 'minio', SECRET 'minio123', ENDPOINT 'localhost:9000', URL_STYLE 'path', USE_SSL false)");
+    }
+
+    #[test]
+    fn test_secret_for_azure() {
+        let azurite = secret(ObjectStore::Azure, &[("AZURE_STORAGE_CONNECTION_STRING", "AccountName=devstoreaccount1;Key='x'")]);
+        assert_eq!(azurite, "CREATE OR REPLACE SECRET domain_hunter_azure (TYPE AZURE, CONNECTION_STRING 'AccountName=devstoreaccount1;Key=''x''')");
+
+        let chain = secret(ObjectStore::Azure, &[("AZURE_STORAGE_ACCOUNT", "domains")]);
+        assert_eq!(chain, "CREATE OR REPLACE SECRET domain_hunter_azure (TYPE AZURE, PROVIDER 'credential_chain', ACCOUNT_NAME 'domains')");
+    }
+}
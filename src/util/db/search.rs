@@ -0,0 +1,321 @@
+use crate::util::db::duck::{read_domain, Domain, DOMAIN_COLUMNS};
+use crate::util::db::export::{quote, DomainFilter};
+use crate::util::db::store::{DOMAINS_TABLE, SEARCH_TABLE};
+use duckdb::{params, Connection};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("duckdb: {0}")]
+    DuckDb(#[from] duckdb::Error),
+    #[error("search query has no letters or digits")]
+    EmptyQuery,
+    #[error("invalid regex '{pattern}': {reason}")]
+    InvalidRegex { pattern: String, reason: String },
+    #[error(transparent)]
+    Filter(#[from] crate::util::db::export::ExportError),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SearchMode {
+    /// Names containing the query's words or fragments of them, e.g. `coffee bean`
+    #[default]
+    Keyword,
+    /// Names spelled like the query, e.g. `coffeebean` finds `cofeebeans.com`
+    Fuzzy,
+    /// Names matching a regular expression, e.g. `^[a-z]{4}\.com$`
+    Regex,
+}
+
+/// A search over domain names; hits come back best first.
+#[derive(Debug, Clone)]
+pub struct DomainSearch {
+    pub query: String,
+    pub mode: SearchMode,
+    pub limit: usize,
+    /// Drop hits scoring below this. Scores run from 0 to 1; fuzzy searches
+    /// default to a 0.5 cut-off and the other modes keep every match.
+    pub min_score: Option<f64>,
+    pub filter: DomainFilter,
+}
+
+impl DomainSearch {
+    pub fn new(query: impl Into<String>, mode: SearchMode) -> Self {
+        DomainSearch {
+            query: query.into(),
+            mode,
+            limit: 50,
+            min_score: None,
+            filter: DomainFilter::default(),
+        }
+    }
+
+    fn min_score(&self) -> Option<f64> {
+        match self.mode {
+            SearchMode::Fuzzy => self.min_score.or(Some(0.5)),
+            _ => self.min_score,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub domain: Domain,
+    pub score: f64,
+}
+
+/// The name without its TLD, lowercased: `Coffee-Bean.co.uk` is `coffee-bean.co`.
+fn label(name: &str) -> String {
+    name.rsplit_once('.').map_or(name, |(label, _)| label).to_lowercase()
+}
+
+/// Split text into lowercase words at anything other than a letter or
+/// digit, and where letters meet digits: `coffee-bean2go` is
+/// `coffee`, `bean`, `2`, `go`.
+pub fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    for c in text.to_lowercase().chars() {
+        if !c.is_alphanumeric() {
+            previous = None;
+            continue;
+        }
+        match previous {
+            Some(p) if p.is_ascii_digit() == c.is_ascii_digit() => words.last_mut().unwrap().push(c),
+            _ => words.push(c.to_string()),
+        }
+        previous = Some(c);
+    }
+    words
+}
+
+/// The distinct three-character windows of `word`, none if it is shorter.
+fn trigrams(word: &str) -> Vec<String> {
+    let chars = word.chars().collect::<Vec<_>>();
+    let mut trigrams = chars.windows(3).map(|window| window.iter().collect::<String>()).collect::<Vec<_>>();
+    trigrams.sort();
+    trigrams.dedup();
+    trigrams
+}
+
+/// What the full-text index holds for a name: its words, then the trigrams
+/// of each, so a fragment like `bean` also finds `coffeebeans.com`.
+pub fn search_document(name: &str) -> String {
+    let words = words(&label(name));
+    let trigrams = words.iter().flat_map(|word| trigrams(word));
+    words.iter().cloned().chain(trigrams).collect::<Vec<_>>().join(" ")
+}
+
+/// The schema DuckDB's `fts` extension creates for the index on [`SEARCH_TABLE`].
+fn fts_schema() -> String {
+    format!("fts_{}", SEARCH_TABLE.replace('.', "_"))
+}
+
+/// (Re)build the full-text index keyword searches rank with, from every
+/// name in the domains table, and return how many names it holds. Installs
+/// the `fts` extension if needed, which is the only step that may download
+/// it. The index is a snapshot: domains added later are matched by
+/// substring, as if there were no index, until it is rebuilt.
+pub fn build_search_index(conn: &mut Connection) -> Result<usize, SearchError> {
+    conn.execute_batch("INSTALL fts; LOAD fts")?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(&format!("CREATE OR REPLACE TABLE {SEARCH_TABLE} (id UBIGINT PRIMARY KEY, document VARCHAR)"))?;
+    let names = {
+        let mut stmt = tx.prepare(&format!("SELECT id, name FROM {DOMAINS_TABLE}"))?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+    {
+        let mut insert = tx.prepare(&format!("INSERT INTO {SEARCH_TABLE} VALUES (?, ?)"))?;
+        for (id, name) in &names {
+            insert.execute(params![id, search_document(name)])?;
+        }
+    }
+    // Documents are already split and lowercased; keep digits, which the
+    // default `ignore` pattern would strip.
+    tx.execute_batch(&format!(
+        "PRAGMA create_fts_index('{SEARCH_TABLE}', 'id', 'document', stemmer = 'none', stopwords = 'none', ignore = '[^a-z0-9]+', overwrite = 1)"
+    ))?;
+    tx.commit()?;
+    Ok(names.len())
+}
+
+/// Whether [`build_search_index`] has run and the extension it installed
+/// loads.
+fn has_search_index(conn: &Connection) -> Result<bool, duckdb::Error> {
+    let exists: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM duckdb_schemas() WHERE schema_name = ?",
+        [fts_schema()],
+        |row| row.get(0),
+    )?;
+    Ok(exists && conn.execute_batch("LOAD fts").is_ok())
+}
+
+/// SQL list literal of `values`.
+fn list(values: &[String]) -> String {
+    format!("[{}]::VARCHAR[]", values.iter().map(|value| quote(value)).collect::<Vec<_>>().join(", "))
+}
+
+/// A `score` expression over `domains` and a predicate every hit satisfies.
+fn scoring(conn: &Connection, search: &DomainSearch) -> Result<(String, String), SearchError> {
+    let label = r"lower(regexp_replace(d.name, '\.[^.]*$', ''))";
+    match search.mode {
+        SearchMode::Keyword => {
+            let words = words(&search.query);
+            if words.is_empty() {
+                return Err(SearchError::EmptyQuery);
+            }
+            // Without the index, every word must appear and shorter names,
+            // where the words make up more of the name, rank first.
+            let length = words.iter().map(|word| word.chars().count()).sum::<usize>();
+            let substring_score = format!("least(1.0, {length} / greatest(length({label}), 1))");
+            let substring = words.iter().map(|word| format!("contains({label}, {})", quote(word))).collect::<Vec<_>>().join(" AND ");
+            if !has_search_index(conn)? {
+                return Ok((substring_score, substring));
+            }
+            // BM25 over words and trigrams ranks whole-word matches above
+            // fragments, and rare fragments above common ones. Names stored
+            // since the index was built are not in it, so they fall back to
+            // substring matching. BM25 is unbounded, so it is taken relative
+            // to the best indexed hit to rank on the substring score's 0-1
+            // scale.
+            let terms = words.iter().cloned().chain(words.iter().flat_map(|word| trigrams(word))).collect::<Vec<_>>();
+            let bm25 = format!("{}.match_bm25(d.id, {})", fts_schema(), quote(&terms.join(" ")));
+            let indexed = format!("d.id IN (SELECT id FROM {SEARCH_TABLE})");
+            Ok((
+                format!("CASE WHEN {indexed} THEN {bm25} / max({bm25}) OVER () ELSE {substring_score} END"),
+                format!("CASE WHEN {indexed} THEN {bm25} IS NOT NULL ELSE {substring} END"),
+            ))
+        },
+        SearchMode::Fuzzy => {
+            let query = words(&search.query).concat();
+            if query.is_empty() {
+                return Err(SearchError::EmptyQuery);
+            }
+            // The better of trigram overlap, which forgives reordered parts,
+            // and edit distance, which forgives typos in short names.
+            let name = format!("regexp_replace({label}, '[^[:alnum:]]', '', 'g')");
+            let name_trigrams = format!("list_distinct(list_transform(range(1, length({name}) - 1), i -> substr({name}, i, 3)))");
+            let query_trigrams = list(&trigrams(&query));
+            let shared = format!("length(list_intersect({name_trigrams}, {query_trigrams}))");
+            let trigram = format!(
+                "coalesce({shared} / nullif(length({name_trigrams}) + length({query_trigrams}) - {shared}, 0), 0)"
+            );
+            let edit = format!(
+                "1.0 - levenshtein({name}, {q}) / greatest(length({name}), length({q}))",
+                q = quote(&query)
+            );
+            Ok((format!("greatest({trigram}, {edit})"), "true".to_string()))
+        },
+        SearchMode::Regex => {
+            if let Err(e) = conn.query_row("SELECT regexp_matches('', ?)", [&search.query], |row| row.get::<_, bool>(0)) {
+                return Err(SearchError::InvalidRegex { pattern: search.query.clone(), reason: e.to_string() });
+            }
+            Ok(("1.0".to_string(), format!("regexp_matches(d.name, {})", quote(&search.query))))
+        },
+    }
+}
+
+/// The SQL a search runs: domains passing the filter and the mode's
+/// predicate, best score first, then by page rank and name.
+fn search_sql(conn: &Connection, search: &DomainSearch) -> Result<String, SearchError> {
+    search.filter.check(conn)?;
+    let (score, predicate) = scoring(conn, search)?;
+    let min_score = search.min_score().map_or("true".to_string(), |min| format!("score >= {min}"));
+    Ok(format!(
+        "SELECT {DOMAIN_COLUMNS}, score FROM (
+            SELECT d.*, CAST({score} AS DOUBLE) AS score
+            FROM {DOMAINS_TABLE} AS d
+            WHERE ({filter}) AND {predicate}
+         ) WHERE {min_score}
+         ORDER BY score DESC, page_rank DESC NULLS LAST, name
+         LIMIT {limit}",
+        filter = search.filter.to_sql(),
+        limit = search.limit,
+    ))
+}
+
+/// Search domain names, see [`SearchMode`]. Keyword searches use the index
+/// from [`build_search_index`] when there is one and plain substring
+/// matching otherwise.
+pub fn search_domains(conn: &Connection, search: &DomainSearch) -> Result<Vec<SearchHit>, SearchError> {
+    let mut stmt = conn.prepare(&search_sql(conn, search)?)?;
+    let hits = stmt.query_map([], |row| Ok(SearchHit { domain: read_domain(row)?, score: row.get(9)? }))?;
+    Ok(hits.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::duck::DuckStore;
+    use crate::util::db::store::DomainStore;
+
+    fn store(names: &[&str]) -> DuckStore {
+        let mut store = DuckStore::open_in_memory().unwrap();
+        store.insert_domains(&names.iter().map(|name| Domain::new(name, true, None)).collect::<Vec<_>>()).unwrap();
+        store
+    }
+
+    fn names(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.domain.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_words_and_documents() {
+        assert_eq!(words("Coffee-Bean2go"), vec!["coffee", "bean", "2", "go"]);
+        assert_eq!(search_document("bean2go.com"), "bean 2 go bea ean");
+    }
+
+    #[test]
+    fn test_keyword_search_without_index() {
+        let mut store = store(&["coffee-bean.com", "coffeebeanshop.net", "beancoffee.org", "tea.com"]);
+        let hits = search_domains(store.connection(), &DomainSearch::new("bean coffee", SearchMode::Keyword)).unwrap();
+        assert_eq!(names(&hits), vec!["beancoffee.org", "coffee-bean.com", "coffeebeanshop.net"]);
+        assert!(matches!(
+            search_domains(store.connection(), &DomainSearch::new("--", SearchMode::Keyword)),
+            Err(SearchError::EmptyQuery)
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy_search_ranks_by_similarity() {
+        let mut store = store(&["coffeebean.com", "cofeebeans.net", "beancoffee.org", "teapot.com"]);
+        let hits = search_domains(store.connection(), &DomainSearch::new("coffeebean", SearchMode::Fuzzy)).unwrap();
+        assert_eq!(names(&hits), vec!["coffeebean.com", "cofeebeans.net", "beancoffee.org"]);
+        assert_eq!(hits[0].score, 1.0);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn test_regex_search_and_filter() {
+        let mut store = store(&["abcd.com", "abcd.net", "abcde.com"]);
+        let search = DomainSearch {
+            filter: DomainFilter { tlds: vec!["com".to_string()], ..DomainFilter::default() },
+            ..DomainSearch::new(r"^[a-z]{4}\.", SearchMode::Regex)
+        };
+        assert_eq!(names(&search_domains(store.connection(), &search).unwrap()), vec!["abcd.com"]);
+        assert!(matches!(
+            search_domains(store.connection(), &DomainSearch::new("(", SearchMode::Regex)),
+            Err(SearchError::InvalidRegex { .. })
+        ));
+    }
+
+    /// Building the index installs `fts`, which needs a network connection
+    /// the first time.
+    #[test]
+    #[ignore = "needs the fts extension"]
+    fn test_keyword_search_with_index() {
+        let mut store = store(&["coffeebean.com", "coffee.com", "tea.com"]);
+        assert_eq!(build_search_index(store.connection()).unwrap(), 3);
+        let hits = search_domains(store.connection(), &DomainSearch::new("coffee", SearchMode::Keyword)).unwrap();
+        assert_eq!(names(&hits), vec!["coffee.com", "coffeebean.com"]);
+
+        // Stored after the index was built, yet still found, and ranked on
+        // the same scale: below the exact match, whose BM25 is the best.
+        store.insert_domains(&[Domain::new("coffeeshop.net", true, None)]).unwrap();
+        let hits = search_domains(store.connection(), &DomainSearch::new("coffee", SearchMode::Keyword)).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert_eq!(names(&hits)[0], "coffee.com");
+        assert!(names(&hits).contains(&"coffeeshop.net"));
+        assert!(hits.iter().all(|hit| hit.score > 0.0 && hit.score <= 1.0), "{hits:?}");
+    }
+}
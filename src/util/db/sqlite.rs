@@ -0,0 +1,194 @@
+use crate::util::db::duck::{Domain, DomainMetrics};
+use crate::util::db::migrations::{self, MIGRATIONS_TABLE};
+use crate::util::db::store::{DomainStore, StoreError};
+use rusqlite::{params, Connection, Result};
+use std::fs;
+use std::path::Path;
+
+/// [`DomainStore`] backed by a single SQLite file.
+///
+/// SQLite only has signed 64-bit integers, so domain ids are stored as the
+/// same 64 bits reinterpreted as `i64` and converted back on read.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    pub fn from_connection(mut conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(MIGRATIONS_TABLE)?;
+        let current = sqlite_schema_version(&conn)?;
+        for migration in migrations::pending(current) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration.sqlite)?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, description) VALUES (?, ?)",
+                params![migration.version, migration.description],
+            )?;
+            tx.commit()?;
+        }
+        Ok(SqliteStore { conn })
+    }
+}
+
+fn upsert_domains(conn: &Connection, domains: &[Domain]) -> Result<()> {
+    // Like DuckDB, only bump updated_at when something changed.
+    let mut stmt = conn.prepare(
+        "INSERT INTO domains (id, name, available, valid, page_rank, censored, backlinks, whois_created, extras, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+         ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name,
+            available = excluded.available,
+            valid = excluded.valid,
+            page_rank = excluded.page_rank,
+            censored = excluded.censored,
+            backlinks = excluded.backlinks,
+            whois_created = excluded.whois_created,
+            extras = excluded.extras,
+            updated_at = CURRENT_TIMESTAMP
+         WHERE (name, available, valid, page_rank, censored, backlinks, whois_created, extras)
+            IS NOT (excluded.name, excluded.available, excluded.valid, excluded.page_rank, excluded.censored,
+                    excluded.backlinks, excluded.whois_created, excluded.extras)",
+    )?;
+    for domain in domains {
+        stmt.execute(params![
+            domain.id().map(|id| id as i64),
+            domain.name,
+            domain.available,
+            domain.valid(),
+            domain.page_rank,
+            domain.censored(),
+            domain.metrics.backlinks,
+            domain.metrics.whois_created,
+            domain.extras_json(),
+        ])?;
+    }
+    Ok(())
+}
+
+fn select_domains(conn: &Connection) -> Result<Vec<Domain>> {
+    let mut stmt = conn.prepare("SELECT id, name, available, valid, page_rank, censored, backlinks, whois_created, extras FROM domains")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Domain::from_parts(
+            row.get::<_, Option<i64>>(0)?.map(|id| id as u64),
+            row.get(1)?,
+            row.get::<_, Option<bool>>(2)?.unwrap_or_default(),
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+        )
+        .with_metrics(DomainMetrics {
+            backlinks: row.get(6)?,
+            whois_created: row.get(7)?,
+        })
+        .with_extras(Domain::parse_extras(row.get(8)?)))
+    })?;
+    rows.collect()
+}
+
+fn sqlite_schema_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("SELECT coalesce(max(version), 0) FROM schema_migrations", [], |row| row.get(0))
+}
+
+impl DomainStore for SqliteStore {
+    fn insert_domains(&mut self, domains: &[Domain]) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        upsert_domains(&tx, domains)?;
+        tx.commit()?;
+        Ok(domains.len())
+    }
+
+    fn insert_domains_checked(&mut self, domains: &[Domain], check: &dyn Fn(&[Domain]) -> Result<(), StoreError>) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        upsert_domains(&tx, domains)?;
+        check(&select_domains(&tx)?)?;
+        tx.commit()?;
+        Ok(domains.len())
+    }
+
+    fn list_domains(&self) -> Result<Vec<Domain>, StoreError> {
+        Ok(select_domains(&self.conn)?)
+    }
+
+    fn delete_domains(&mut self, names: &[String]) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        let mut deleted = 0;
+        for name in names {
+            tx.execute(
+                "INSERT OR REPLACE INTO domain_tombstones (id, name, deleted_at)
+                 SELECT id, name, CURRENT_TIMESTAMP FROM domains WHERE name = ?",
+                params![name],
+            )?;
+            deleted += tx.execute("DELETE FROM domains WHERE name = ?", params![name])?;
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn list_valid_domains(&self) -> Result<Vec<String>, StoreError> {
+        let mut stmt = self.conn.prepare("SELECT name FROM domains WHERE valid = true AND page_rank > 0 AND (name LIKE '%.com' OR name LIKE '%.net' OR name LIKE '%.org') AND censored = false")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<_>>>()?)
+    }
+
+    fn count_domains(&self) -> Result<usize, StoreError> {
+        let count: i64 = self.conn.query_row("SELECT count(*) FROM domains", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn schema_version(&self) -> Result<u32, StoreError> {
+        Ok(sqlite_schema_version(&self.conn)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_above_i64_max_round_trip() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let domain = Domain::from_parts(Some(u64::MAX), "test.com".to_string(), true, None, None, None);
+        store.insert_domains(std::slice::from_ref(&domain)).unwrap();
+
+        assert_eq!(store.list_domains().unwrap(), vec![domain]);
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let store = SqliteStore::from_connection(store.conn).unwrap();
+
+        assert_eq!(store.schema_version().unwrap(), migrations::latest_version());
+    }
+
+    #[test]
+    fn test_delete_leaves_tombstone() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.insert_domains(&[Domain::new("gone.com", true, None), Domain::new("kept.com", true, None)]).unwrap();
+
+        assert_eq!(store.delete_domains(&["gone.com".to_string(), "never.com".to_string()]).unwrap(), 1);
+        let tombstones: Vec<String> = store.conn.prepare("SELECT name FROM domain_tombstones").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(tombstones, vec!["gone.com".to_string()]);
+        assert_eq!(store.count_domains().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_insert_bad_domain_is_rejected() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let domain = Domain::from_parts(Some(1), "test com".to_string(), true, None, None, None);
+
+        assert!(store.insert_domains(&[domain]).is_err());
+    }
+}
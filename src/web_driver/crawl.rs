@@ -0,0 +1,412 @@
+use crate::util::db::duck::Domain;
+use crate::web_driver::layout::{self, LayoutContract, LayoutDrift};
+use crate::web_driver::replay::{self, ReplayServer};
+use crate::web_driver::retry::{self, RetryConfig};
+use crate::web_driver::source::{Source, SourceError};
+
+use async_trait::async_trait;
+use futures_util::stream::{self, Stream, StreamExt};
+use thirtyfour::{DesiredCapabilities, WebDriver};
+use thirtyfour::prelude::*;
+use dotenv::dotenv;
+use std::future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+pub use tokio_util::sync::CancellationToken;
+
+async fn browser() -> WebDriverResult<WebDriver> {
+    let mut caps = DesiredCapabilities::chrome();
+
+    caps.set_application_cache_enabled(false).unwrap();
+    caps.set_headless().unwrap();
+    caps.set_no_sandbox().unwrap();
+    caps.set_disable_dev_shm_usage().unwrap();
+    // --disable-extensions
+    // start-maximized
+    // enable-automation
+    let browser = WebDriver::new("http://localhost:4444", caps).await?;
+    browser.set_window_rect(0, 0, 1920, 1200).await?;
+    browser.maximize_window().await?;
+    Ok(browser)
+}
+
+/// One page of a crawl, numbered from 0. A resumed crawl carries on the
+/// numbering of the run it resumes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub number: usize,
+    pub domains: Vec<Domain>,
+}
+
+/// Pages read one after another, from a browser or, in tests, from memory.
+#[async_trait]
+trait Pages: Send + 'static {
+    /// The next page, or `None` after the last.
+    async fn next_page(&mut self) -> Result<Option<Vec<Domain>>, SourceError>;
+
+    /// Release whatever the pages are read from.
+    async fn close(&mut self) -> Result<(), SourceError> {
+        Ok(())
+    }
+}
+
+/// A source driven through a browser that is started on the first page.
+/// A page that fails with a transient error is tried again after a backoff
+/// in a fresh browser, which is set up and paged forward to where the
+/// crawl had got to.
+struct BrowserPages {
+    source: Box<dyn Source>,
+    browser: Option<WebDriver>,
+    started: bool,
+    /// The page setup lands on: 0 on the site, and the first page replayed
+    /// on a recording.
+    first: usize,
+    /// The page to read next, counting from 0.
+    next: usize,
+    retry: RetryConfig,
+    /// Where to record each page for replaying later.
+    record: Option<PathBuf>,
+    /// The recording being replayed, served for as long as the pages are read.
+    replay: Option<ReplayServer>,
+}
+
+impl BrowserPages {
+    async fn read_page(&mut self) -> Result<Option<Vec<Domain>>, SourceError> {
+        let browser = match &mut self.browser {
+            Some(browser) => browser,
+            slot => slot.insert(browser().await?),
+        };
+        let turned = match self.started {
+            false => start(self.source.as_mut(), browser, self.next - self.first).await,
+            true => self.source.paginate(browser).await,
+        };
+        match turned {
+            Ok(true) => self.started = true,
+            Ok(false) => return Ok(None),
+            // A missing element is often the first sign of a redesign.
+            Err(e @ SourceError::UnexpectedPage { .. }) => {
+                check_layout(self.source.layout(), self.source.metadata().name, &browser.source().await?, browser).await?;
+                return Err(e);
+            },
+            // Drift the source found itself, e.g. while setting up.
+            Err(SourceError::Drift(drift)) => return Err(save_drift(drift, &browser.source().await?, browser).await.into()),
+            Err(e) => return Err(e),
+        }
+        let html = browser.source().await?;
+        check_layout(self.source.layout(), self.source.metadata().name, &html, browser).await?;
+        if let Some(dir) = &self.record {
+            replay::record(dir, self.next, &html)?;
+        }
+        Ok(Some(self.source.parse(&html)?))
+    }
+}
+
+/// Set `source` up and skip the `skip` pages already read, by an earlier
+/// run or attempt; `false` if there are no more pages than that.
+async fn start(source: &mut dyn Source, browser: &WebDriver, skip: usize) -> Result<bool, SourceError> {
+    source.setup(browser).await?;
+    for _ in 0..skip {
+        if !source.paginate(browser).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Check `html`, the page on screen, against `site`'s layout contract. A
+/// page that breaks it is saved, with a screenshot, and reported as drift.
+async fn check_layout(contract: LayoutContract, site: &'static str, html: &str, browser: &WebDriver) -> Result<(), SourceError> {
+    match contract.drift(site, html) {
+        Some(drift) => Err(save_drift(drift, html, browser).await.into()),
+        None => Ok(()),
+    }
+}
+
+/// `drift`, with `html`, the page it was found on, saved beside a
+/// screenshot unless it was saved already.
+async fn save_drift(mut drift: LayoutDrift, html: &str, browser: &WebDriver) -> LayoutDrift {
+    if drift.saved.is_none() {
+        drift.saved = layout::save_page(&layout::drift_dir(), drift.site, html, browser).await.ok();
+    }
+    drift
+}
+
+#[async_trait]
+impl Pages for BrowserPages {
+    async fn next_page(&mut self) -> Result<Option<Vec<Domain>>, SourceError> {
+        let mut retries = 0;
+        loop {
+            match self.read_page().await {
+                Ok(page) => {
+                    self.next += 1;
+                    return Ok(page);
+                },
+                Err(e) if retry::is_transient(&e) && retries < self.retry.retries => {
+                    // Start over rather than trust a browser in an unknown
+                    // state; quitting one that is already gone may fail.
+                    if let Some(browser) = self.browser.take() {
+                        browser.quit().await.ok();
+                    }
+                    self.started = false;
+                    time::sleep(self.retry.delay(retries)).await;
+                    retries += 1;
+                },
+                Err(e) if retries > 0 => return Err(SourceError::GaveUp { attempts: retries + 1, last: Box::new(e) }),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), SourceError> {
+        // Always explicitly close the browser, before the server it reads.
+        if let Some(browser) = self.browser.take() {
+            browser.quit().await?;
+        }
+        self.replay = None;
+        Ok(())
+    }
+}
+
+/// The pages of a running crawl, each available as soon as it has been
+/// read. The crawl runs as its own task a few pages ahead of the reader,
+/// and stops if the stream is dropped.
+pub struct PageStream {
+    pages: mpsc::Receiver<Result<Page, SourceError>>,
+}
+
+impl Stream for PageStream {
+    type Item = Result<Page, SourceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.pages.poll_recv(cx)
+    }
+}
+
+impl PageStream {
+    /// The same crawl one domain at a time.
+    pub fn records(self) -> impl Stream<Item = Result<Domain, SourceError>> {
+        self.flat_map(|page| {
+            stream::iter(match page {
+                Ok(page) => page.domains.into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            })
+        })
+    }
+}
+
+/// Crawl every page of `source` from page `from`, counting from 0, so a
+/// resumed crawl skips the pages an earlier run already stored. With
+/// `record`, each page's HTML is saved there for [`replay`]. Cancelling
+/// `cancel` stops the crawl before the next page; pages already read are
+/// still delivered before the stream ends, so nothing scraped is lost. An
+/// error ends the stream after it.
+pub fn crawl(source: Box<dyn Source>, from: usize, record: Option<PathBuf>, cancel: CancellationToken) -> PageStream {
+    dotenv().ok();
+    let pages = BrowserPages { source, browser: None, started: false, first: 0, next: from, retry: RetryConfig::from_env(), record, replay: None };
+    spawn_pages(pages, from, cancel)
+}
+
+/// Crawl the pages recorded in `dir` instead of the live site, as [`crawl`]
+/// does, in a browser pointed at a local [`ReplayServer`]. A new crawl
+/// (`from` 0) starts at the first page recorded, which is later for a
+/// recording of a resumed crawl; a resumed one at page `from`, which must
+/// have been recorded.
+pub async fn replay(mut source: Box<dyn Source>, dir: PathBuf, from: usize, cancel: CancellationToken) -> Result<PageStream, SourceError> {
+    dotenv().ok();
+    let first = match (from, replay::first_page(&dir)?) {
+        (_, None) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no pages are recorded in {}", dir.display())).into()),
+        (0, Some(first)) => first,
+        (from, Some(_)) if dir.join(replay::page_file(from)).is_file() => from,
+        (from, Some(first)) => {
+            let reason = format!("page {from} is not recorded in {}, which starts at page {first}", dir.display());
+            return Err(io::Error::new(io::ErrorKind::NotFound, reason).into());
+        },
+    };
+    let server = ReplayServer::start(dir).await?;
+    source.open_at(format!("{}/{}", server.url(), replay::page_file(first)));
+    let pages = BrowserPages {
+        source,
+        browser: None,
+        started: false,
+        first,
+        next: first,
+        retry: RetryConfig::from_env(),
+        record: None,
+        replay: Some(server),
+    };
+    Ok(spawn_pages(pages, first, cancel))
+}
+
+fn spawn_pages(mut pages: impl Pages, from: usize, cancel: CancellationToken) -> PageStream {
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        let mut number = from;
+        loop {
+            let next = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => break,
+                next = pages.next_page() => next,
+            };
+            match next {
+                Ok(Some(domains)) => {
+                    // The reader has gone away; stop crawling for nobody.
+                    if tx.send(Ok(Page { number, domains })).await.is_err() {
+                        break;
+                    }
+                    number += 1;
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    break;
+                },
+            }
+        }
+        if let Err(e) = pages.close().await {
+            tx.send(Err(e)).await.ok();
+        }
+    });
+    PageStream { pages: rx }
+}
+
+/// Cancel `cancel` on Ctrl-C, or once `timeout` has passed. Stopping waits
+/// for the page in hand to be stored, so a further Ctrl-C while it does
+/// exits the process at once, with the status a shell gives an interrupt.
+pub fn cancel_on_interrupt(cancel: CancellationToken, timeout: Option<Duration>) {
+    tokio::spawn(async move {
+        let deadline = async {
+            match timeout {
+                Some(timeout) => time::sleep(timeout).await,
+                None => future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = cancel.cancelled() => {},
+            _ = tokio::signal::ctrl_c() => cancel.cancel(),
+            _ = deadline => cancel.cancel(),
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_driver::expired_domains::{ExpiredDomains, ExpiredDomainsConfig};
+    use crate::web_driver::wait::WaitConfig;
+    use std::fs;
+
+    /// Pages of one domain each, `delay` apart, failing at `fail_at`.
+    struct Fake {
+        remaining: Vec<&'static str>,
+        delay: Duration,
+        fail_at: Option<usize>,
+        read: usize,
+    }
+
+    impl Fake {
+        fn new(names: &[&'static str], delay: Duration) -> Self {
+            Fake { remaining: names.iter().rev().copied().collect(), delay, fail_at: None, read: 0 }
+        }
+    }
+
+    #[async_trait]
+    impl Pages for Fake {
+        async fn next_page(&mut self) -> Result<Option<Vec<Domain>>, SourceError> {
+            time::sleep(self.delay).await;
+            if self.fail_at == Some(self.read) {
+                return Err(SourceError::UnexpectedPage { site: "fake", reason: "gone".to_string() });
+            }
+            self.read += 1;
+            Ok(self.remaining.pop().map(|name| vec![Domain::new(name, true, None)]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_stream_in_order() {
+        let pages = spawn_pages(Fake::new(&["a.com", "b.com", "c.com"], Duration::ZERO), 0, CancellationToken::new());
+        let names = pages.records().map(|domain| domain.unwrap().name).collect::<Vec<_>>().await;
+        assert_eq!(names, vec!["a.com", "b.com", "c.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_keeps_pages_already_read() {
+        let cancel = CancellationToken::new();
+        let mut pages = spawn_pages(Fake::new(&["a.com", "b.com", "c.com"], Duration::from_millis(50)), 0, cancel.clone());
+
+        let first = pages.next().await.unwrap().unwrap();
+        assert_eq!((first.number, first.domains[0].name.as_str()), (0, "a.com"));
+        cancel.cancel();
+        // Whatever was read before the cancel arrives; then the stream ends.
+        let rest = pages.map(|page| page.unwrap().number).collect::<Vec<_>>().await;
+        assert!(rest.len() <= 1, "{rest:?}");
+    }
+
+    #[tokio::test]
+    async fn test_error_ends_the_stream() {
+        let fake = Fake { fail_at: Some(1), ..Fake::new(&["a.com", "b.com"], Duration::ZERO) };
+        let pages = spawn_pages(fake, 0, CancellationToken::new()).collect::<Vec<_>>().await;
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].is_ok() && matches!(pages[1], Err(SourceError::UnexpectedPage { .. })));
+    }
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/web_driver/fixtures/expired-domains.co");
+
+    /// The source with short waits, since a replayed table never redraws
+    /// after a facet is ticked and each of those waits runs out.
+    fn replayed_source() -> Box<dyn Source> {
+        let wait = WaitConfig { timeout: Duration::from_secs(2), interval: Duration::from_millis(50) };
+        Box::new(ExpiredDomains::new(ExpiredDomainsConfig { wait, ..ExpiredDomainsConfig::default() }))
+    }
+
+    #[tokio::test]
+    async fn test_replay_starts_at_a_recorded_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = |result: Result<PageStream, SourceError>| result.err().map(|e| e.to_string()).unwrap_or_default();
+        let nothing = replay(replayed_source(), dir.path().into(), 0, CancellationToken::new()).await;
+        assert!(error(nothing).contains("no pages are recorded"));
+
+        // A recording of a crawl resumed at page 3 has no page 0 or 1.
+        replay::record(dir.path(), 3, "<html></html>").unwrap();
+        let missing = replay(replayed_source(), dir.path().into(), 1, CancellationToken::new()).await;
+        assert!(error(missing).contains("page 1 is not recorded"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs chromedriver on localhost:4444"]
+    async fn test_replay_crawls_recorded_pages() {
+        let pages = replay(replayed_source(), FIXTURES.into(), 0, CancellationToken::new()).await.unwrap();
+        let pages = pages.map(|page| page.unwrap()).collect::<Vec<_>>().await;
+        assert_eq!(pages.iter().map(|page| (page.number, page.domains.len())).collect::<Vec<_>>(), vec![(0, 3), (1, 2)]);
+        assert_eq!(pages[0].domains[0].name, "coffeebean.com");
+        assert_eq!(pages[1].domains[0].metrics.backlinks, Some(3310));
+
+        // Resuming replays only what is left.
+        let rest = replay(replayed_source(), FIXTURES.into(), 1, CancellationToken::new()).await.unwrap();
+        assert_eq!(rest.map(|page| page.unwrap().number).collect::<Vec<_>>().await, vec![1]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs chromedriver on localhost:4444"]
+    async fn test_replay_saves_drifted_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let last = fs::read_to_string(format!("{FIXTURES}/page-0001.html")).unwrap();
+        fs::copy(format!("{FIXTURES}/page-0000.html"), dir.path().join("page-0000.html")).unwrap();
+        fs::write(dir.path().join("page-0001.html"), last.replace("Dropped Domains", "Deleted Domains")).unwrap();
+        let pages = replay(replayed_source(), dir.path().into(), 0, CancellationToken::new()).await.unwrap();
+        let pages = pages.collect::<Vec<_>>().await;
+        assert!(pages[0].is_ok());
+        let Err(SourceError::Drift(drift)) = &pages[1] else { panic!("{:?}", pages[1]) };
+        let saved = drift.saved.as_ref().expect("the drifted page is saved");
+        assert!(fs::read_to_string(saved).unwrap().contains("Deleted Domains"));
+        fs::remove_file(saved).ok();
+        fs::remove_file(saved.with_extension("png")).ok();
+    }
+}
@@ -1,26 +1,23 @@
-use crate::util::db::duck;
 use crate::util::db::duck::Domain;
-use crate::util::db::duck::DuckDbType;
+use crate::util::db::import::{parse_bool, parse_date};
+use crate::web_driver::layout::{ExpectedElement, ExpectedHeaders, ExpectedText, ExpectedTitle, LayoutContract};
+use crate::web_driver::source::{Source, SourceError, SourceMetadata};
+use crate::web_driver::wait::{self, WaitConfig};
 
-use thirtyfour::{DesiredCapabilities, WebDriver};
+use async_trait::async_trait;
+use thirtyfour::WebDriver;
 use thirtyfour::prelude::*;
 use thirtyfour::components::SelectElement;
-use thirtyfour::error::WebDriverErrorInfo;
 use scraper::{Html, Selector};
-use scraper::CaseSensitivity;
-use itertools::max;
-use tokio::time;
 use dotenv::dotenv;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
 
-pub enum CrawlTarget {
-    ExpiredDomainsDotCom,
-    Cloudflare,
-}
-
-struct FindBys {
-    ids: Vec<String>,
-    url: String,
-}
+/// How long a filter gets to start redrawing the table before it is taken
+/// to have changed nothing; see `ExpiredDomains::redrawn`.
+const REDRAW_SETTLE: Duration = Duration::from_secs(2);
 
 // #[derive(Debug, Clone, Component)]
 // pub struct CheckboxComponent {
@@ -53,145 +50,749 @@ struct FindBys {
 //     }
 // }
 
-pub async fn basically_selenium(target: CrawlTarget) -> WebDriverResult<Vec<String>> {
-     dotenv().ok();
-     let mut results = Vec::new();
-     let mut caps = DesiredCapabilities::chrome();
-
-     caps.set_application_cache_enabled(false).unwrap();
-     caps.set_headless().unwrap();
-     caps.set_no_sandbox().unwrap();
-     caps.set_disable_dev_shm_usage().unwrap();
-     // --disable-extensions
-     // start-maximized
-     // enable-automation
-     let browser = WebDriver::new("http://localhost:4444", caps).await?;
-     browser.set_window_rect(0, 0, 1920, 1200).await?;
-     browser.maximize_window().await?;
-
-     let site = match target {
-        CrawlTarget::ExpiredDomainsDotCom => FindBys {
-          ids: vec![
-            "exp-collapse-link-TILE_NS11".to_string(), // Open "Dropped Domains where Pagerank > 0"
-            "facet_0-0".to_string(), // Show only Available Domains
-            "facet_2-0".to_string(), // Include '.com' domains
-            "facet_2-1".to_string(), // Include '.net' domains
-            "facet_2-2".to_string(), // Include '.org' domains
-          ],
-          url: "https://www.expired-domains.co/domains-available-by-range/last-31-days/".to_string(),
-        },
-        CrawlTarget::Cloudflare => todo!(),
-    };
+/// Result windows the site has a page for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DateRange {
+    Last7Days,
+    #[default]
+    Last31Days,
+    Last90Days,
+}
 
-     browser.goto(site.url).await?;
-    //  Sleep for 5 seconds
-     time::sleep(time::Duration::from_millis(5000)).await;
-     match target {
-        CrawlTarget::ExpiredDomainsDotCom => {
-          // Setup the page
-          for id in site.ids {
-            let elem = browser.find(By::Id(id)).await?;
-            elem.click().await?;
-          }
-          // Sleep for 2 seconds
-          time::sleep(time::Duration::from_millis(2000)).await;
-
-          // Get the "Results per page" element
-          let results_per_page_elem = browser.find(By::Id("tileTableTILE_NS11_length")).await?;
-          let results_per_page_selector = SelectElement::new(&results_per_page_elem).await?;
-          results_per_page_selector.select_by_value("100").await?; // Show 100 results per page
-          // Sleep for 5 seconds
-          time::sleep(time::Duration::from_millis(5000)).await;
-
-          // Get page values (nth page, focus title, )
-          let pages_ul = Html::parse_fragment(&browser.find(By::Id("tileTableTILE_NS11_paginate")).await?.inner_html().await?);
-          let last_page = get_last_page(&pages_ul.html())?;
-
-          // Verify the title of the target box
-          let title = browser.find(By::Id("exp-title-text-TILE_NS11")).await?.text().await?;
-          if title != "Dropped Domains (PageRank > 0)" {
-            panic!("Expected title 'Dropped Domains (PageRank > 0)'");
-          }
-          // Cycle through pages
-          // grab the content of the target table
-          for _ in 0..=last_page {
-            let table = browser.find(By::Id("tileTableTILE_NS11_wrapper")).await?.find(By::Tag("table")).await?.outer_html().await?;
-            get_records(&table, &mut results).await?;
-            next_page(&browser).await?; // Click "Next" button
-          }
+impl FromStr for DateRange {
+    type Err = SourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last-7-days" => Ok(DateRange::Last7Days),
+            "last-31-days" => Ok(DateRange::Last31Days),
+            "last-90-days" => Ok(DateRange::Last90Days),
+            other => Err(config_error(format!("unknown date range '{other}', expected last-7-days, last-31-days or last-90-days"))),
+        }
+    }
+}
+
+impl DateRange {
+    fn path(&self) -> &'static str {
+        match self {
+            DateRange::Last7Days => "last-7-days",
+            DateRange::Last31Days => "last-31-days",
+            DateRange::Last90Days => "last-90-days",
+        }
+    }
+}
+
+/// The label of the facet that hides registered domains.
+const AVAILABLE_FACET: &str = "Available";
+/// TLDs the site has a facet for, each labelled with the dotted TLD.
+const TLD_FACETS: &[&str] = &["com", "net", "org"];
+/// Sizes the "Results per page" menu offers.
+const PAGE_SIZES: &[u16] = &[10, 25, 50, 100];
+
+fn config_error(reason: String) -> SourceError {
+    SourceError::Config { site: "expired-domains.co", reason }
+}
+
+/// A CSS selector for the element with `id`. It matches the attribute, so
+/// an ID that is no CSS identifier, e.g. one starting with a digit, still
+/// works.
+fn by_id(id: &str) -> String {
+    format!("[id=\"{id}\"]")
+}
+
+/// What to crawl from expired-domains.co, from `EXPIRED_DOMAINS_TILE`
+/// (default `TILE_NS11`), `EXPIRED_DOMAINS_TITLE`, `EXPIRED_DOMAINS_TLDS`
+/// (comma separated, default `com,net,org`), `EXPIRED_DOMAINS_AVAILABLE_ONLY`
+/// (default `true`), `EXPIRED_DOMAINS_RANGE` (default `last-31-days`) and
+/// `EXPIRED_DOMAINS_PAGE_SIZE` (default 100), and waits from [`WaitConfig`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpiredDomainsConfig {
+    /// The results tile, which prefixes the IDs of everything in it.
+    pub tile: String,
+    /// The tile's expected heading, checked before each page is read; `*`
+    /// matches anything, e.g. `Dropped Domains*`.
+    pub title: String,
+    /// TLD facets to tick, without the dot; none ticks none, showing every TLD.
+    pub tlds: Vec<String>,
+    pub available_only: bool,
+    pub range: DateRange,
+    pub page_size: u16,
+    pub wait: WaitConfig,
+}
+
+impl Default for ExpiredDomainsConfig {
+    fn default() -> Self {
+        ExpiredDomainsConfig {
+            tile: "TILE_NS11".to_string(),
+            title: "Dropped Domains (PageRank > 0)".to_string(),
+            tlds: vec!["com".to_string(), "net".to_string(), "org".to_string()],
+            available_only: true,
+            range: DateRange::default(),
+            page_size: 100,
+            wait: WaitConfig::default(),
+        }
+    }
+}
+
+impl ExpiredDomainsConfig {
+    pub fn from_env() -> Result<Self, SourceError> {
+        dotenv().ok();
+        Self::from_vars(|key| env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, SourceError> {
+        let mut config = ExpiredDomainsConfig { wait: WaitConfig::from_vars(&var), ..ExpiredDomainsConfig::default() };
+        if let Some(tile) = var("EXPIRED_DOMAINS_TILE") {
+            // It goes into element IDs and selectors, which it must not break out of.
+            if !tile.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(config_error(format!("EXPIRED_DOMAINS_TILE '{tile}' may only hold letters, digits, '_' and '-'")));
+            }
+            config.tile = tile;
+        }
+        if let Some(title) = var("EXPIRED_DOMAINS_TITLE") {
+            config.title = title;
+        }
+        if let Some(tlds) = var("EXPIRED_DOMAINS_TLDS") {
+            config.tlds = tlds.split(',').map(|tld| tld.trim().trim_start_matches('.').to_lowercase()).filter(|tld| !tld.is_empty()).collect();
+        }
+        if let Some(available_only) = var("EXPIRED_DOMAINS_AVAILABLE_ONLY") {
+            config.available_only = parse_bool(&available_only).ok().flatten().ok_or_else(|| {
+                config_error(format!("EXPIRED_DOMAINS_AVAILABLE_ONLY '{available_only}' is not a boolean"))
+            })?;
+        }
+        if let Some(range) = var("EXPIRED_DOMAINS_RANGE") {
+            config.range = range.parse()?;
+        }
+        if let Some(page_size) = var("EXPIRED_DOMAINS_PAGE_SIZE") {
+            config.page_size = page_size
+                .parse()
+                .map_err(|_| config_error(format!("EXPIRED_DOMAINS_PAGE_SIZE '{page_size}' is not a number")))?;
+        }
+        config.plan()?;
+        Ok(config)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://www.expired-domains.co/domains-available-by-range/{}/", self.range.path())
+    }
+
+    /// The ID of the element `suffix` names within the tile's table, e.g. `_next`.
+    fn table_id(&self, suffix: &str) -> String {
+        format!("tileTable{}{suffix}", self.tile)
+    }
+
+    /// The clicks and selections that set up the tile, in order.
+    fn plan(&self) -> Result<Vec<Step>, SourceError> {
+        let mut steps = vec![Step::Click {
+            id: format!("exp-collapse-link-{}", self.tile),
+            what: format!("open the '{}' tile", self.title),
+        }];
+        if self.available_only {
+            steps.push(Step::Facet { label: AVAILABLE_FACET.to_string(), what: "show only available domains".to_string() });
+        }
+        for tld in &self.tlds {
+            if !TLD_FACETS.contains(&tld.as_str()) {
+                return Err(config_error(format!("no facet for .{tld}, expected one of: {}", TLD_FACETS.join(", "))));
+            }
+            steps.push(Step::Facet { label: format!(".{tld}"), what: format!("include .{tld} domains") });
+        }
+        if !PAGE_SIZES.contains(&self.page_size) {
+            return Err(config_error(format!("page size {} is not one of {PAGE_SIZES:?}", self.page_size)));
+        }
+        steps.push(Step::PageSize { id: self.table_id("_length"), size: self.page_size });
+        Ok(steps)
+    }
+}
+
+/// One interaction that sets up the results tile.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Click { id: String, what: String },
+    /// Tick the facet checkbox whose label reads `label`. Facets are found
+    /// by label because their IDs only number them in the order shown.
+    Facet { label: String, what: String },
+    PageSize { id: String, size: u16 },
+}
+
+/// Dropped domains from [expired-domains.co](https://www.expired-domains.co),
+/// filtered as its [`ExpiredDomainsConfig`] says.
+pub struct ExpiredDomains {
+    config: ExpiredDomainsConfig,
+    /// Where setup goes instead of the site, if anywhere.
+    url: Option<String>,
+    /// The page on screen, counting from 0.
+    page: usize,
+}
+
+impl ExpiredDomains {
+    pub fn new(config: ExpiredDomainsConfig) -> Self {
+        ExpiredDomains { config, url: None, page: 0 }
+    }
+
+    /// The element with `id` once it is on the page, or an error saying
+    /// which step needed it.
+    async fn expect(&self, browser: &WebDriver, id: &str, what: &str) -> Result<WebElement, SourceError> {
+        wait::element(browser, By::Id(id), self.config.wait).await?.ok_or_else(|| SourceError::UnexpectedPage {
+            site: "expired-domains.co",
+            reason: format!("#{id} did not appear within {:?}, needed to {what}", self.config.wait.timeout),
+        })
+    }
+
+    /// The ID of the facet checkbox labelled `label` once it is on the page.
+    async fn facet(&self, browser: &WebDriver, label: &str, what: &str) -> Result<String, SourceError> {
+        let found = wait::poll_until(self.config.wait, what, || async { Ok(facet_id(&browser.source().await?, label)) }).await;
+        match found {
+            Err(SourceError::Timeout { .. }) => Err(SourceError::UnexpectedPage {
+                site: "expired-domains.co",
+                reason: format!("no facet labelled '{label}' appeared within {:?}, needed to {what}", self.config.wait.timeout),
+            }),
+            other => other,
+        }
+    }
+
+    /// Where the results are, from the paginator and the info line under
+    /// the table.
+    async fn position(&self, browser: &WebDriver) -> Result<Position, SourceError> {
+        let paginator = self.expect(browser, &self.config.table_id("_paginate"), "find the pages").await?.outer_html().await?;
+        let info = match browser.find_all(By::Id(self.config.table_id("_info"))).await?.into_iter().next() {
+          Some(info) => info.text().await?,
+          None => String::new(),
+        };
+        Ok(Position::parse(&paginator, &info))
+    }
+
+    /// The results table's HTML, or nothing before it has been drawn.
+    async fn table_html(&self, browser: &WebDriver) -> Result<String, SourceError> {
+        Ok(match browser.find_all(By::Id(self.config.table_id(""))).await?.into_iter().next() {
+            Some(table) => table.outer_html().await?,
+            None => String::new(),
+        })
+    }
+
+    /// Wait for the page to match the layout contract, so a redesign shows
+    /// up as drift before setup clicks anything, rather than as the first
+    /// element it misses.
+    async fn check_layout(&self, browser: &WebDriver) -> Result<(), SourceError> {
+        let contract = self.layout();
+        let held = wait::poll_until(self.config.wait, "the page to match its layout", || async {
+            Ok(contract.check(&browser.source().await?).is_empty().then_some(()))
+        })
+        .await;
+        match held {
+            Err(SourceError::Timeout { .. }) => match contract.drift(self.metadata().name, &browser.source().await?) {
+                Some(drift) => Err(drift.into()),
+                None => Ok(()),
+            },
+            other => other,
+        }
+    }
+
+    /// The results table's HTML and its `_info` text, to tell a redraw by.
+    async fn drawn(&self, browser: &WebDriver) -> Result<(String, String), SourceError> {
+        let info = match browser.find_all(By::Id(self.config.table_id("_info"))).await?.into_iter().next() {
+            Some(info) => info.text().await?,
+            None => String::new(),
+        };
+        Ok((self.table_html(browser).await?, info))
+    }
+
+    /// Whether DataTables shows its "Processing..." indicator, drawn while
+    /// the site fetches the rows for a redraw.
+    async fn processing(&self, browser: &WebDriver) -> Result<bool, SourceError> {
+        match browser.find_all(By::Id(self.config.table_id("_processing"))).await?.into_iter().next() {
+            Some(indicator) => Ok(indicator.is_displayed().await?),
+            None => Ok(false),
+        }
+    }
+
+    /// Wait for the table to redraw from `before` after a filter changed,
+    /// so the next step starts from the table that filter drew: for its
+    /// rows or its `_info` text to change. A filter that hid nothing draws
+    /// the same table, so once [`REDRAW_SETTLE`] has passed with nothing
+    /// processing, the table is taken as it stands; only a redraw still
+    /// processing runs on to the full wait.
+    async fn redrawn(&self, browser: &WebDriver, before: &(String, String)) -> Result<(), SourceError> {
+        let started = Instant::now();
+        let settle = REDRAW_SETTLE.min(self.config.wait.timeout);
+        wait::poll_until(self.config.wait, "the results table to redraw", || async {
+            if self.drawn(browser).await? != *before {
+                return Ok(Some(()));
+            }
+            Ok((started.elapsed() >= settle && !self.processing(browser).await?).then_some(()))
+        })
+        .await
+    }
+}
+
+impl Default for ExpiredDomains {
+    fn default() -> Self {
+        ExpiredDomains::new(ExpiredDomainsConfig::default())
+    }
+}
 
+#[async_trait]
+impl Source for ExpiredDomains {
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            name: "expired-domains.co",
+            url: self.config.url(),
+            description: "Dropped domains by date range, TLD and availability",
         }
-        CrawlTarget::Cloudflare => {
-            todo!()
+    }
+
+    fn layout(&self) -> LayoutContract {
+        let element = |id: String, what: &str| ExpectedElement { selector: by_id(&id), what: what.to_string() };
+        let mut elements = Vec::new();
+        let mut texts = Vec::new();
+        // Everything setup uses, then everything a page is read with. A bad
+        // config has no plan, but from_vars never makes one.
+        for step in self.config.plan().unwrap_or_default() {
+            match step {
+                Step::Click { id, what } => elements.push(element(id, &what)),
+                Step::Facet { label, what } => texts.push(ExpectedText { selector: "label".to_string(), patterns: vec![label.clone(), format!("{label} (*)")], what }),
+                Step::PageSize { id, .. } => elements.push(element(id, "set the page size")),
+            }
+        }
+        elements.extend([
+            element(self.config.table_id("_wrapper"), "read the results"),
+            element(self.config.table_id("_info"), "count the results"),
+            element(self.config.table_id("_paginate"), "find the pages"),
+            element(self.config.table_id("_next"), "go to the next page"),
+        ]);
+        LayoutContract {
+            elements,
+            texts,
+            headers: Some(ExpectedHeaders { table: by_id(&self.config.table_id("")), headers: vec!["Domain".to_string()] }),
+            title: Some(ExpectedTitle { selector: by_id(&format!("exp-title-text-{}", self.config.tile)), patterns: vec![self.config.title.clone()] }),
         }
-     }
-    
-     // Always explicitly close the browser.
-     browser.quit().await?;
+    }
+
+    fn open_at(&mut self, url: String) {
+        self.url = Some(url);
+    }
+
+    async fn setup(&mut self, browser: &WebDriver) -> Result<(), SourceError> {
+        browser.goto(self.url.as_deref().unwrap_or(&self.config.url())).await?;
+        self.check_layout(browser).await?;
 
-     match dotenv::var("DB_TYPE").unwrap().as_str() {
-        "sql" => {
-          let mut conn = duck::db_init(DuckDbType::Persistent).unwrap();
-          let tx = conn.transaction().unwrap();
-          for domain in &results {
-            duck::insert_domain(&tx, &Domain::new(domain, true, None)).unwrap();
+        // Setup the page, waiting for each element to appear before using it
+        for step in self.config.plan()? {
+          match step {
+            Step::Click { id, what } => {
+              self.expect(browser, &id, &what).await?.click().await?;
+            },
+            Step::Facet { label, what } => {
+              let id = self.facet(browser, &label, &what).await?;
+              let checkbox = self.expect(browser, &id, &what).await?;
+              let before = self.drawn(browser).await?;
+              checkbox.click().await?;
+              self.redrawn(browser, &before).await?;
+            },
+            Step::PageSize { id, size } => {
+              let results_per_page_elem = self.expect(browser, &id, "set the page size").await?;
+              let before = self.drawn(browser).await?;
+              let results_per_page_selector = SelectElement::new(&results_per_page_elem).await?;
+              results_per_page_selector.select_by_value(&size.to_string()).await?;
+              self.redrawn(browser, &before).await?;
+            },
           }
-          tx.commit().unwrap();
-        },
-        "graph" => todo!(),
-        &_ => todo!(),
-     }
+        }
+
+        self.expect(browser, &self.config.table_id("_paginate"), "find the pages").await?;
+        self.page = 0;
+        Ok(())
+    }
+
+    fn parse(&self, html: &str) -> Result<Vec<Domain>, SourceError> {
+        // grab the content of the target table
+        let page = Html::parse_document(html);
+        let table = Selector::parse(&format!("{} table", by_id(&self.config.table_id("_wrapper"))))
+            .map_err(|e| config_error(format!("no selector for tile '{}': {e}", self.config.tile)))?;
+        match page.select(&table).next() {
+          Some(table) => Ok(parse_table(&table.html())),
+          None => Err(SourceError::UnexpectedPage { site: "expired-domains.co", reason: "the results table is missing".to_string() }),
+        }
+    }
+
+    async fn paginate(&mut self, browser: &WebDriver) -> Result<bool, SourceError> {
+        let before = self.position(browser).await?;
+        if !before.has_next() {
+          return Ok(false);
+        }
+        let before_table = self.table_html(browser).await?;
+
+        // "Next" is a link, or an item holding one, depending on the theme
+        let next = self.expect(browser, &self.config.table_id("_next"), "go to the next page").await?;
+        match next.find_all(By::Tag("a")).await?.into_iter().next() {
+          Some(link) => link.click().await?,
+          None => next.click().await?,
+        }
+        // Wait for the position to move on, then for the new rows to
+        // arrive, so the same table is never read twice. A click that never
+        // moves the position means the site is stuck, and clicking again
+        // would loop forever; rows slow to follow a position that did move
+        // are only a slow page, and time out to be tried again.
+        let moved = wait::poll_until(self.config.wait, "the next page", || async {
+          let now = self.position(browser).await?;
+          Ok((now != before).then_some(now))
+        })
+        .await;
+        match moved {
+          Err(SourceError::Timeout { .. }) => return Err(SourceError::Stuck { site: "expired-domains.co", page: self.page }),
+          Err(e) => return Err(e),
+          Ok(_) => {},
+        }
+        wait::changed(browser, By::Id(self.config.table_id("")), &before_table, self.config.wait).await?;
+        self.page += 1;
+        Ok(true)
+    }
+}
+
+/// The DataTables info line, e.g. "Showing 101 to 200 of 5,432 entries".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageInfo {
+    first: u64,
+    last: u64,
+    total: u64,
+}
+
+impl PageInfo {
+    /// The first three numbers in `text`; a "(filtered from ...)" note may
+    /// follow them.
+    fn parse(text: &str) -> Option<Self> {
+        let mut numbers = text
+            .split(|c: char| !c.is_ascii_digit() && c != ',')
+            .filter_map(|word| word.replace(',', "").parse::<u64>().ok());
+        Some(PageInfo { first: numbers.next()?, last: numbers.next()?, total: numbers.next()? })
+    }
 
-     Ok(results)
+    fn is_last(&self) -> bool {
+        self.last >= self.total
+    }
 }
 
-async fn next_page(driver: &WebDriver) -> WebDriverResult<()> {
-    let next_button_elem = driver.find(By::Id("tileTableTILE_NS11_next")).await?.find(By::Tag("a")).await?;
-    next_button_elem.click().await?;
-    time::sleep(time::Duration::from_millis(100)).await;
-    Ok(())
+/// Where the results table is up to. The paginator's page links are no
+/// guide to how many pages there are, since DataTables elides the middle
+/// ones with "…", so this goes by the "Next" button and the info line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Position {
+    /// The highlighted page number.
+    current: Option<u32>,
+    next_disabled: bool,
+    info: Option<PageInfo>,
 }
 
-fn get_last_page(html: &String) -> WebDriverResult<u16> {
-    // Parse the HTML
-    let fragment = Html::parse_fragment(html);
+impl Position {
+    /// From the paginator's HTML, in the classic or the Bootstrap theme, and
+    /// the info line's text.
+    fn parse(paginator: &str, info: &str) -> Self {
+        let fragment = Html::parse_fragment(paginator);
+        let current = Selector::parse(".current, .active").unwrap();
+        let next = Selector::parse("[id$='_next'], .next").unwrap();
+        let disabled = |element: scraper::ElementRef| {
+            element.value().classes().any(|class| class == "disabled") || element.value().attr("aria-disabled") == Some("true")
+        };
+        Position {
+            current: fragment.select(&current).find_map(|element| text(element).parse().ok()),
+            next_disabled: fragment.select(&next).next().is_none_or(|next| disabled(next) || next.descendent_elements().any(disabled)),
+            info: PageInfo::parse(info),
+        }
+    }
 
-    // Create selectors (like regex)
-    let ul_selector = Selector::parse("ul").unwrap();
+    fn has_next(&self) -> bool {
+        !self.next_disabled && !self.info.is_some_and(|info| info.is_last())
+    }
+}
 
-    // Handle the List of Pages
-    let ul = fragment.select(&ul_selector).next().unwrap();
-    let pages = ul.descendent_elements().filter_map(|s| 
-                match s.inner_html().parse::<u16>().is_ok() {
-                  true => Some(s.inner_html().parse::<u16>().unwrap()),
-                  false => None
-                }
-              ).collect::<Vec<u16>>();
+/// The ID of the facet checkbox whose label reads `label`, ignoring case
+/// and the count the site shows after it, e.g. `.com (1,234)`. The label
+/// either names the checkbox with `for` or wraps it.
+fn facet_id(html: &str, label: &str) -> Option<String> {
+    let page = Html::parse_document(html);
+    let labels = Selector::parse("label").unwrap();
+    let checkbox = Selector::parse("input[type='checkbox'][id]").unwrap();
+    page.select(&labels).filter(|element| facet_label(&text(*element)).eq_ignore_ascii_case(label)).find_map(|element| {
+        element
+            .value()
+            .attr("for")
+            .map(str::to_string)
+            .or_else(|| element.select(&checkbox).next().and_then(|input| input.value().id()).map(str::to_string))
+    })
+}
+
+/// A facet's label without the count after it.
+fn facet_label(text: &str) -> &str {
+    match text.rsplit_once(" (") {
+        Some((label, count)) if count.ends_with(')') && count[..count.len() - 1].chars().all(|c| c.is_ascii_digit() || c == ',') => label,
+        _ => text,
+    }
+}
+
+/// Where a column of the results table goes, from its header.
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    Name,
+    PageRank,
+    Backlinks,
+    WhoisCreated,
+    /// Anything else, kept in `extras` under the header text.
+    Extra(String),
+}
+
+impl Column {
+    fn from_header(header: &str) -> Self {
+        let key = header.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+        match key.as_str() {
+            "domain" | "domainname" => Column::Name,
+            "pr" | "pagerank" => Column::PageRank,
+            "bl" | "backlinks" => Column::Backlinks,
+            "created" | "whois" | "whoiscreated" | "registered" => Column::WhoisCreated,
+            _ => Column::Extra(header.to_string()),
+        }
+    }
+}
 
-    // Only return the maximum page number
-    Ok(max(pages).unwrap())
+/// A count as the site prints it: `1,234`, `1.2K` or `3M`; `-` for none.
+fn parse_count(text: &str) -> Option<f64> {
+    let text = text.trim().replace(',', "");
+    let (number, scale) = match text.chars().last()?.to_ascii_uppercase() {
+        'K' => (&text[..text.len() - 1], 1e3),
+        'M' => (&text[..text.len() - 1], 1e6),
+        _ => (text.as_str(), 1.0),
+    };
+    number.trim().parse::<f64>().ok().map(|number| number * scale)
 }
 
-async fn get_records(table_html: &String, results: &mut Vec<String>) -> WebDriverResult<()>{
-    let mut records = Vec::new();
+fn text(element: scraper::ElementRef) -> String {
+    element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
 
-    // Parse the HTML
+/// Every row of a results table as a domain. Columns are found by their
+/// headers, so reordered or added columns still land in the right field;
+/// columns without a field of their own go into `extras`. Rows without a
+/// valid domain name are skipped.
+fn parse_table(table_html: &str) -> Vec<Domain> {
     let fragment = Html::parse_fragment(table_html);
+    let header_selector = Selector::parse("thead th").unwrap();
+    let row_selector = Selector::parse("tbody tr").unwrap();
+    let cell_selector = Selector::parse("td").unwrap();
+    let link_selector = Selector::parse(".exp-domain-link").unwrap();
+
+    let columns = fragment.select(&header_selector).map(|th| Column::from_header(&text(th))).collect::<Vec<_>>();
+    let mut domains = Vec::new();
+    for row in fragment.select(&row_selector) {
+        let cells = row.select(&cell_selector).map(text).collect::<Vec<_>>();
+        let linked = row.select(&link_selector).next().map(text);
+        let name = linked.or_else(|| {
+            columns.iter().position(|column| *column == Column::Name).and_then(|i| cells.get(i).cloned())
+        });
+        let Some(Ok(mut domain)) = name.map(|name| Domain::try_new(&name.to_lowercase(), true, None)) else {
+            continue;
+        };
+
+        let mut extras = serde_json::Map::new();
+        for (column, cell) in columns.iter().zip(&cells) {
+            if cell.is_empty() || cell == "-" {
+                continue;
+            }
+            match column {
+                Column::Name => {},
+                Column::PageRank => domain.page_rank = parse_count(cell),
+                Column::Backlinks => domain.metrics.backlinks = parse_count(cell).map(|count| count as i64),
+                Column::WhoisCreated => domain.metrics.whois_created = parse_date(cell).ok().flatten(),
+                Column::Extra(header) => {
+                    let value = match parse_count(cell) {
+                        Some(number) => serde_json::json!(number),
+                        None => serde_json::json!(cell),
+                    };
+                    extras.insert(header.clone(), value);
+                },
+            }
+        }
+        if !extras.is_empty() {
+            domain = domain.with_extras(Some(extras.into()));
+        }
+        domains.push(domain);
+    }
+    domains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_driver::layout::Drift;
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    const TABLE: &str = r#"
+      <table id="tileTableTILE_NS11">
+        <thead><tr><th>Domain</th><th>PR</th><th>BL</th><th>ACR</th><th>Dropped</th><th>Created</th></tr></thead>
+        <tbody>
+          <tr>
+            <td><a class="exp-domain-link" href="/domain">CoffeeBean.com</a></td>
+            <td>3</td><td>1,204</td><td>1.5K</td><td>2024-05-01</td><td>2009-02-14</td>
+          </tr>
+          <tr>
+            <td><a class="exp-domain-link" href="/domain">tea.net</a></td>
+            <td>-</td><td>12</td><td></td><td>2024-05-02</td><td>-</td>
+          </tr>
+          <tr><td>not a domain</td><td>1</td></tr>
+        </tbody>
+      </table>"#;
+
+    #[test]
+    fn test_rows_map_headers_to_fields() {
+        let domains = parse_table(TABLE);
+        assert_eq!(domains.len(), 2);
+
+        let coffee = &domains[0];
+        assert_eq!((coffee.name.as_str(), coffee.page_rank, coffee.metrics.backlinks), ("coffeebean.com", Some(3.0), Some(1204)));
+        assert_eq!(coffee.metrics.whois_created, NaiveDate::from_ymd_opt(2009, 2, 14));
+        assert_eq!(coffee.extras, Some(json!({"ACR": 1500.0, "Dropped": "2024-05-01"})));
+
+        let tea = &domains[1];
+        assert_eq!((tea.page_rank, tea.metrics.backlinks, tea.metrics.whois_created), (None, Some(12), None));
+        assert_eq!(tea.extras, Some(json!({"Dropped": "2024-05-02"})));
+    }
+
+    #[test]
+    fn test_plan_from_config() {
+        let vars = [("EXPIRED_DOMAINS_TLDS", ".net, ORG"), ("EXPIRED_DOMAINS_AVAILABLE_ONLY", "no"), ("EXPIRED_DOMAINS_RANGE", "last-7-days"), ("EXPIRED_DOMAINS_PAGE_SIZE", "50")];
+        let config = ExpiredDomainsConfig::from_vars(|key| vars.iter().find(|(name, _)| *name == key).map(|(_, value)| value.to_string())).unwrap();
+        assert_eq!(config.url(), "https://www.expired-domains.co/domains-available-by-range/last-7-days/");
+
+        let ids = config.plan().unwrap().into_iter().map(|step| match step {
+            Step::Click { id, .. } => id,
+            Step::Facet { label, .. } => format!("[{label}]"),
+            Step::PageSize { id, size } => format!("{id}={size}"),
+        });
+        assert_eq!(ids.collect::<Vec<_>>(), vec!["exp-collapse-link-TILE_NS11", "[.net]", "[.org]", "tileTableTILE_NS11_length=50"]);
+    }
+
+    #[test]
+    fn test_facets_are_found_by_label() {
+        // Reordered from how the site first listed them, with counts.
+        let facets = r#"
+          <div class="exp-facets">
+            <label for="facet_2-0"><input type="checkbox" id="facet_2-0"> .org (97)</label>
+            <label for="facet_2-1"><input type="checkbox" id="facet_2-1"> .com (1,234)</label>
+            <label><input type="checkbox" id="facet_0-0"> Available</label>
+            <label for="facet_2-2"><input type="checkbox" id="facet_2-2"> .com.au (3)</label>
+          </div>"#;
+        assert_eq!(facet_id(facets, ".com").as_deref(), Some("facet_2-1"));
+        assert_eq!(facet_id(facets, ".ORG").as_deref(), Some("facet_2-0"));
+        assert_eq!(facet_id(facets, "available").as_deref(), Some("facet_0-0"));
+        assert_eq!(facet_id(facets, ".net"), None);
+        assert_eq!(facet_label("Registered (2024)"), "Registered");
+        assert_eq!(facet_label("Dropped (soon)"), "Dropped (soon)");
+    }
+
+    #[test]
+    fn test_bad_config_is_rejected() {
+        let config = |key: &'static str, value: &'static str| ExpiredDomainsConfig::from_vars(move |k| (k == key).then(|| value.to_string()));
+        assert!(matches!(config("EXPIRED_DOMAINS_TLDS", "com,info"), Err(SourceError::Config { reason, .. }) if reason.contains(".info")));
+        assert!(matches!(config("EXPIRED_DOMAINS_PAGE_SIZE", "30"), Err(SourceError::Config { .. })));
+        assert!(matches!(config("EXPIRED_DOMAINS_RANGE", "yesterday"), Err(SourceError::Config { .. })));
+        assert!(matches!(config("EXPIRED_DOMAINS_TILE", "TILE NS11"), Err(SourceError::Config { .. })));
+        assert!(matches!(config("EXPIRED_DOMAINS_TILE", "TILE\"]"), Err(SourceError::Config { .. })));
+    }
+
+    /// Page 2 of many, in the Bootstrap theme, with the middle pages elided.
+    const BOOTSTRAP_PAGINATOR: &str = include_str!("fixtures/expired-domains.co/paginate-bootstrap.html");
+    /// The last page, in the classic theme.
+    const CLASSIC_LAST_PAGINATOR: &str = include_str!("fixtures/expired-domains.co/paginate-classic-last.html");
+
+    #[test]
+    fn test_position_from_paginator() {
+        let middle = Position::parse(BOOTSTRAP_PAGINATOR, "Showing 101 to 200 of 5,432 entries");
+        assert_eq!((middle.current, middle.next_disabled), (Some(2), false));
+        assert_eq!(middle.info, Some(PageInfo { first: 101, last: 200, total: 5432 }));
+        assert!(middle.has_next());
+
+        let last = Position::parse(CLASSIC_LAST_PAGINATOR, "");
+        assert_eq!((last.current, last.next_disabled, last.info), (Some(55), true, None));
+        assert!(!last.has_next());
+    }
+
+    #[test]
+    fn test_info_ends_the_pages_even_if_next_is_enabled() {
+        let filtered = Position::parse(BOOTSTRAP_PAGINATOR, "Showing 5,401 to 5,432 of 5,432 entries (filtered from 90,000 total entries)");
+        assert_eq!(filtered.info.map(|info| (info.last, info.total)), Some((5432, 5432)));
+        assert!(!filtered.has_next());
+        assert!(!Position::parse(BOOTSTRAP_PAGINATOR, "Showing 0 to 0 of 0 entries").has_next());
+        assert!(!Position::parse("<div></div>", "").has_next(), "no Next button means no next page");
+    }
+
+    #[test]
+    fn test_layout_contract_catches_a_retitled_tile() {
+        let page = |title: &str| {
+            format!(
+                r#"<label><input type="checkbox" id="facet_0-0"> Available</label>
+                   <label for="facet_2-0">.com (2,310)</label><label for="facet_2-1">.net (604)</label><label for="facet_2-2">.org (433)</label>
+                   <a id="exp-collapse-link-TILE_NS11"><h3 id="exp-title-text-TILE_NS11">{title}</h3></a>
+                   <div id="tileTableTILE_NS11_wrapper"><div id="tileTableTILE_NS11_length"></div>{TABLE}<div id="tileTableTILE_NS11_info"></div>{BOOTSTRAP_PAGINATOR}</div>"#
+            )
+        };
+        let source = ExpiredDomains::default();
+        assert_eq!(source.layout().check(&page("Dropped Domains (PageRank &gt; 0)")), Vec::new());
 
-    // Create selectors (like regex)
-    let table_selector = Selector::parse("table").unwrap();
-    
-    // Get the table
-    let table = fragment.select(&table_selector).next().unwrap();
+        let changes = source.layout().check(&page("Deleted Domains"));
+        assert!(matches!(changes.as_slice(), [Drift::Title { found: Some(found), .. }] if found == "Deleted Domains"));
 
-    // Cycle each row; get the domain names
-    for node in table.descendent_elements() { 
-        // if the node has the class "exp-domain-link" -> add it to the records
-        if node.value().has_class("exp-domain-link", CaseSensitivity::AsciiCaseInsensitive) {
-          records.push(node.inner_html());
+        // Facets are part of the contract by their labels, not their IDs.
+        let changes = source.layout().check(&page("Dropped Domains (PageRank &gt; 0)").replace(".net (604)", "Net (604)"));
+        assert!(matches!(changes.as_slice(), [Drift::MissingText { what, .. }] if what == "include .net domains"));
+    }
+
+    /// Two pages of a crawl, as `crawl --record` saves them.
+    const RECORDED: [&str; 2] = [
+        include_str!("fixtures/expired-domains.co/page-0000.html"),
+        include_str!("fixtures/expired-domains.co/page-0001.html"),
+    ];
+
+    /// The outer HTML and the text of the element with ID `id`.
+    fn element(html: &str, id: &str) -> Option<(String, String)> {
+        let page = Html::parse_document(html);
+        let element = page.select(&Selector::parse(&by_id(id)).unwrap()).next()?;
+        Some((element.html(), element.text().collect()))
+    }
+
+    /// What a crawl does with the recorded pages, less the browser: setup's
+    /// steps find what they click on the first page, and each page matches
+    /// the layout, parses, and says whether another follows.
+    #[test]
+    fn test_recorded_pages_crawl_without_a_browser() {
+        let source = ExpiredDomains::default();
+        let config = &source.config;
+        for step in config.plan().unwrap() {
+            let found = match &step {
+                Step::Click { id, .. } | Step::PageSize { id, .. } => element(RECORDED[0], id).is_some(),
+                Step::Facet { label, .. } => facet_id(RECORDED[0], label).is_some_and(|id| element(RECORDED[0], &id).is_some()),
+            };
+            assert!(found, "{step:?}");
         }
+
+        let mut crawled = Vec::new();
+        for html in RECORDED {
+            assert_eq!(source.layout().check(html), Vec::new());
+            let (paginator, _) = element(html, &config.table_id("_paginate")).unwrap();
+            let (_, info) = element(html, &config.table_id("_info")).unwrap();
+            crawled.push((source.parse(html).unwrap(), Position::parse(&paginator, &info).has_next()));
+        }
+        let pages = crawled.iter().map(|(domains, next)| (domains.len(), *next)).collect::<Vec<_>>();
+        assert_eq!(pages, vec![(3, true), (2, false)]);
+        assert_eq!(crawled[0].0[0].name, "coffeebean.com");
+        assert_eq!(crawled[1].0[0].metrics.backlinks, Some(3310));
+
+        let drifted = RECORDED[1].replace("Dropped Domains", "Deleted Domains");
+        assert!(matches!(source.layout().check(&drifted).as_slice(), [Drift::Title { .. }]));
     }
 
-    results.append(&mut records);
-    Ok(())
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("1,234"), Some(1234.0));
+        assert_eq!(parse_count("2.5m"), Some(2_500_000.0));
+        assert_eq!(parse_count("-"), None);
+        assert_eq!(parse_count("2024-05-01"), None);
+    }
 }
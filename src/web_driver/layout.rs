@@ -0,0 +1,224 @@
+use chrono::Utc;
+use dotenv::dotenv;
+use itertools::Itertools;
+use scraper::{ElementRef, Html, Selector};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thirtyfour::WebDriver;
+
+/// What a source's pages must look like for its selectors to work, checked
+/// against the page before anything is read from it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayoutContract {
+    pub elements: Vec<ExpectedElement>,
+    pub texts: Vec<ExpectedText>,
+    pub headers: Option<ExpectedHeaders>,
+    pub title: Option<ExpectedTitle>,
+}
+
+/// An element the source uses, by CSS selector, and what for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedElement {
+    pub selector: String,
+    pub what: String,
+}
+
+/// An element the source finds by what it reads rather than by ID, e.g. a
+/// filter's label: some element `selector` matches must read one of
+/// `patterns`, where `*` stands for anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedText {
+    pub selector: String,
+    pub patterns: Vec<String>,
+    pub what: String,
+}
+
+/// Column headers a results table must have, in any order and among others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedHeaders {
+    pub table: String,
+    pub headers: Vec<String>,
+}
+
+/// Text an element must match: one of `patterns`, where `*` stands for
+/// anything, e.g. `Dropped Domains*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedTitle {
+    pub selector: String,
+    pub patterns: Vec<String>,
+}
+
+/// One way a page differs from its contract.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    MissingElement { selector: String, what: String },
+    MissingText { selector: String, patterns: Vec<String>, what: String },
+    MissingHeader { table: String, header: String, found: Vec<String> },
+    Title { selector: String, expected: Vec<String>, found: Option<String> },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::MissingElement { selector, what } => write!(f, "no {selector}, needed to {what}"),
+            Drift::MissingText { selector, patterns, what } => write!(f, "no {selector} reading {patterns:?}, needed to {what}"),
+            Drift::MissingHeader { table, header, found } => write!(f, "{table} has no '{header}' column, only {found:?}"),
+            Drift::Title { selector, expected, found: Some(found) } => write!(f, "{selector} reads '{found}', expected {expected:?}"),
+            Drift::Title { selector, .. } => write!(f, "no {selector} to check the title in"),
+        }
+    }
+}
+
+/// A page that no longer matches its source's contract, and where a copy of
+/// it was saved for fixing the selectors.
+#[derive(Debug, thiserror::Error)]
+#[error("{site} has changed its layout: {}{}", .changes.iter().join("; "), .saved.as_ref().map(|saved| format!(" (page saved to {})", saved.display())).unwrap_or_default())]
+pub struct LayoutDrift {
+    pub site: &'static str,
+    pub changes: Vec<Drift>,
+    /// The page's HTML; a screenshot sits beside it with a `.png` extension.
+    pub saved: Option<PathBuf>,
+}
+
+impl LayoutContract {
+    /// The drift of `site`'s page `html` from the contract, if there is any;
+    /// nothing has been saved yet.
+    pub fn drift(&self, site: &'static str, html: &str) -> Option<LayoutDrift> {
+        let changes = self.check(html);
+        (!changes.is_empty()).then_some(LayoutDrift { site, changes, saved: None })
+    }
+
+    /// Every way `html` breaks the contract; none if it holds.
+    pub fn check(&self, html: &str) -> Vec<Drift> {
+        let page = Html::parse_document(html);
+        let mut changes = Vec::new();
+        for element in &self.elements {
+            if first(&page, &element.selector).is_none() {
+                changes.push(Drift::MissingElement { selector: element.selector.clone(), what: element.what.clone() });
+            }
+        }
+        for expected in &self.texts {
+            let found = Selector::parse(&expected.selector)
+                .is_ok_and(|selector| page.select(&selector).any(|element| expected.patterns.iter().any(|pattern| matches(pattern, &text(element)))));
+            if !found {
+                changes.push(Drift::MissingText { selector: expected.selector.clone(), patterns: expected.patterns.clone(), what: expected.what.clone() });
+            }
+        }
+        if let Some(expected) = &self.headers {
+            match first(&page, &expected.table) {
+                Some(table) => {
+                    let th = Selector::parse("thead th").unwrap();
+                    let found = table.select(&th).map(text).collect::<Vec<_>>();
+                    for header in &expected.headers {
+                        if !found.iter().any(|found| found.eq_ignore_ascii_case(header)) {
+                            changes.push(Drift::MissingHeader { table: expected.table.clone(), header: header.clone(), found: found.clone() });
+                        }
+                    }
+                },
+                None => changes.push(Drift::MissingElement { selector: expected.table.clone(), what: "read the results".to_string() }),
+            }
+        }
+        if let Some(expected) = &self.title {
+            let found = first(&page, &expected.selector).map(text);
+            if !found.as_deref().is_some_and(|found| expected.patterns.iter().any(|pattern| matches(pattern, found))) {
+                changes.push(Drift::Title { selector: expected.selector.clone(), expected: expected.patterns.clone(), found });
+            }
+        }
+        changes
+    }
+}
+
+fn first<'a>(page: &'a Html, selector: &str) -> Option<ElementRef<'a>> {
+    // A selector that does not parse can never match, which is drift too.
+    Selector::parse(selector).ok().and_then(|selector| page.select(&selector).next())
+}
+
+fn text(element: ElementRef) -> String {
+    element.text().collect::<String>().split_whitespace().join(" ")
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters.
+fn matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(prefix) = parts.next() else { return true };
+    let Some(mut rest) = text.strip_prefix(prefix) else { return false };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((suffix, middle)) = parts.split_last() else { return rest.is_empty() };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(suffix)
+}
+
+/// Where drifted pages are saved, from `CRAWL_DRIFT_DIR` or `./data/drift`.
+pub fn drift_dir() -> PathBuf {
+    dotenv().ok();
+    env::var("CRAWL_DRIFT_DIR").unwrap_or("./data/drift".to_string()).into()
+}
+
+/// Save `html` and a screenshot of the browser under `dir`, named after the
+/// site and the time, and return the HTML's path. The screenshot is best
+/// effort: a browser that has gone away still leaves the HTML.
+pub async fn save_page(dir: &Path, site: &str, html: &str, browser: &WebDriver) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{site}-{}.html", Utc::now().format("%Y%m%d-%H%M%S-%3f")));
+    fs::write(&path, html)?;
+    browser.screenshot(&path.with_extension("png")).await.ok();
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+      <html><body>
+        <h3 id="exp-title-text-TILE_NS11">Dropped Domains (PageRank &gt; 0)</h3>
+        <table id="tileTableTILE_NS11"><thead><tr><th>Domain</th><th>PR</th></tr></thead></table>
+        <div id="tileTableTILE_NS11_paginate"></div>
+        <label for="facet_2-0">.com (12)</label>
+      </body></html>"#;
+
+    fn contract() -> LayoutContract {
+        LayoutContract {
+            elements: vec![ExpectedElement { selector: "#tileTableTILE_NS11_paginate".to_string(), what: "turn the page".to_string() }],
+            texts: vec![ExpectedText { selector: "label".to_string(), patterns: vec![".com".to_string(), ".com (*)".to_string()], what: "include .com".to_string() }],
+            headers: Some(ExpectedHeaders { table: "#tileTableTILE_NS11".to_string(), headers: vec!["domain".to_string()] }),
+            title: Some(ExpectedTitle { selector: "#exp-title-text-TILE_NS11".to_string(), patterns: vec!["Dropped Domains*".to_string()] }),
+        }
+    }
+
+    #[test]
+    fn test_matching_page_has_no_drift() {
+        assert_eq!(contract().check(PAGE), Vec::new());
+    }
+
+    #[test]
+    fn test_drift_reports_each_change() {
+        let changed = PAGE
+            .replace("Dropped Domains", "Deleted Domains")
+            .replace("<th>Domain</th>", "<th>Name</th>")
+            .replace("_paginate", "_pages")
+            .replace(".com (12)", ".com.au (12)");
+        let changes = contract().check(&changed);
+        assert_eq!(changes.len(), 4, "{changes:?}");
+        assert!(matches!(&changes[0], Drift::MissingElement { selector, .. } if selector == "#tileTableTILE_NS11_paginate"));
+        assert!(matches!(&changes[1], Drift::MissingText { what, .. } if what == "include .com"));
+        assert!(matches!(&changes[2], Drift::MissingHeader { header, found, .. } if header == "domain" && found == &["Name", "PR"]));
+        assert_eq!(changes[3].to_string(), r#"#exp-title-text-TILE_NS11 reads 'Deleted Domains (PageRank > 0)', expected ["Dropped Domains*"]"#);
+    }
+
+    #[test]
+    fn test_title_patterns() {
+        assert!(matches("Dropped Domains (PageRank > 0)", "Dropped Domains (PageRank > 0)"));
+        assert!(matches("Dropped*(PageRank*)", "Dropped Domains (PageRank > 2)"));
+        assert!(matches("*", ""));
+        assert!(!matches("Dropped Domains", "Dropped Domains (PageRank > 0)"));
+        assert!(!matches("a*b*c", "acb"));
+    }
+}
@@ -1 +1,8 @@
-pub mod expired_domains;
\ No newline at end of file
+pub mod crawl;
+pub mod expired_domains;
+pub mod layout;
+pub mod replay;
+pub mod retry;
+pub mod sink;
+pub mod source;
+pub mod wait;
@@ -0,0 +1,191 @@
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// The file page `number` of a crawl is recorded in.
+pub fn page_file(number: usize) -> String {
+    format!("page-{number:04}.html")
+}
+
+/// The page number a recorded file holds, from its name.
+fn page_number(name: &str) -> Option<usize> {
+    name.strip_prefix("page-")?.strip_suffix(".html")?.parse().ok()
+}
+
+/// The first page recorded in `dir`, which is later than 0 for a recording
+/// of a resumed crawl; `None` if nothing is.
+pub fn first_page(dir: &Path) -> io::Result<Option<usize>> {
+    let mut first = None;
+    for entry in fs::read_dir(dir)? {
+        if let Some(number) = entry?.file_name().to_str().and_then(page_number) {
+            first = Some(first.map_or(number, |first: usize| first.min(number)));
+        }
+    }
+    Ok(first)
+}
+
+/// Save page `number`'s HTML under `dir`, where a replay will find it.
+pub fn record(dir: &Path, number: usize, html: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(page_file(number)), html)
+}
+
+/// Serves a recorded crawl over HTTP on a local port, so a browser can
+/// crawl it as it would the site. Each page is served as recorded, minus
+/// its scripts, which would redraw it from the live site; in their place,
+/// clicking DataTables' "Next" button, an element whose ID ends in `_next`,
+/// opens the next recorded page. On the last page recorded it does
+/// nothing. The server stops when this is dropped.
+pub struct ReplayServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ReplayServer {
+    pub async fn start(dir: PathBuf) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let dir = dir.clone();
+                tokio::spawn(async move { serve(stream, &dir).await.ok() });
+            }
+        });
+        Ok(ReplayServer { addr, task })
+    }
+
+    /// The server's root, e.g. `http://127.0.0.1:40123`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for ReplayServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer one request: the file it names, or 404. Only plain file names
+/// are served, never a path out of the directory.
+async fn serve(stream: TcpStream, dir: &Path) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request = String::new();
+    stream.read_line(&mut request).await?;
+    // Skip the headers; nothing in them matters here.
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+    let name = match request.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["GET", path, _] => path.trim_start_matches('/').to_string(),
+        _ => String::new(),
+    };
+    let file = match name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        true => None,
+        false => fs::read(dir.join(&name)).ok(),
+    };
+    let file = match (file, page_number(&name)) {
+        (Some(html), Some(number)) => {
+            let next = Some(page_file(number + 1)).filter(|next| dir.join(next).is_file());
+            Some(replayable(&String::from_utf8_lossy(&html), next.as_deref()).into_bytes())
+        },
+        (file, _) => file,
+    };
+    let (status, body) = match file {
+        Some(body) => ("200 OK", body),
+        None => ("404 Not Found", Vec::new()),
+    };
+    let content_type = match Path::new(&name).extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    };
+    let head = format!("HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+/// A recorded page without its scripts and, if there is a `next` page,
+/// with "Next" opening it.
+fn replayable(html: &str, next: Option<&str>) -> String {
+    let mut page = String::with_capacity(html.len());
+    // Tags are matched case-insensitively, in a lowercased copy made once;
+    // ASCII lowercasing keeps every byte offset the same in both.
+    let lower = html.to_ascii_lowercase();
+    let mut at = 0;
+    while let Some(start) = lower[at..].find("<script").map(|start| at + start) {
+        page.push_str(&html[at..start]);
+        at = match lower[start..].find("</script>") {
+            Some(end) => start + end + "</script>".len(),
+            None => html.len(),
+        };
+    }
+    page.push_str(&html[at..]);
+    if let Some(next) = next {
+        let script = format!(
+            r#"<script>document.addEventListener('click', function (event) {{ if (event.target.closest('[id$="_next"]')) {{ event.preventDefault(); location.href = '/{next}'; }} }}, true);</script>"#
+        );
+        match page.to_ascii_lowercase().rfind("</body>") {
+            Some(at) => page.insert_str(at, &script),
+            None => page.push_str(&script),
+        }
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    /// The status and body of a GET from the server.
+    async fn get(server: &ReplayServer, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        stream.write_all(format!("GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n").as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.split_whitespace().nth(1).unwrap().to_string(), body.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_server_serves_recorded_pages_only() {
+        let dir = tempfile::tempdir().unwrap();
+        record(dir.path(), 3, "<html><body>three</body></html>").unwrap();
+        record(dir.path(), 4, "<html><body>four<script src=\"https://example.com/dt.js\"></SCRIPT></body></html>").unwrap();
+        fs::write(dir.path().join("notes.txt"), "kept as is").unwrap();
+        let server = ReplayServer::start(dir.path().to_path_buf()).await.unwrap();
+
+        let (status, three) = get(&server, &format!("/{}", page_file(3))).await;
+        assert_eq!(status, "200");
+        assert!(three.starts_with("<html><body>three<script>") && three.contains("location.href = '/page-0004.html'"), "{three}");
+        assert_eq!(get(&server, &format!("/{}", page_file(4))).await.1, "<html><body>four</body></html>");
+        assert_eq!(get(&server, "/notes.txt").await.1, "kept as is");
+        assert_eq!(get(&server, &format!("/{}", page_file(5))).await.0, "404");
+        assert_eq!(get(&server, "/../secret.html").await.0, "404");
+    }
+
+    #[test]
+    fn test_replayable_strips_every_script() {
+        let html = "<html><head><Script>a()</Script></head><body>one<script>b()</script>two<SCRIPT src=\"c.js\"></SCRIPT></body></html>";
+        assert_eq!(replayable(html, None), "<html><head></head><body>onetwo</body></html>");
+        assert_eq!(replayable("<body>cut<script>never closed</body>", None), "<body>cut");
+    }
+
+    #[test]
+    fn test_first_page() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(first_page(dir.path()).unwrap(), None);
+        record(dir.path(), 12, "").unwrap();
+        record(dir.path(), 9, "").unwrap();
+        fs::write(dir.path().join("page-x.html"), "").unwrap();
+        assert_eq!(first_page(dir.path()).unwrap(), Some(9));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::web_driver::source::SourceError;
+use dotenv::dotenv;
+use std::env;
+use std::time::Duration;
+use thirtyfour::error::WebDriverErrorInner;
+
+/// How often, and how patiently, to retry a page after a transient error,
+/// from `CRAWL_RETRIES` (default 3), `CRAWL_RETRY_BASE_MS` (default 1000)
+/// and `CRAWL_RETRY_MAX_MS` (default 60000). The wait doubles after each
+/// attempt, up to the maximum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryConfig {
+    /// Retries after the first attempt; 0 never retries.
+    pub retries: u32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig { retries: 3, base: Duration::from_secs(1), max: Duration::from_secs(60) }
+    }
+}
+
+impl RetryConfig {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        Self::from_vars(|key| env::var(key).ok())
+    }
+
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let millis = |key: &str| var(key).and_then(|value| value.parse().ok()).map(Duration::from_millis);
+        let default = RetryConfig::default();
+        RetryConfig {
+            retries: var("CRAWL_RETRIES").and_then(|value| value.parse().ok()).unwrap_or(default.retries),
+            base: millis("CRAWL_RETRY_BASE_MS").unwrap_or(default.base),
+            max: millis("CRAWL_RETRY_MAX_MS").unwrap_or(default.max),
+        }
+    }
+
+    /// How long to wait before retry number `retry`, counting from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.base.saturating_mul(2u32.saturating_pow(retry)).min(self.max)
+    }
+}
+
+/// Whether `error` may go away if the page is tried again: the browser or
+/// the network hiccuped, or the site was slow. Errors in what we asked for,
+/// or in the page we got, would only happen again.
+pub fn is_transient(error: &SourceError) -> bool {
+    match error {
+        SourceError::Timeout { .. } => true,
+        SourceError::WebDriver(e) => matches!(
+            e.as_inner(),
+            WebDriverErrorInner::RequestFailed(_)
+                | WebDriverErrorInner::HttpError(_)
+                | WebDriverErrorInner::Timeout(_)
+                | WebDriverErrorInner::ElementClickIntercepted(_)
+                | WebDriverErrorInner::ElementNotInteractable(_)
+                | WebDriverErrorInner::NoSuchElement(_)
+                | WebDriverErrorInner::StaleElementReference(_)
+                | WebDriverErrorInner::WebDriverTimeout(_)
+                | WebDriverErrorInner::ScriptTimeout(_)
+                | WebDriverErrorInner::InvalidSessionId(_)
+                | WebDriverErrorInner::NoSuchWindow(_)
+                | WebDriverErrorInner::SessionNotCreated(_)
+                | WebDriverErrorInner::CommandRecvError(_)
+                | WebDriverErrorInner::CommandSendError(_)
+        ) || matches!(e.as_inner(), WebDriverErrorInner::UnknownResponse(status, _) if *status >= 500),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use thirtyfour::error::WebDriverError;
+
+    #[test]
+    fn test_delay_doubles_up_to_the_max() {
+        let retry = RetryConfig { retries: 10, base: Duration::from_millis(500), max: Duration::from_secs(3) };
+        let delays = (0..5).map(|retry_number| retry.delay(retry_number).as_millis()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+        assert_eq!(retry.delay(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        let webdriver = |inner| SourceError::WebDriver(WebDriverError::from_inner(inner));
+        assert!(is_transient(&webdriver(WebDriverErrorInner::HttpError("connection reset".to_string()))));
+        assert!(is_transient(&webdriver(WebDriverErrorInner::UnknownResponse(503, String::new()))));
+        assert!(is_transient(&SourceError::Timeout { what: "the table".to_string(), after: Duration::from_secs(1) }));
+        assert!(!is_transient(&webdriver(WebDriverErrorInner::UnknownResponse(404, String::new()))));
+        assert!(!is_transient(&webdriver(WebDriverErrorInner::ParseError("bad json".to_string()))));
+        assert!(!is_transient(&SourceError::UnexpectedPage { site: "fake", reason: "wrong title".to_string() }));
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        let retry = RetryConfig::from_vars(|key| (key == "CRAWL_RETRIES").then(|| "0".to_string()));
+        assert_eq!(retry, RetryConfig { retries: 0, ..RetryConfig::default() });
+    }
+}
@@ -0,0 +1,460 @@
+use crate::util::db::actor::{ActorError, DbHandle};
+use crate::util::db::checkpoint::{CheckpointError, Checkpoints};
+use crate::util::db::duck::{Domain, DuckStore};
+use crate::util::db::lake::{self, LakeError, LakeWriter};
+use crate::util::db::store::{StoreError, StoreKind};
+use crate::web_driver::crawl::Page;
+use async_trait::async_trait;
+use dotenv::dotenv;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("store: {0}")]
+    Store(#[from] StoreError),
+    #[error(transparent)]
+    Actor(#[from] ActorError),
+    #[error("lake: {0}")]
+    Lake(#[from] LakeError),
+    #[error("the lake writer stopped after a panic")]
+    LakeStopped,
+    #[error("unknown sink '{0}', expected db, lake, stdout or file:<path>")]
+    Unknown(String),
+}
+
+/// Where crawled domains are written.
+#[async_trait]
+pub trait Sink: Send {
+    /// Write one batch: a page, or the whole crawl when writing at the end.
+    async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError>;
+
+    /// Called once after the last batch.
+    async fn finish(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// The DuckDB store, written through its worker thread.
+pub struct DbSink {
+    db: DbHandle,
+}
+
+impl DbSink {
+    pub fn open(path: &str) -> Result<Self, SinkError> {
+        Ok(DbSink::new(DbHandle::spawn(DuckStore::open(path)?, 4)?))
+    }
+
+    /// Write through a worker shared with the rest of the crawl, e.g. the
+    /// one recording its checkpoints; DuckDB opens a file only once.
+    pub fn new(db: DbHandle) -> Self {
+        DbSink { db }
+    }
+}
+
+#[async_trait]
+impl Sink for DbSink {
+    async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError> {
+        self.db.insert_domains(domains.to_vec()).await?;
+        Ok(())
+    }
+}
+
+/// The crawl lake, one Parquet file per batch. Writing blocks on DuckDB,
+/// so each batch is written on the blocking pool, the writer going with it.
+pub struct LakeSink {
+    writer: Option<LakeWriter>,
+}
+
+impl LakeSink {
+    pub fn new(root: PathBuf, source: &str) -> Result<Self, SinkError> {
+        Ok(LakeSink { writer: Some(LakeWriter::new(root, source)?) })
+    }
+}
+
+#[async_trait]
+impl Sink for LakeSink {
+    async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError> {
+        let mut writer = self.writer.take().ok_or(SinkError::LakeStopped)?;
+        let domains = domains.to_vec();
+        let (writer, written) = tokio::task::spawn_blocking(move || {
+            let written = writer.write(&domains);
+            (writer, written)
+        })
+        .await
+        .map_err(|_| SinkError::LakeStopped)?;
+        self.writer = Some(writer);
+        written?;
+        Ok(())
+    }
+}
+
+/// Newline-delimited JSON, one domain per line, appended to a file.
+pub struct FileSink {
+    out: BufWriter<File>,
+}
+
+impl FileSink {
+    pub fn create(path: &Path) -> Result<Self, SinkError> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(FileSink { out: BufWriter::new(file) })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError> {
+        for domain in domains {
+            writeln!(self.out, "{}", domain.to_json())?;
+        }
+        // Flush each batch so a crash loses at most the page in hand.
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Domain names, one per line.
+pub struct StdoutSink;
+
+#[async_trait]
+impl Sink for StdoutSink {
+    async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError> {
+        let mut out = io::stdout().lock();
+        for domain in domains {
+            writeln!(out, "{}", domain.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where pages a failed sink missed are kept, from `CRAWL_FALLBACK_DIR` or
+/// `./data/fallback`.
+pub fn fallback_dir() -> PathBuf {
+    dotenv().ok();
+    env::var("CRAWL_FALLBACK_DIR").ok().filter(|dir| !dir.is_empty()).unwrap_or("./data/fallback".to_string()).into()
+}
+
+/// A sink named on the command line or in `CRAWL_SINKS`: `db`, `lake`,
+/// `stdout` or `file:<path>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkSpec {
+    Db,
+    Lake,
+    Stdout,
+    File(PathBuf),
+}
+
+impl FromStr for SinkSpec {
+    type Err = SinkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "db" | "sql" => Ok(SinkSpec::Db),
+            "lake" => Ok(SinkSpec::Lake),
+            "stdout" | "-" => Ok(SinkSpec::Stdout),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(SinkSpec::File(path.into())),
+                _ => Err(SinkError::Unknown(s.to_string())),
+            },
+        }
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkSpec::Db => f.write_str("db"),
+            SinkSpec::Lake => f.write_str("lake"),
+            SinkSpec::Stdout => f.write_str("stdout"),
+            SinkSpec::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+impl SinkSpec {
+    /// Open the sink; `source` names the lake partition and the database
+    /// sink writes through `db` when given one.
+    pub fn open(&self, source: &str, db: Option<&DbHandle>) -> Result<Box<dyn Sink>, SinkError> {
+        Ok(match self {
+            SinkSpec::Db => match db {
+                Some(db) => Box::new(DbSink::new(db.clone())),
+                None => Box::new(DbSink::open(&StoreKind::DuckDb.default_path())?),
+            },
+            SinkSpec::Lake => Box::new(LakeSink::new(lake::default_root(), source)?),
+            SinkSpec::Stdout => Box::new(StdoutSink),
+            SinkSpec::File(path) => Box::new(FileSink::create(path)?),
+        })
+    }
+
+    /// Sinks from `CRAWL_SINKS`, comma separated, or else the one `DB_TYPE`
+    /// names (`sql` or `lake`), or else stdout.
+    pub fn from_env() -> Result<Vec<Self>, SinkError> {
+        dotenv().ok();
+        if let Some(sinks) = env::var("CRAWL_SINKS").ok().filter(|sinks| !sinks.trim().is_empty()) {
+            return sinks.split(',').map(|sink| sink.trim().parse()).collect();
+        }
+        match env::var("DB_TYPE").as_deref() {
+            Ok(db_type @ ("sql" | "lake")) => Ok(vec![db_type.parse()?]),
+            _ => Ok(vec![SinkSpec::Stdout]),
+        }
+    }
+}
+
+/// How one sink fared over a crawl.
+#[derive(Debug)]
+pub struct SinkReport {
+    pub sink: String,
+    pub written: usize,
+    /// The first error; the sink was given nothing after it.
+    pub error: Option<SinkError>,
+}
+
+/// How the sinks fared over a crawl, and how far it was checkpointed.
+#[derive(Debug)]
+pub struct DrainReport {
+    pub sinks: Vec<SinkReport>,
+    /// The fallback file, if a sink failed and pages went there instead.
+    pub fallback: Option<SinkReport>,
+    /// The last page every sink stored and the run recorded.
+    pub checkpointed: Option<usize>,
+    /// Why checkpointing stopped, if it did; the sinks carried on.
+    pub checkpoint_error: Option<CheckpointError>,
+}
+
+/// Write the pages a crawl sends to every sink, page by page or, with
+/// `at_end`, all at once after the last page. A failing sink is dropped and
+/// reported while the others carry on, so one broken store does not cost
+/// the rest of the crawl. From the page a sink fails on, every page is also
+/// appended as NDJSON to the `fallback` file, created then, so nothing read
+/// is lost even when every sink has failed.
+///
+/// A page is checkpointed once every sink has stored it, and never after a
+/// sink has failed, so resuming the run repeats whatever a sink missed.
+pub async fn drain(
+    mut pages: mpsc::Receiver<Page>,
+    sinks: Vec<(String, Box<dyn Sink>)>,
+    at_end: bool,
+    checkpoints: Option<Checkpoints>,
+    fallback: Option<PathBuf>,
+) -> DrainReport {
+    let mut sinks = sinks
+        .into_iter()
+        .map(|(sink, out)| (SinkReport { sink, written: 0, error: None }, out))
+        .collect::<Vec<_>>();
+    let mut fallback = fallback.map(Fallback::new);
+    let mut drained = DrainReport { sinks: Vec::new(), fallback: None, checkpointed: None, checkpoint_error: None };
+    let mut held = Vec::new();
+    while let Some(page) = pages.recv().await {
+        match at_end {
+            true => held.push(page),
+            false => {
+                write_all(&mut sinks, fallback.as_mut(), &page.domains).await;
+                checkpoint(&mut drained, stored(&sinks), checkpoints.as_ref(), &[page]).await;
+            },
+        }
+    }
+    if at_end {
+        write_all(&mut sinks, fallback.as_mut(), &held.iter().flat_map(|page| page.domains.iter().cloned()).collect::<Vec<_>>()).await;
+        checkpoint(&mut drained, stored(&sinks), checkpoints.as_ref(), &held).await;
+    }
+    for (report, sink) in &mut sinks {
+        if report.error.is_none() {
+            report.error = sink.finish().await.err();
+        }
+    }
+    drained.sinks = sinks.into_iter().map(|(report, _)| report).collect();
+    drained.fallback = fallback.filter(|fallback| fallback.used).map(|fallback| fallback.report);
+    drained
+}
+
+/// The file pages go to once a sink has failed, created on first use.
+struct Fallback {
+    path: PathBuf,
+    sink: Option<FileSink>,
+    used: bool,
+    report: SinkReport,
+}
+
+impl Fallback {
+    fn new(path: PathBuf) -> Self {
+        let report = SinkReport { sink: SinkSpec::File(path.clone()).to_string(), written: 0, error: None };
+        Fallback { path, sink: None, used: false, report }
+    }
+
+    async fn write(&mut self, domains: &[Domain]) {
+        self.used = true;
+        if self.report.error.is_some() {
+            return;
+        }
+        let written = match &mut self.sink {
+            Some(sink) => sink.write(domains).await,
+            None => match FileSink::create(&self.path) {
+                Ok(sink) => self.sink.insert(sink).write(domains).await,
+                Err(e) => Err(e),
+            },
+        };
+        match written {
+            Ok(()) => self.report.written += domains.len(),
+            Err(e) => self.report.error = Some(e),
+        }
+    }
+}
+
+/// Checkpoint `pages` if every sink has `stored` them.
+async fn checkpoint(drained: &mut DrainReport, stored: bool, checkpoints: Option<&Checkpoints>, pages: &[Page]) {
+    let Some(checkpoints) = checkpoints.filter(|_| stored && drained.checkpoint_error.is_none()) else { return };
+    for page in pages {
+        match checkpoints.record(page.number, page.domains.len()).await {
+            Ok(()) => drained.checkpointed = Some(page.number),
+            Err(e) => {
+                drained.checkpoint_error = Some(e);
+                return;
+            },
+        }
+    }
+}
+
+fn stored(sinks: &[(SinkReport, Box<dyn Sink>)]) -> bool {
+    sinks.iter().all(|(report, _)| report.error.is_none())
+}
+
+async fn write_all(sinks: &mut [(SinkReport, Box<dyn Sink>)], fallback: Option<&mut Fallback>, domains: &[Domain]) {
+    for (report, sink) in sinks.iter_mut().filter(|(report, _)| report.error.is_none()) {
+        match sink.write(domains).await {
+            Ok(()) => report.written += domains.len(),
+            Err(e) => report.error = Some(e),
+        }
+    }
+    if let Some(fallback) = fallback.filter(|_| !stored(sinks)) {
+        fallback.write(domains).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::db::checkpoint;
+    use std::sync::{Arc, Mutex};
+
+    /// Records batch sizes, failing on the batch numbered `fail_on`.
+    struct Recorder {
+        batches: Arc<Mutex<Vec<usize>>>,
+        fail_on: Option<usize>,
+    }
+
+    #[async_trait]
+    impl Sink for Recorder {
+        async fn write(&mut self, domains: &[Domain]) -> Result<(), SinkError> {
+            let mut batches = self.batches.lock().unwrap();
+            if self.fail_on == Some(batches.len()) {
+                return Err(io::Error::other("disk full").into());
+            }
+            batches.push(domains.len());
+            Ok(())
+        }
+    }
+
+    fn pages(pages: &[&[&str]]) -> mpsc::Receiver<Page> {
+        let (tx, rx) = mpsc::channel(pages.len().max(1));
+        for (number, page) in pages.iter().enumerate() {
+            tx.try_send(Page { number, domains: page.iter().map(|name| Domain::new(name, true, None)).collect() }).unwrap();
+        }
+        rx
+    }
+
+    #[test]
+    fn test_parse_sinks() {
+        assert_eq!("db".parse::<SinkSpec>().unwrap(), SinkSpec::Db);
+        assert_eq!("file:out/crawl.ndjson".parse::<SinkSpec>().unwrap(), SinkSpec::File("out/crawl.ndjson".into()));
+        assert_eq!(SinkSpec::File("a.ndjson".into()).to_string(), "file:a.ndjson");
+        assert!(matches!("file:".parse::<SinkSpec>(), Err(SinkError::Unknown(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lake_sink_keeps_its_writer_across_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = LakeSink::new(dir.path().to_path_buf(), "expired-domains.co").unwrap();
+        sink.write(&[Domain::new("one.com", true, None)]).await.unwrap();
+        sink.write(&[Domain::new("two.com", true, None), Domain::new("three.com", true, None)]).await.unwrap();
+
+        let files = sink.writer.as_ref().unwrap().files();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.exists()));
+    }
+
+    #[tokio::test]
+    async fn test_failing_sink_does_not_stop_the_others() {
+        let (good, bad) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let sinks: Vec<(String, Box<dyn Sink>)> = vec![
+            ("good".to_string(), Box::new(Recorder { batches: good.clone(), fail_on: None })),
+            ("bad".to_string(), Box::new(Recorder { batches: bad.clone(), fail_on: Some(1) })),
+        ];
+        let reports = drain(pages(&[&["a.com", "b.com"], &["c.com"], &["d.com"]]), sinks, false, None, None).await.sinks;
+
+        assert_eq!(*good.lock().unwrap(), vec![2, 1, 1]);
+        assert_eq!(*bad.lock().unwrap(), vec![2]);
+        assert_eq!((reports[0].written, reports[0].error.is_none()), (4, true));
+        assert_eq!((reports[1].written, reports[1].error.is_some()), (2, true));
+    }
+
+    #[tokio::test]
+    async fn test_pages_a_sink_missed_go_to_the_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fallback").join("run.ndjson");
+        let sinks: Vec<(String, Box<dyn Sink>)> = vec![("bad".to_string(), Box::new(Recorder { batches: Arc::default(), fail_on: Some(1) }))];
+        let report = drain(pages(&[&["a.com"], &["b.com", "c.com"], &["d.com"]]), sinks, false, None, Some(path.clone())).await;
+
+        let fallback = report.fallback.unwrap();
+        assert_eq!((fallback.written, fallback.error.is_none()), (3, true));
+        let names = fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["b.com", "c.com", "d.com"]);
+
+        let sinks: Vec<(String, Box<dyn Sink>)> = vec![("good".to_string(), Box::new(Recorder { batches: Arc::default(), fail_on: None }))];
+        let unused = dir.path().join("unused.ndjson");
+        assert!(drain(pages(&[&["a.com"]]), sinks, false, None, Some(unused.clone())).await.fallback.is_none());
+        assert!(!unused.exists());
+    }
+
+    #[tokio::test]
+    async fn test_at_end_writes_one_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crawl.ndjson");
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sinks: Vec<(String, Box<dyn Sink>)> = vec![
+            ("file".to_string(), Box::new(FileSink::create(&path).unwrap())),
+            ("recorder".to_string(), Box::new(Recorder { batches: batches.clone(), fail_on: None })),
+        ];
+        let reports = drain(pages(&[&["a.com"], &["b.com"]]), sinks, true, None, None).await.sinks;
+        assert!(reports.iter().all(|report| report.written == 2 && report.error.is_none()));
+        assert_eq!(*batches.lock().unwrap(), vec![2]);
+
+        let lines = fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+        assert_eq!(lines.iter().map(|line| line["name"].as_str().unwrap()).collect::<Vec<_>>(), vec!["a.com", "b.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_stop_at_the_first_failed_sink() {
+        let db = DbHandle::spawn(DuckStore::open_in_memory().unwrap(), 4).unwrap();
+        let run = db.call(|store| checkpoint::start_run(store.connection(), "fake")).await.unwrap().unwrap();
+        let sinks: Vec<(String, Box<dyn Sink>)> = vec![
+            ("good".to_string(), Box::new(Recorder { batches: Arc::default(), fail_on: None })),
+            ("bad".to_string(), Box::new(Recorder { batches: Arc::default(), fail_on: Some(2) })),
+        ];
+        let checkpoints = Checkpoints::new(db.clone(), &run.id);
+        let report = drain(pages(&[&["a.com"], &["b.com", "c.com"], &["d.com"], &["e.com"]]), sinks, false, Some(checkpoints), None).await;
+        assert_eq!(report.checkpointed, Some(1));
+
+        let resumed = db.call(move |store| checkpoint::find_run(store.connection(), &run.id)).await.unwrap().unwrap().unwrap();
+        assert_eq!((resumed.next_page, resumed.domains), (2, 3));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::util::db::duck::Domain;
+use crate::web_driver::expired_domains::{ExpiredDomains, ExpiredDomainsConfig};
+use crate::web_driver::layout::{LayoutContract, LayoutDrift};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use std::env;
+use std::time::Duration;
+use thirtyfour::error::WebDriverError;
+use thirtyfour::WebDriver;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("webdriver: {0}")]
+    WebDriver(#[from] WebDriverError),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unknown source '{name}', expected one of: {known}")]
+    Unknown { name: String, known: String },
+    #[error("{site} is misconfigured: {reason}")]
+    Config { site: &'static str, reason: String },
+    #[error("timed out after {after:?} waiting for {what}")]
+    Timeout { what: String, after: Duration },
+    #[error("{site} is not showing the expected page: {reason}")]
+    UnexpectedPage { site: &'static str, reason: String },
+    #[error(transparent)]
+    Drift(#[from] LayoutDrift),
+    #[error("{site} is stuck after page {page}: the next page never arrived")]
+    Stuck { site: &'static str, page: usize },
+    #[error("gave up after {attempts} attempts: {last}")]
+    GaveUp { attempts: u32, last: Box<SourceError> },
+}
+
+/// What a source is, for logs and the lake's `source` partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMetadata {
+    /// The name it is chosen by, e.g. `expired-domains.co`.
+    pub name: &'static str,
+    pub url: String,
+    pub description: &'static str,
+}
+
+/// A site domains are crawled from. [`crawl`](crate::web_driver::crawl::crawl)
+/// calls [`setup`](Source::setup) once, then alternates
+/// [`parse`](Source::parse) and [`paginate`](Source::paginate) until there
+/// are no more pages. [`replay`](crate::web_driver::crawl::replay) runs the
+/// same calls against a recorded crawl, opened with
+/// [`open_at`](Source::open_at).
+#[async_trait]
+pub trait Source: Send {
+    fn metadata(&self) -> SourceMetadata;
+
+    /// What its pages must look like, checked before each is read; the
+    /// default expects nothing.
+    fn layout(&self) -> LayoutContract {
+        LayoutContract::default()
+    }
+
+    /// Have [`setup`](Source::setup) open `url` instead of the site, e.g. a
+    /// recorded page on a [`ReplayServer`](crate::web_driver::replay::ReplayServer).
+    fn open_at(&mut self, url: String);
+
+    /// Open the site and get the first page of results on screen.
+    async fn setup(&mut self, browser: &WebDriver) -> Result<(), SourceError>;
+
+    /// The domains on a page, from its HTML.
+    fn parse(&self, html: &str) -> Result<Vec<Domain>, SourceError>;
+
+    /// Move to the next page; `false` once there is none.
+    async fn paginate(&mut self, browser: &WebDriver) -> Result<bool, SourceError>;
+}
+
+type NewSource = fn() -> Result<Box<dyn Source>, SourceError>;
+
+/// Every source, by name, each configured from the environment.
+const SOURCES: &[(&str, NewSource)] = &[("expired-domains.co", || Ok(Box::new(ExpiredDomains::new(ExpiredDomainsConfig::from_env()?))))];
+
+/// Names [`source_by_name`] accepts.
+pub fn source_names() -> Vec<&'static str> {
+    SOURCES.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn source_by_name(name: &str) -> Result<Box<dyn Source>, SourceError> {
+    SOURCES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .ok_or_else(|| SourceError::Unknown { name: name.to_string(), known: source_names().join(", ") })
+        .and_then(|(_, source)| source())
+}
+
+/// The source to crawl from `CRAWL_SOURCE`, or `expired-domains.co`.
+pub fn default_source_name() -> String {
+    dotenv().ok();
+    env::var("CRAWL_SOURCE").ok().filter(|name| !name.is_empty()).unwrap_or("expired-domains.co".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_by_name() {
+        for name in source_names() {
+            assert_eq!(source_by_name(name).unwrap().metadata().name, name);
+        }
+        assert!(matches!(source_by_name("nope"), Err(SourceError::Unknown { known, .. }) if known == "expired-domains.co"));
+    }
+}
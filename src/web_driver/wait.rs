@@ -0,0 +1,111 @@
+use crate::web_driver::source::SourceError;
+use dotenv::dotenv;
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+use thirtyfour::prelude::*;
+use tokio::time::{self, Instant};
+
+/// How long to wait for the page, and how often to look, from
+/// `CRAWL_WAIT_TIMEOUT_MS` (default 30000) and `CRAWL_WAIT_POLL_MS`
+/// (default 250).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaitConfig {
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        WaitConfig { timeout: Duration::from_secs(30), interval: Duration::from_millis(250) }
+    }
+}
+
+impl WaitConfig {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        Self::from_vars(|key| env::var(key).ok())
+    }
+
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let millis = |key: &str| var(key).and_then(|value| value.parse().ok()).map(Duration::from_millis);
+        let default = WaitConfig::default();
+        WaitConfig {
+            timeout: millis("CRAWL_WAIT_TIMEOUT_MS").unwrap_or(default.timeout),
+            interval: millis("CRAWL_WAIT_POLL_MS").unwrap_or(default.interval),
+        }
+    }
+}
+
+/// Call `check` every interval until it returns something, giving up with
+/// [`SourceError::Timeout`] once the timeout has passed. `what` describes
+/// the condition for that error.
+pub async fn poll_until<T, F, Fut>(wait: WaitConfig, what: &str, mut check: F) -> Result<T, SourceError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<T>, SourceError>>,
+{
+    let deadline = Instant::now() + wait.timeout;
+    loop {
+        if let Some(found) = check().await? {
+            return Ok(found);
+        }
+        if Instant::now() >= deadline {
+            return Err(SourceError::Timeout { what: what.to_string(), after: wait.timeout });
+        }
+        time::sleep(wait.interval).await;
+    }
+}
+
+/// The element `by` finds, once it is on the page, or `None` if it has not
+/// appeared within the timeout.
+pub async fn element(browser: &WebDriver, by: By, wait: WaitConfig) -> Result<Option<WebElement>, SourceError> {
+    Ok(browser.query(by).wait(wait.timeout, wait.interval).first_opt().await?)
+}
+
+/// The element's HTML once it differs from `before`, e.g. a table redrawn
+/// after a click. The element is looked up afresh each time, since a
+/// redraw may replace it.
+pub async fn changed(browser: &WebDriver, by: By, before: &str, wait: WaitConfig) -> Result<String, SourceError> {
+    poll_until(wait, &format!("{by:?} to change"), || async {
+        let now = match browser.find_all(by.clone()).await?.into_iter().next() {
+            Some(element) => element.outer_html().await?,
+            None => return Ok(None),
+        };
+        Ok((now != before).then_some(now))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn test_poll_until_returns_once_ready() {
+        let wait = WaitConfig { timeout: Duration::from_secs(1), interval: Duration::from_millis(1) };
+        let calls = Cell::new(0);
+        let found = poll_until(wait, "the third call", || {
+            calls.set(calls.get() + 1);
+            let ready = calls.get() == 3;
+            async move { Ok(ready.then_some("ready")) }
+        })
+        .await
+        .unwrap();
+        assert_eq!((found, calls.get()), ("ready", 3));
+    }
+
+    #[tokio::test]
+    async fn test_poll_until_times_out() {
+        let wait = WaitConfig { timeout: Duration::from_millis(20), interval: Duration::from_millis(5) };
+        let result = poll_until(wait, "never", || async { Ok(None::<()>) }).await;
+        assert!(matches!(result, Err(SourceError::Timeout { what, .. }) if what == "never"));
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        let wait = WaitConfig::from_vars(|key| (key == "CRAWL_WAIT_TIMEOUT_MS").then(|| "1500".to_string()));
+        assert_eq!(wait, WaitConfig { timeout: Duration::from_millis(1500), ..WaitConfig::default() });
+    }
+}